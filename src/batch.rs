@@ -0,0 +1,688 @@
+use anyhow::{Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use std::collections::hash_map::{Entry, HashMap};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{output, payload_bytes, run_log, write_output, Cli, RenderOptions};
+
+/// A single code to generate from a batch CSV row
+#[derive(Debug, PartialEq)]
+pub struct BatchRow {
+    pub data: String,
+    pub filename: String,
+}
+
+/// Turn a name taken from the data into a single path segment: characters
+/// that would let it escape the output directory or that aren't allowed in
+/// filenames on common filesystems become `_`, and leading dots are dropped
+/// so it can't name a parent directory or a hidden file
+fn sanitize_filename(value: &str) -> String {
+    let name: String = value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    name.trim_start_matches('.').to_string()
+}
+
+/// Fill `{column}` placeholders in an output filename pattern from the row
+fn expand_output_template(
+    pattern: &str,
+    headers: &csv::StringRecord,
+    record: &csv::StringRecord,
+) -> Result<String> {
+    let mut filename = String::new();
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 1..start + len];
+        let col = headers
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown column '{}' in --output-template", name))?;
+
+        filename.push_str(&rest[..start]);
+        filename.push_str(&sanitize_filename(record.get(col).unwrap_or_default()));
+        rest = &rest[start + len + 1..];
+    }
+    filename.push_str(rest);
+
+    Ok(filename)
+}
+
+/// Read batch rows from CSV. The header row must name a `data` column. Output
+/// names come from `output_template` if given, else an optional `filename`
+/// column, both sanitized to a single path segment; rows with neither get
+/// `qr_0001.png` etc.
+pub fn read_batch_rows(path: &Path, output_template: Option<&str>) -> Result<Vec<BatchRow>> {
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("Failed to open batch file {:?}", path))?;
+
+    let headers = reader.headers()?.clone();
+    let data_col = headers
+        .iter()
+        .position(|h| h.trim().eq_ignore_ascii_case("data"))
+        .ok_or_else(|| anyhow::anyhow!("Batch file must have a 'data' column"))?;
+    let filename_col = headers
+        .iter()
+        .position(|h| h.trim().eq_ignore_ascii_case("filename"));
+
+    let mut rows = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Invalid CSV on row {}", i + 1))?;
+        let data = record.get(data_col).unwrap_or_default().to_string();
+        let filename = match output_template {
+            Some(pattern) => expand_output_template(pattern, &headers, &record)?,
+            None => filename_col
+                .and_then(|col| record.get(col))
+                .map(sanitize_filename)
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| format!("qr_{:04}.png", i + 1)),
+        };
+        rows.push(BatchRow { data, filename });
+    }
+
+    Ok(rows)
+}
+
+/// Read one payload per line, skipping blank lines and `#` comments. Output
+/// names come from `output_template` (fields `{n}` and `{data}`) if given,
+/// else `line_0001.png` etc. numbered by code.
+pub fn read_line_rows(path: &Path, output_template: Option<&str>) -> Result<Vec<BatchRow>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read lines file {:?}", path))?;
+    let headers = csv::StringRecord::from(vec!["n", "data"]);
+
+    contents
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .enumerate()
+        .map(|(i, line)| {
+            let n = format!("{:04}", i + 1);
+            let filename = match output_template {
+                Some(pattern) => expand_output_template(
+                    pattern,
+                    &headers,
+                    &csv::StringRecord::from(vec![n.as_str(), line]),
+                )?,
+                None => format!("line_{}.png", n),
+            };
+            Ok(BatchRow {
+                data: line.to_string(),
+                filename,
+            })
+        })
+        .collect()
+}
+
+fn progress_bar(len: u64) -> ProgressBar {
+    // Only draw when a person is watching; logs and pipes get the summary alone
+    if !std::io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    let bar = ProgressBar::new(len);
+    bar.set_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} [{elapsed_precise}, ETA {eta}] {msg}")
+            .expect("valid progress template"),
+    );
+    bar
+}
+
+/// The progress bar's running tally
+fn progress_message(succeeded: usize, failed: usize) -> String {
+    format!("{} ok, {} failed", succeeded, failed)
+}
+
+/// Generate one code per CSV row, writing files into the `--output` directory
+pub fn run_batch(cli: &Cli, opts: &RenderOptions, path: &Path) -> Result<()> {
+    let rows = read_batch_rows(path, cli.output_template.as_deref())?;
+    if cli.validate_only {
+        return validate_rows(cli, opts, &rows);
+    }
+    let out_dir = output_dir(cli)?;
+
+    generate_rows(cli, opts, &rows, &out_dir)
+}
+
+/// The `--output` directory for multi-code modes, created if missing
+fn output_dir(cli: &Cli) -> Result<PathBuf> {
+    let out_dir = cli.output.clone().unwrap_or_else(|| PathBuf::from("."));
+    std::fs::create_dir_all(&out_dir)
+        .with_context(|| format!("Failed to create output directory {:?}", out_dir))?;
+    Ok(out_dir)
+}
+
+/// Generate one code per line of a text file into the `--output` directory
+pub fn run_lines(cli: &Cli, opts: &RenderOptions, path: &Path) -> Result<()> {
+    let rows = read_line_rows(path, cli.output_template.as_deref())?;
+    if cli.validate_only {
+        return validate_rows(cli, opts, &rows);
+    }
+    let out_dir = output_dir(cli)?;
+
+    generate_rows(cli, opts, &rows, &out_dir)
+}
+
+/// Rows for `count` sequential codes, with `{n}` in the data and filename
+/// patterns replaced by the counter, zero-padded to `pad` digits
+pub fn counter_rows(
+    data: &str,
+    filename: &str,
+    start: u64,
+    count: u64,
+    pad: usize,
+) -> Result<Vec<BatchRow>> {
+    if !filename.contains("{n}") {
+        return Err(anyhow::anyhow!(
+            "--count needs {{n}} in --output so each code gets its own file, e.g. -o \"qr_{{n}}.png\""
+        ));
+    }
+
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let last = start.checked_add(count - 1).ok_or_else(|| {
+        anyhow::anyhow!(
+            "--count {} from --start {} runs past the largest counter value, {}",
+            count,
+            start,
+            u64::MAX
+        )
+    })?;
+
+    (start..=last)
+        .map(|n| {
+            let n = format!("{:0width$}", n, width = pad);
+            Ok(BatchRow {
+                data: data.replace("{n}", &n),
+                filename: filename.replace("{n}", &n),
+            })
+        })
+        .collect()
+}
+
+/// Generate `count` codes from the data and `--output` patterns
+pub fn run_count(cli: &Cli, opts: &RenderOptions, count: u64) -> Result<()> {
+    let data = cli.data.clone().unwrap_or_default();
+    if !data.contains("{n}") {
+        eprintln!("Warning: data has no {{n}} placeholder, so every code will be identical");
+    }
+
+    let filename = cli
+        .output
+        .as_deref()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "qr_{n}.png".to_string());
+    let rows = counter_rows(&data, &filename, cli.start, count, cli.pad)?;
+    if cli.validate_only {
+        return validate_rows(cli, opts, &rows);
+    }
+
+    generate_rows(cli, opts, &rows, Path::new(""))
+}
+
+/// What happened to one row of a multi-code run
+enum RowOutcome {
+    /// Written, with its `--json-output` summary and the payload encoded
+    Saved(serde_json::Value, Vec<u8>),
+    /// --no-clobber found the file already there
+    Skipped,
+    Failed(anyhow::Error),
+}
+
+/// Rows whose finished payload repeats an earlier row's, as (row, first row)
+/// pairs numbered from 1. Rows whose payload failed are left out.
+fn duplicate_rows(payloads: &[Result<Vec<u8>>]) -> Vec<(usize, usize)> {
+    let mut first_seen = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (i, payload) in payloads.iter().enumerate() {
+        let Ok(data) = payload else {
+            continue;
+        };
+        match first_seen.entry(run_log::data_hash(data)) {
+            Entry::Occupied(first) => duplicates.push((i + 1, *first.get())),
+            Entry::Vacant(slot) => {
+                slot.insert(i + 1);
+            }
+        }
+    }
+    duplicates
+}
+
+/// With --verify-unique, list the rows that repeat an earlier payload and
+/// fail, or just warn with --verify-unique=warn
+fn check_unique(cli: &Cli, payloads: &[Result<Vec<u8>>]) -> Result<()> {
+    let Some(mode) = &cli.verify_unique else {
+        return Ok(());
+    };
+    let duplicates = duplicate_rows(payloads);
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    for (row, first) in &duplicates {
+        eprintln!("Row {}: same payload as row {}", row, first);
+    }
+    if mode == "warn" {
+        eprintln!(
+            "Warning: {} of {} rows repeat an earlier payload",
+            duplicates.len(),
+            payloads.len()
+        );
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "{} of {} rows repeat an earlier payload; no codes were written",
+        duplicates.len(),
+        payloads.len()
+    ))
+}
+
+/// Where a row's code is saved: its filename in `out_dir`, plus the
+/// `--hash-suffix` of its payload
+fn row_output(cli: &Cli, row: &BatchRow, data: &[u8], out_dir: &Path) -> PathBuf {
+    output::output_path(cli, &out_dir.join(&row.filename), data)
+}
+
+/// Fail before anything is written when two rows would be saved to the same
+/// file, since their workers would write it at the same time
+fn check_distinct_outputs(
+    cli: &Cli,
+    rows: &[BatchRow],
+    payloads: &[Result<Vec<u8>>],
+    out_dir: &Path,
+) -> Result<()> {
+    let mut first_seen = HashMap::new();
+    for (i, (row, payload)) in rows.iter().zip(payloads).enumerate() {
+        let Ok(data) = payload else {
+            continue;
+        };
+        match first_seen.entry(row_output(cli, row, data, out_dir)) {
+            Entry::Occupied(first) => {
+                return Err(anyhow::anyhow!(
+                    "Rows {} and {} would both be saved to {}; no codes were written",
+                    first.get(),
+                    i + 1,
+                    first.key().display()
+                ));
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(i + 1);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generate every row into `out_dir` on `--jobs` threads, carrying on past
+/// failures. Rows finish in any order, so their messages are printed in row
+/// order once all are done, followed by a summary.
+fn generate_rows(cli: &Cli, opts: &RenderOptions, rows: &[BatchRow], out_dir: &Path) -> Result<()> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(cli.jobs.unwrap_or(0) as usize)
+        .build()
+        .context("Failed to start worker threads")?;
+
+    // Payloads come first so --verify-unique can stop before any file is written
+    let payloads: Vec<Result<Vec<u8>>> = pool.install(|| {
+        rows.par_iter()
+            .map(|row| payload_bytes(cli, opts, &row.data))
+            .collect()
+    });
+    check_unique(cli, &payloads)?;
+    check_distinct_outputs(cli, rows, &payloads, out_dir)?;
+
+    let bar = progress_bar(rows.len() as u64);
+    let (ok, failing) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let outcomes: Vec<(PathBuf, RowOutcome)> = pool.install(|| {
+        rows.par_iter()
+            .zip(payloads)
+            .map(|(row, data)| {
+                let outcome = generate_row(cli, opts, row, data, out_dir);
+                match &outcome.1 {
+                    RowOutcome::Saved(..) => {
+                        ok.fetch_add(1, Ordering::Relaxed);
+                    }
+                    RowOutcome::Failed(_) => {
+                        failing.fetch_add(1, Ordering::Relaxed);
+                    }
+                    RowOutcome::Skipped => {}
+                }
+                bar.set_message(progress_message(
+                    ok.load(Ordering::Relaxed),
+                    failing.load(Ordering::Relaxed),
+                ));
+                bar.inc(1);
+                outcome
+            })
+            .collect()
+    });
+    bar.finish_and_clear();
+
+    let mut succeeded = 0;
+    let mut failed = 0;
+    let mut skipped = 0;
+    let mut summaries = Vec::new();
+    let mut manifest = Vec::new();
+    for (i, (output, outcome)) in outcomes.into_iter().enumerate() {
+        match outcome {
+            RowOutcome::Saved(summary, data) => {
+                succeeded += 1;
+                if cli.manifest.is_some() {
+                    manifest.push(output::manifest_entry(&summary, &data));
+                }
+                if cli.json_output {
+                    summaries.push(summary);
+                } else {
+                    println!("QR code saved to: {}", output.display());
+                }
+            }
+            RowOutcome::Skipped => {
+                skipped += 1;
+                eprintln!(
+                    "Row {}: skipped, {} already exists",
+                    i + 1,
+                    output.display()
+                );
+            }
+            RowOutcome::Failed(e) => {
+                failed += 1;
+                eprintln!("Row {}: {:#}", i + 1, e);
+            }
+        }
+    }
+    // Print the array even when some rows failed, so tools see what was written
+    if cli.json_output {
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+    }
+    if let Some(manifest_path) = &cli.manifest {
+        output::write_manifest(manifest_path, &manifest, cli.write_retries)?;
+    }
+
+    eprintln!(
+        "Batch complete: {} succeeded, {} failed, {} skipped",
+        succeeded, failed, skipped
+    );
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} of {} rows failed", failed, rows.len()));
+    }
+
+    Ok(())
+}
+
+/// Run each row through the template and payload steps without writing
+/// anything, reporting every row and failing if any row would
+fn validate_rows(cli: &Cli, opts: &RenderOptions, rows: &[BatchRow]) -> Result<()> {
+    let payloads: Vec<Result<Vec<u8>>> = rows
+        .iter()
+        .map(|row| payload_bytes(cli, opts, &row.data))
+        .collect();
+
+    let mut failed = 0;
+    for (i, payload) in payloads.iter().enumerate() {
+        match payload {
+            Ok(_) => println!("Row {}: ok", i + 1),
+            Err(e) => {
+                failed += 1;
+                eprintln!("Row {}: {:#}", i + 1, e);
+            }
+        }
+    }
+
+    eprintln!(
+        "Validation complete: {} passed, {} failed",
+        rows.len() - failed,
+        failed
+    );
+    check_unique(cli, &payloads)?;
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} of {} rows failed", failed, rows.len()));
+    }
+
+    Ok(())
+}
+
+/// Generate one row, whose finished payload is `data`, into `out_dir`,
+/// returning where it went (or would have)
+fn generate_row(
+    cli: &Cli,
+    opts: &RenderOptions,
+    row: &BatchRow,
+    data: Result<Vec<u8>>,
+    out_dir: &Path,
+) -> (PathBuf, RowOutcome) {
+    let data = match data {
+        Ok(data) => data,
+        Err(e) => return (out_dir.join(&row.filename), RowOutcome::Failed(e)),
+    };
+
+    let output = row_output(cli, row, &data, out_dir);
+    let outcome = if cli.no_clobber && output.exists() {
+        RowOutcome::Skipped
+    } else {
+        match write_output(cli, opts, &data, &output) {
+            // --emit conflicts with the batch modes, so each row is one file
+            Ok(mut summaries) => RowOutcome::Saved(summaries.remove(0), data),
+            Err(e) => RowOutcome::Failed(e),
+        }
+    };
+    (output, outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_csv(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_read_batch_rows_with_filenames() {
+        let file = write_csv("data,filename\nhello,a.png\nworld,b.png\n");
+        let rows = read_batch_rows(file.path(), None).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                BatchRow {
+                    data: "hello".to_string(),
+                    filename: "a.png".to_string()
+                },
+                BatchRow {
+                    data: "world".to_string(),
+                    filename: "b.png".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_batch_rows_default_filenames() {
+        let file = write_csv("data\nhello\nworld\n");
+        let rows = read_batch_rows(file.path(), None).unwrap();
+        assert_eq!(rows[0].filename, "qr_0001.png");
+        assert_eq!(rows[1].filename, "qr_0002.png");
+    }
+
+    #[test]
+    fn test_read_batch_rows_quoted_commas() {
+        let file = write_csv("data,filename\n\"MyNet,pass,WPA\",wifi.png\n");
+        let rows = read_batch_rows(file.path(), None).unwrap();
+        assert_eq!(rows[0].data, "MyNet,pass,WPA");
+    }
+
+    #[test]
+    fn test_read_batch_rows_output_template() {
+        let file = write_csv("sku,data\nA100,hello\nB200,world\n");
+        let rows = read_batch_rows(file.path(), Some("code_{sku}.png")).unwrap();
+        assert_eq!(rows[0].filename, "code_A100.png");
+        assert_eq!(rows[1].filename, "code_B200.png");
+    }
+
+    #[test]
+    fn test_read_batch_rows_output_template_overrides_filename() {
+        let file = write_csv("data,filename,id\nhello,a.png,7\n");
+        let rows = read_batch_rows(file.path(), Some("qr_{id}_{data}.png")).unwrap();
+        assert_eq!(rows[0].filename, "qr_7_hello.png");
+    }
+
+    #[test]
+    fn test_read_batch_rows_output_template_sanitizes() {
+        let file = write_csv("data,name\nhello,../../etc/passwd\n");
+        let rows = read_batch_rows(file.path(), Some("{name}.png")).unwrap();
+        assert_eq!(rows[0].filename, "_.._etc_passwd.png");
+        assert!(!rows[0].filename.contains('/'));
+    }
+
+    #[test]
+    fn test_read_batch_rows_sanitizes_filename_column() {
+        let file = write_csv(
+            "data,filename
+hello,../../etc/passwd
+world,..\n",
+        );
+        let rows = read_batch_rows(file.path(), None).unwrap();
+        assert_eq!(rows[0].filename, "_.._etc_passwd");
+        // Nothing left after sanitizing, so the default name is used
+        assert_eq!(rows[1].filename, "qr_0002.png");
+    }
+
+    #[test]
+    fn test_read_batch_rows_output_template_unknown_column() {
+        let file = write_csv("data\nhello\n");
+        assert!(read_batch_rows(file.path(), Some("{sku}.png")).is_err());
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename(" a/b\\c:d "), "a_b_c_d");
+        assert_eq!(sanitize_filename("tab\there"), "tab_here");
+        assert_eq!(sanitize_filename("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(sanitize_filename("a/b:c"), "a_b_c");
+        assert_eq!(sanitize_filename(".hidden.png"), "hidden.png");
+    }
+
+    #[test]
+    fn test_read_line_rows_skips_blanks_and_comments() {
+        let file = write_csv("https://a.example\n# comment\n\n   \nhttps://b.example\r\n");
+        let rows = read_line_rows(file.path(), None).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                BatchRow {
+                    data: "https://a.example".to_string(),
+                    filename: "line_0001.png".to_string()
+                },
+                BatchRow {
+                    data: "https://b.example".to_string(),
+                    filename: "line_0002.png".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_line_rows_output_template() {
+        let file = write_csv("alpha\nbeta/gamma\n");
+        let rows = read_line_rows(file.path(), Some("{n}-{data}.png")).unwrap();
+        assert_eq!(rows[0].filename, "0001-alpha.png");
+        assert_eq!(rows[1].filename, "0002-beta_gamma.png");
+    }
+
+    #[test]
+    fn test_counter_rows() {
+        let rows = counter_rows("TICKET-{n}", "t_{n}.png", 1000, 3, 0).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].data, "TICKET-1000");
+        assert_eq!(rows[2].data, "TICKET-1002");
+        assert_eq!(rows[2].filename, "t_1002.png");
+    }
+
+    #[test]
+    fn test_counter_rows_padding() {
+        let rows = counter_rows("{n}", "out/{n}.png", 7, 1, 4).unwrap();
+        assert_eq!(rows[0].data, "0007");
+        assert_eq!(rows[0].filename, "out/0007.png");
+    }
+
+    #[test]
+    fn test_counter_rows_overflow() {
+        assert!(counter_rows("T{n}", "q_{n}.png", u64::MAX, 2, 0).is_err());
+        let rows = counter_rows("T{n}", "q_{n}.png", u64::MAX - 1, 2, 0).unwrap();
+        assert_eq!(rows[1].data, format!("T{}", u64::MAX));
+    }
+
+    #[test]
+    fn test_counter_rows_requires_placeholder_in_filename() {
+        assert!(counter_rows("TICKET-{n}", "ticket.png", 1, 5, 0).is_err());
+    }
+
+    #[test]
+    fn test_read_batch_rows_missing_data_column() {
+        let file = write_csv("url,filename\nhello,a.png\n");
+        assert!(read_batch_rows(file.path(), None).is_err());
+    }
+
+    #[test]
+    fn test_progress_message() {
+        assert_eq!(progress_message(0, 0), "0 ok, 0 failed");
+        assert_eq!(progress_message(12, 3), "12 ok, 3 failed");
+    }
+
+    #[test]
+    fn test_duplicate_rows() {
+        let payloads = vec![
+            Ok(b"TICKET-1".to_vec()),
+            Ok(b"TICKET-2".to_vec()),
+            Err(anyhow::anyhow!("bad row")),
+            Ok(b"TICKET-1".to_vec()),
+            Ok(b"TICKET-2".to_vec()),
+            Ok(b"TICKET-1".to_vec()),
+        ];
+        assert_eq!(duplicate_rows(&payloads), vec![(4, 1), (5, 2), (6, 1)]);
+        assert!(duplicate_rows(&payloads[..3]).is_empty());
+    }
+
+    #[test]
+    fn test_check_distinct_outputs() {
+        use clap::Parser;
+        let row = |data: &str, filename: &str| BatchRow {
+            data: data.to_string(),
+            filename: filename.to_string(),
+        };
+        let rows = vec![
+            row("a", "one.png"),
+            row("b", "two.png"),
+            row("c", "one.png"),
+        ];
+        let payloads: Vec<Result<Vec<u8>>> = rows
+            .iter()
+            .map(|row| Ok(row.data.clone().into_bytes()))
+            .collect();
+        let out_dir = Path::new("out");
+
+        let cli = Cli::parse_from(["qrgen", "--batch", "rows.csv"]);
+        let err = check_distinct_outputs(&cli, &rows, &payloads, out_dir).unwrap_err();
+        assert!(err.to_string().starts_with("Rows 1 and 3"));
+        assert!(check_distinct_outputs(&cli, &rows[..2], &payloads[..2], out_dir).is_ok());
+
+        // Different payloads get different hash suffixes
+        let cli = Cli::parse_from(["qrgen", "--batch", "rows.csv", "--hash-suffix"]);
+        assert!(check_distinct_outputs(&cli, &rows, &payloads, out_dir).is_ok());
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use clap::Args;
+use image::Rgba;
+use qrcode::{EcLevel, Version};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::segments::EncodeOptions;
+use crate::{check_canvas_size, draw_qr_code, encode_at_version, parse_error_correction};
+
+/// Versions timed by the benchmark, from the smallest code to the largest
+const VERSIONS: [i16; 6] = [1, 5, 10, 20, 30, 40];
+
+const BORDER: u32 = 4;
+
+#[derive(Args, Debug, Clone)]
+pub struct BenchmarkArgs {
+    /// Size of each box in pixels
+    #[arg(short, long, default_value = "10")]
+    size: u32,
+
+    /// Error correction level: L(7%), M(15%), Q(25%), H(30%)
+    #[arg(short, long, default_value = "M")]
+    error_correction: String,
+
+    /// Codes to generate for each version
+    #[arg(short = 'n', long, default_value = "20", value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+}
+
+/// The longest payload that still fits in `version`, so each version is timed
+/// with a full code
+fn fill_version(version: i16, level: EcLevel) -> Vec<u8> {
+    let fits = |len: usize| {
+        encode_at_version(
+            &payload(len),
+            Version::Normal(version),
+            level,
+            EncodeOptions::default(),
+        )
+        .is_ok()
+    };
+
+    let (mut lo, mut hi) = (0usize, 3000);
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if fits(mid) {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    payload(lo)
+}
+
+fn payload(len: usize) -> Vec<u8> {
+    b"https://example.com/qrgen-benchmark?id="
+        .iter()
+        .copied()
+        .cycle()
+        .take(len)
+        .collect()
+}
+
+fn print_stats(label: &str, codes: u32, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    println!(
+        "{}: {:.1} codes/sec, {:.2} ms avg",
+        label,
+        codes as f64 / secs,
+        secs * 1000.0 / codes as f64
+    );
+}
+
+/// Encode and draw codes of increasing version in memory and report the
+/// throughput for each
+pub fn run(args: &BenchmarkArgs) -> Result<()> {
+    let level = parse_error_correction(&args.error_correction)?;
+    let fill = Rgba([0, 0, 0, 255]);
+    let back = Rgba([255, 255, 255, 255]);
+
+    println!(
+        "Benchmarking {} codes per version at --size {}, error correction {:?}",
+        args.iterations, args.size, level
+    );
+
+    let mut total = Duration::ZERO;
+    for version in VERSIONS {
+        let data = fill_version(version, level);
+        let width = (version as u32) * 4 + 17;
+        check_canvas_size(width, BORDER, args.size, 100)?;
+
+        let start = Instant::now();
+        for _ in 0..args.iterations {
+            let code = encode_at_version(
+                &data,
+                Version::Normal(version),
+                level,
+                EncodeOptions::default(),
+            )?;
+            black_box(draw_qr_code(&code, args.size, BORDER, &[fill], back, 0.0));
+        }
+        let elapsed = start.elapsed();
+        total += elapsed;
+
+        let label = format!(
+            "Version {:>2} ({}x{}, {} bytes)",
+            version,
+            width,
+            width,
+            data.len()
+        );
+        print_stats(&label, args.iterations, elapsed);
+    }
+
+    print_stats("Total", args.iterations * VERSIONS.len() as u32, total);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_version_is_full() {
+        for version in [1, 10, 40] {
+            let data = fill_version(version, EcLevel::M);
+            assert!(encode_at_version(
+                &data,
+                Version::Normal(version),
+                EcLevel::M,
+                EncodeOptions::default()
+            )
+            .is_ok());
+
+            let mut longer = data.clone();
+            longer.push(b'x');
+            assert!(encode_at_version(
+                &longer,
+                Version::Normal(version),
+                EcLevel::M,
+                EncodeOptions::default()
+            )
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use image::RgbaImage;
+use std::borrow::Cow;
+
+/// Place the rendered image on the system clipboard. On Linux the clipboard
+/// is served by the owning process, so the image only outlives qrgen if a
+/// clipboard manager takes it over.
+pub fn copy_image(img: &RgbaImage) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().context("Clipboard is not available")?;
+
+    clipboard
+        .set_image(arboard::ImageData {
+            width: img.width() as usize,
+            height: img.height() as usize,
+            bytes: Cow::Borrowed(img.as_raw()),
+        })
+        .context("Failed to copy QR code to clipboard")
+}
+
+/// Anything text can be read from like a clipboard, so the input path can be
+/// tested without a display
+trait TextSource {
+    fn get_text(&mut self) -> Result<String, arboard::Error>;
+}
+
+impl TextSource for arboard::Clipboard {
+    fn get_text(&mut self) -> Result<String, arboard::Error> {
+        arboard::Clipboard::get_text(self)
+    }
+}
+
+/// Read the clipboard's text to use as the payload
+pub fn read_text() -> Result<String> {
+    let mut clipboard = arboard::Clipboard::new().context("Clipboard is not available")?;
+    text_from(&mut clipboard)
+}
+
+fn text_from(source: &mut impl TextSource) -> Result<String> {
+    match source.get_text() {
+        // Copying a line often picks up its line break too
+        Ok(text) if !text.trim().is_empty() => Ok(text.trim_end_matches(['\r', '\n']).to_string()),
+        Ok(_) | Err(arboard::Error::ContentNotAvailable) => Err(anyhow::anyhow!(
+            "Clipboard is empty or doesn't contain text"
+        )),
+        Err(e) => Err(e).context("Failed to read the clipboard"),
+    }
+}
+
+#[cfg(test)]
+mod mock_tests {
+    use super::*;
+
+    /// Clipboard stand-in holding text, or nothing like a clipboard with an image
+    struct MockClipboard(Option<&'static str>);
+
+    impl TextSource for MockClipboard {
+        fn get_text(&mut self) -> Result<String, arboard::Error> {
+            self.0
+                .map(str::to_string)
+                .ok_or(arboard::Error::ContentNotAvailable)
+        }
+    }
+
+    #[test]
+    fn test_text_from_clipboard() {
+        let mut clipboard = MockClipboard(Some("https://example.com\n"));
+        assert_eq!(text_from(&mut clipboard).unwrap(), "https://example.com");
+    }
+
+    #[test]
+    fn test_text_from_empty_or_non_text_clipboard() {
+        for contents in [None, Some(""), Some(" \n")] {
+            let err = text_from(&mut MockClipboard(contents)).unwrap_err();
+            assert!(err.to_string().contains("empty or doesn't contain text"));
+        }
+    }
+}
+
+// Needs a real display/clipboard, so only run with --features clipboard-tests
+#[cfg(all(test, feature = "clipboard-tests"))]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_read_text_round_trip() {
+        arboard::Clipboard::new()
+            .unwrap()
+            .set_text("https://example.com")
+            .unwrap();
+        assert_eq!(read_text().unwrap(), "https://example.com");
+    }
+
+    #[test]
+    fn test_copy_image_round_trip() {
+        let img = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        copy_image(&img).unwrap();
+
+        let copied = arboard::Clipboard::new().unwrap().get_image().unwrap();
+        assert_eq!((copied.width, copied.height), (4, 4));
+        assert_eq!(copied.bytes.as_ref(), img.as_raw().as_slice());
+    }
+}
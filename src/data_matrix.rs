@@ -0,0 +1,145 @@
+use anyhow::Result;
+use datamatrix::placement::Bitmap;
+use datamatrix::{DataMatrix, SymbolList};
+use image::{Rgba, RgbaImage};
+
+use crate::{check_canvas_size, fill_module, round_corners, transform_image, Cli, RenderOptions};
+
+/// Encode as an ECC 200 Data Matrix in the smallest square symbol that fits
+fn encode(data: &[u8]) -> Result<Bitmap<bool>> {
+    let code = DataMatrix::encode(data, SymbolList::default().enforce_square())
+        .map_err(|e| anyhow::anyhow!("Failed to generate Data Matrix: {:?}", e))?;
+    Ok(code.bitmap())
+}
+
+fn draw(
+    bitmap: &Bitmap<bool>,
+    size: u32,
+    border: u32,
+    fill_color: Rgba<u8>,
+    back_color: Rgba<u8>,
+) -> RgbaImage {
+    let width = (bitmap.width() as u32 + 2 * border) * size;
+    let height = (bitmap.height() as u32 + 2 * border) * size;
+
+    let mut img = RgbaImage::from_pixel(width, height, back_color);
+    for (x, y) in bitmap.pixels() {
+        fill_module(&mut img, x as u32, y as u32, size, border, 0, fill_color);
+    }
+
+    img
+}
+
+/// Encode and draw a Data Matrix with the same sizing, colors and output
+/// transforms as a QR code
+pub fn render_image(cli: &Cli, opts: &RenderOptions, data: &[u8]) -> Result<RgbaImage> {
+    let bitmap = encode(data)?;
+    check_canvas_size(bitmap.width() as u32, opts.border, cli.size, cli.max_size)?;
+
+    let img = draw(
+        &bitmap,
+        cli.size,
+        opts.border,
+        opts.fill_color,
+        opts.back_color,
+    );
+    let img = transform_image(img, &cli.rotate, &cli.flip)?;
+    round_corners(img, cli.border_radius, opts.border * cli.size)
+}
+
+/// Width of the symbol in modules, without any quiet zone
+pub fn symbol_width(data: &[u8]) -> Result<u32> {
+    Ok(encode(data)?.width() as u32)
+}
+
+/// The symbol as half-block characters, two modules per character cell,
+/// with a one-module quiet zone. On a dark terminal the blocks mark the
+/// light modules instead, so the symbol still reads dark on light.
+fn terminal_string(bitmap: &Bitmap<bool>, dark_terminal: bool) -> String {
+    const BLOCKS: [char; 4] = [' ', '▄', '▀', '█'];
+    let (width, height) = (bitmap.width() + 2, bitmap.height() + 2);
+    let mut dark = vec![false; width * height];
+    for (x, y) in bitmap.pixels() {
+        dark[(y + 1) * width + x + 1] = true;
+    }
+    let filled = |x: usize, y: usize| y < height && dark[y * width + x] != dark_terminal;
+
+    let mut out = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let index = (usize::from(filled(x, y)) << 1) | usize::from(filled(x, y + 1));
+            out.push(BLOCKS[index]);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn print_terminal(data: &[u8], dark_terminal: bool) -> Result<()> {
+    println!("{}", terminal_string(&encode(data)?, dark_terminal));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample the center of every module and decode the result
+    fn decode_image(img: &RgbaImage, size: u32, border: u32) -> Vec<u8> {
+        let modules = img.width() / size - 2 * border;
+        let pixels: Vec<bool> = (0..modules)
+            .flat_map(|y| (0..modules).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let px = (x + border) * size + size / 2;
+                let py = (y + border) * size + size / 2;
+                img.get_pixel(px, py)[0] < 128
+            })
+            .collect();
+        DataMatrix::decode(&pixels, modules as usize).unwrap()
+    }
+
+    #[test]
+    fn test_draw_round_trip() {
+        let bitmap = encode(b"Hello, World!").unwrap();
+        let img = draw(
+            &bitmap,
+            5,
+            2,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        );
+
+        assert_eq!(img.width(), img.height());
+        assert_eq!(decode_image(&img, 5, 2), b"Hello, World!");
+    }
+
+    #[test]
+    fn test_encode_is_square() {
+        let bitmap = encode(b"ABC123").unwrap();
+        assert_eq!(bitmap.width(), bitmap.height());
+    }
+
+    #[test]
+    fn test_terminal_string_matches_unicode() {
+        let bitmap = encode(b"test").unwrap();
+        assert_eq!(terminal_string(&bitmap, false), bitmap.unicode());
+    }
+
+    #[test]
+    fn test_terminal_themes_are_inverted() {
+        let bitmap = encode(b"test").unwrap();
+        let light = terminal_string(&bitmap, false);
+        let dark = terminal_string(&bitmap, true);
+        let inverse = |c| match c {
+            ' ' => '█',
+            '█' => ' ',
+            '▀' => '▄',
+            '▄' => '▀',
+            other => other,
+        };
+        // Square symbols have even sides, so every cell holds two modules
+        // and inverts fully
+        let inverted: String = light.chars().map(inverse).collect();
+        assert_eq!(dark, inverted);
+    }
+}
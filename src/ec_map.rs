@@ -0,0 +1,198 @@
+use anyhow::Result;
+use image::{Rgba, RgbaImage};
+use qrcode::bits::Bits;
+use qrcode::{Color, QrCode, Version};
+
+/// What a module of the symbol carries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    /// Finders, timing, alignment, format and version information
+    Function,
+    /// Part of a data codeword
+    Data,
+    /// Part of an error correction codeword
+    ErrorCorrection,
+    /// Leftover bits after the last codeword
+    Remainder,
+}
+
+/// Dark and light colors for each region in the visualization
+fn region_colors(region: Region) -> (Rgba<u8>, Rgba<u8>) {
+    match region {
+        Region::Function => (Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])),
+        Region::Data => (Rgba([31, 79, 191, 255]), Rgba([201, 216, 255, 255])),
+        Region::ErrorCorrection => (Rgba([192, 57, 43, 255]), Rgba([255, 208, 200, 255])),
+        Region::Remainder => (Rgba([85, 85, 85, 255]), Rgba([221, 221, 221, 255])),
+    }
+}
+
+/// Whether (x, y) holds one of the two version information blocks, which
+/// versions 7 and up carry beside the top-right and bottom-left finders
+fn in_version_info(version: Version, width: usize, x: usize, y: usize) -> bool {
+    match version {
+        Version::Normal(v) if v >= 7 => {
+            let block = |a: usize, b: usize| a < 6 && (width - 11..width - 8).contains(&b);
+            block(x, y) || block(y, x)
+        }
+        _ => false,
+    }
+}
+
+/// Modules that hold codeword bits, in the order bits are placed: upwards
+/// and downwards in two-module columns from the bottom-right corner,
+/// stepping over the vertical timing pattern
+fn placement_order(code: &QrCode) -> Vec<(usize, usize)> {
+    let width = code.width();
+    let version = code.version();
+    let mut order = Vec::new();
+
+    let mut right = width - 1;
+    let mut upwards = true;
+    loop {
+        for i in 0..width {
+            let y = if upwards { width - 1 - i } else { i };
+            for x in [right, right - 1] {
+                if !code.is_functional(x, y) && !in_version_info(version, width, x, y) {
+                    order.push((x, y));
+                }
+            }
+        }
+        if right < 3 {
+            break;
+        }
+        right -= 2;
+        if right == 6 {
+            right = 5;
+        }
+        upwards = !upwards;
+    }
+    order
+}
+
+/// The region of every module, row by row. Interleaved data codewords are
+/// placed first, then the error correction codewords, then remainder bits.
+pub fn module_regions(code: &QrCode) -> Result<Vec<Region>> {
+    let width = code.width();
+    let data_bits = Bits::new(code.version()).max_len(code.error_correction_level())?;
+    let order = placement_order(code);
+    let codeword_bits = order.len() / 8 * 8;
+
+    let mut regions = vec![Region::Function; width * width];
+    for (i, &(x, y)) in order.iter().enumerate() {
+        regions[y * width + x] = if i < data_bits {
+            Region::Data
+        } else if i < codeword_bits {
+            Region::ErrorCorrection
+        } else {
+            Region::Remainder
+        };
+    }
+    Ok(regions)
+}
+
+/// Draw the code with data, error correction and remainder modules tinted
+/// apart, each module `size` pixels, on a white `border`-module quiet zone
+pub fn draw(code: &QrCode, size: u32, border: u32) -> Result<RgbaImage> {
+    let width = code.width();
+    let regions = module_regions(code)?;
+    let colors = code.to_colors();
+
+    let img_size = (width as u32 + 2 * border) * size;
+    let (_, quiet) = region_colors(Region::Function);
+    let mut img = RgbaImage::from_pixel(img_size, img_size, quiet);
+    for (i, (&region, &color)) in regions.iter().zip(&colors).enumerate() {
+        let (dark, light) = region_colors(region);
+        let paint = if color == Color::Dark { dark } else { light };
+        let px = (i % width) as u32 + border;
+        let py = (i / width) as u32 + border;
+        for dy in 0..size {
+            for dx in 0..size {
+                img.put_pixel(px * size + dx, py * size + dy, paint);
+            }
+        }
+    }
+    Ok(img)
+}
+
+/// One-line breakdown of the codewords printed alongside the visualization
+pub fn summary(code: &QrCode) -> Result<String> {
+    let regions = module_regions(code)?;
+    let count = |wanted: Region| regions.iter().filter(|&&r| r == wanted).count() / 8;
+    let data = count(Region::Data);
+    let ec = count(Region::ErrorCorrection);
+    Ok(format!(
+        "{} data codewords (blue), {} error correction codewords (red), {:.0}% redundancy",
+        data,
+        ec,
+        ec as f64 * 100.0 / (data + ec) as f64
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qrcode::EcLevel;
+
+    fn region_counts(code: &QrCode) -> (usize, usize, usize) {
+        let regions = module_regions(code).unwrap();
+        let count = |wanted: Region| regions.iter().filter(|&&r| r == wanted).count();
+        (
+            count(Region::Data),
+            count(Region::ErrorCorrection),
+            count(Region::Remainder),
+        )
+    }
+
+    #[test]
+    fn test_module_regions_match_codeword_counts() {
+        // Version 1-M: 16 data and 10 EC codewords, no remainder bits
+        let code = QrCode::with_version("hi", Version::Normal(1), EcLevel::M).unwrap();
+        assert_eq!(region_counts(&code), (16 * 8, 10 * 8, 0));
+
+        // Version 2-H: 16 data and 28 EC codewords, 7 remainder bits
+        let code = QrCode::with_version("hi", Version::Normal(2), EcLevel::H).unwrap();
+        assert_eq!(region_counts(&code), (16 * 8, 28 * 8, 7));
+
+        // Version 7-L has version information blocks: 156 data and 40 EC
+        // codewords, no remainder bits
+        let code = QrCode::with_version("hi", Version::Normal(7), EcLevel::L).unwrap();
+        assert_eq!(region_counts(&code), (156 * 8, 40 * 8, 0));
+    }
+
+    #[test]
+    fn test_placement_starts_bottom_right() {
+        let code = QrCode::with_version("hi", Version::Normal(1), EcLevel::M).unwrap();
+        let order = placement_order(&code);
+        assert_eq!(&order[..4], &[(20, 20), (19, 20), (20, 19), (19, 19)]);
+        // Column 6 is the timing pattern, so the last column pair is 1 and 0
+        assert_eq!(order.last(), Some(&(0, 12)));
+    }
+
+    #[test]
+    fn test_draw_tints_regions() {
+        let code = QrCode::with_version("hi", Version::Normal(1), EcLevel::H).unwrap();
+        let img = draw(&code, 2, 1).unwrap();
+        assert_eq!(img.dimensions(), (46, 46));
+
+        let regions = module_regions(&code).unwrap();
+        let colors = code.to_colors();
+        // The first placed module (20, 20) is data, in the data palette
+        let (dark, light) = region_colors(regions[20 * 21 + 20]);
+        assert_eq!(regions[20 * 21 + 20], Region::Data);
+        let expected = if colors[20 * 21 + 20] == Color::Dark {
+            dark
+        } else {
+            light
+        };
+        assert_eq!(*img.get_pixel(42, 42), expected);
+    }
+
+    #[test]
+    fn test_summary() {
+        let code = QrCode::with_version("hi", Version::Normal(1), EcLevel::M).unwrap();
+        assert_eq!(
+            summary(&code).unwrap(),
+            "16 data codewords (blue), 10 error correction codewords (red), 38% redundancy"
+        );
+    }
+}
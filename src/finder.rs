@@ -0,0 +1,179 @@
+use image::{Rgba, RgbaImage};
+
+use crate::palette_color;
+
+/// Modules along each side of a finder pattern
+const FINDER_MODULES: u32 = 7;
+
+/// How the three finder patterns are drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// Plain 7x7 squares, as drawn by every other renderer
+    Square,
+    /// Rounded corners inside the ring and on the center dot
+    Rounded,
+    /// Circular hole in the ring around a circular dot
+    Circle,
+    /// Like `Circle` but with the top-right and bottom-left corners sharp
+    Leaf,
+}
+
+impl Shape {
+    /// The shape named by `--finder-shape`
+    pub fn from_name(name: &str) -> Shape {
+        match name {
+            "rounded" => Shape::Rounded,
+            "circle" => Shape::Circle,
+            "leaf" => Shape::Leaf,
+            _ => Shape::Square,
+        }
+    }
+
+    /// Corner radii, in modules, of the hole inside the ring and of the
+    /// center dot, each top-left, top-right, bottom-right, bottom-left. The
+    /// ring's outer edge always stays square: detectors fit the code's
+    /// perspective to those corners, and rounding them breaks decoding.
+    fn radii(self) -> [[f32; 4]; 2] {
+        match self {
+            Shape::Square => [[0.0; 4]; 2],
+            Shape::Rounded => [[1.0; 4], [1.0; 4]],
+            Shape::Circle => [[2.5; 4], [1.5; 4]],
+            Shape::Leaf => [[2.0, 0.0, 2.0, 0.0], [1.5, 0.0, 1.5, 0.0]],
+        }
+    }
+
+    /// Whether the point (u, v), in modules from the finder's top-left
+    /// corner, is dark. The ring and dot keep the square pattern's 1:1:3:1:1
+    /// runs through the center, which is what scanners look for.
+    fn is_dark(self, u: f32, v: f32) -> bool {
+        let [hole, dot] = self.radii();
+        let edge = FINDER_MODULES as f32;
+        (in_rounded_rect(u, v, 0.0, edge, [0.0; 4])
+            && !in_rounded_rect(u, v, 1.0, edge - 1.0, hole))
+            || in_rounded_rect(u, v, 2.0, edge - 2.0, dot)
+    }
+}
+
+/// Whether (u, v) lies in the square from `start` to `end` on both axes with
+/// its corners rounded by `radii`
+fn in_rounded_rect(u: f32, v: f32, start: f32, end: f32, radii: [f32; 4]) -> bool {
+    if u < start || u > end || v < start || v > end {
+        return false;
+    }
+
+    let corners = [(start, start), (end, start), (end, end), (start, end)];
+    corners.iter().zip(radii).all(|(&(cx, cy), r)| {
+        // Center of the corner's arc, `r` in from both edges
+        let ax = if cx == start { start + r } else { end - r };
+        let ay = if cy == start { start + r } else { end - r };
+        let outside_x = if cx == start { u < ax } else { u > ax };
+        let outside_y = if cy == start { v < ay } else { v > ay };
+        !(outside_x && outside_y) || (u - ax).powi(2) + (v - ay).powi(2) <= r * r
+    })
+}
+
+/// Redraw the three finder patterns of an already drawn code as `shape`.
+/// Dark pixels take the fill color the module would have had; pixels the
+/// shape leaves light are restored with `light(x, y)`.
+pub fn restyle(
+    img: &mut RgbaImage,
+    qr_width: u32,
+    size: u32,
+    border: u32,
+    fill: &[Rgba<u8>],
+    shape: Shape,
+    light: impl Fn(u32, u32) -> Rgba<u8>,
+) {
+    if shape == Shape::Square {
+        return;
+    }
+
+    let far = qr_width - FINDER_MODULES;
+    for (fx, fy) in [(0, 0), (far, 0), (0, far)] {
+        for dy in 0..FINDER_MODULES * size {
+            for dx in 0..FINDER_MODULES * size {
+                // Sample each pixel at its center
+                let u = (dx as f32 + 0.5) / size as f32;
+                let v = (dy as f32 + 0.5) / size as f32;
+                let (ix, iy) = ((fx + border) * size + dx, (fy + border) * size + dy);
+                let color = if shape.is_dark(u, v) {
+                    palette_color(fill, fx + dx / size, fy + dy / size, qr_width)
+                } else {
+                    light(ix, iy)
+                };
+                img.put_pixel(ix, iy, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    /// Dark modules of a square finder, for comparing against the shapes
+    fn square_dark(u: f32, v: f32) -> bool {
+        let ring = |t: f32| (0.0..1.0).contains(&t) || (6.0..7.0).contains(&t);
+        let dot = |t: f32| (2.0..5.0).contains(&t);
+        ring(u) || ring(v) || (dot(u) && dot(v))
+    }
+
+    #[test]
+    fn test_square_matches_finder_pattern() {
+        for y in 0..7 {
+            for x in 0..7 {
+                let (u, v) = (x as f32 + 0.5, y as f32 + 0.5);
+                assert_eq!(Shape::Square.is_dark(u, v), square_dark(u, v), "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn test_shapes_keep_center_runs() {
+        // Scanners look for 1:1:3:1:1 runs along the lines through the center
+        for shape in [Shape::Rounded, Shape::Circle, Shape::Leaf] {
+            for i in 0..7 {
+                let t = i as f32 + 0.5;
+                assert_eq!(shape.is_dark(t, 3.5), square_dark(t, 3.5), "{shape:?}");
+                assert_eq!(shape.is_dark(3.5, t), square_dark(3.5, t), "{shape:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_shape_corners() {
+        for shape in [Shape::Rounded, Shape::Circle, Shape::Leaf] {
+            // The outer corners stay square for detectors
+            assert!(shape.is_dark(0.1, 0.1), "{shape:?}");
+            assert!(shape.is_dark(6.9, 6.9), "{shape:?}");
+        }
+        // Inside the ring and on the dot, corners are cut away
+        assert!(Shape::Rounded.is_dark(1.1, 1.1));
+        assert!(!Shape::Rounded.is_dark(2.1, 2.1));
+        assert!(Shape::Circle.is_dark(1.6, 5.4));
+        assert!(!Shape::Circle.is_dark(2.3, 4.7));
+        assert!(Shape::Leaf.is_dark(1.1, 1.1));
+        assert!(!Shape::Leaf.is_dark(5.9, 1.1));
+        assert!(Shape::Leaf.is_dark(4.9, 2.1));
+    }
+
+    #[test]
+    fn test_restyle_only_touches_finders() {
+        let size = 4;
+        let mut img = RgbaImage::from_pixel(21 * size, 21 * size, BLACK);
+        restyle(&mut img, 21, size, 0, &[BLACK], Shape::Circle, |_, _| WHITE);
+
+        // Each finder's hole is cleared, the rest of the code is untouched
+        assert_eq!(*img.get_pixel(0, 0), BLACK);
+        assert_eq!(*img.get_pixel(3 * size, size + 1), WHITE);
+        assert_eq!(*img.get_pixel(17 * size, size + 1), WHITE);
+        assert_eq!(*img.get_pixel(3 * size, 15 * size + 1), WHITE);
+        assert_eq!(*img.get_pixel(18 * size, 18 * size), BLACK);
+        assert_eq!(*img.get_pixel(10 * size, 10 * size), BLACK);
+        // Center of the top-left finder stays dark
+        assert_eq!(*img.get_pixel(3 * size + 1, 3 * size + 1), BLACK);
+    }
+}
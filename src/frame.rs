@@ -0,0 +1,215 @@
+use anyhow::Result;
+use image::{GenericImage, Rgba, RgbaImage};
+
+use crate::text::Font;
+
+/// A rounded frame drawn around the code, with a labelled tab underneath
+/// pointing up at it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub text: String,
+    pub color: Rgba<u8>,
+    pub text_color: Rgba<u8>,
+}
+
+/// The tab text for `--brand-frame`; `scan-me` is shorthand for "SCAN ME"
+pub fn frame_text(spec: &str) -> String {
+    match spec {
+        "scan-me" => "SCAN ME".to_string(),
+        _ => spec.to_string(),
+    }
+}
+
+/// Whether (x, y) lies inside a `width` by `height` rectangle whose corners
+/// are rounded by `radius`, sampling at the pixel's center
+fn in_rounded_rect(x: u32, y: u32, width: u32, height: u32, radius: u32) -> bool {
+    let r = radius as f32;
+    let dx = r - (x.min(width - 1 - x) as f32 + 0.5);
+    let dy = r - (y.min(height - 1 - y) as f32 + 0.5);
+    dx <= 0.0 || dy <= 0.0 || dx * dx + dy * dy <= r * r
+}
+
+/// Paint a rounded rectangle with its top-left corner at (left, top)
+fn fill_rounded_rect(
+    img: &mut RgbaImage,
+    (left, top): (u32, u32),
+    (width, height): (u32, u32),
+    radius: u32,
+    color: Rgba<u8>,
+) {
+    for y in 0..height {
+        for x in 0..width {
+            if in_rounded_rect(x, y, width, height, radius) {
+                img.put_pixel(left + x, top + y, color);
+            }
+        }
+    }
+}
+
+/// Largest text scale, up to half a module, at which `text` fits in a tab
+/// no wider than `max_width`
+fn text_scale(text: &str, font: &Font, module_size: u32, max_width: u32) -> Option<u32> {
+    (1..=(module_size / 2).max(1))
+        .rev()
+        .find(|&scale| font.text_size(text, scale).0 + 6 * scale <= max_width)
+}
+
+/// Text scale and tab size for a frame `width` pixels wide
+fn tab_layout(
+    frame: &Frame,
+    font: &Font,
+    module_size: u32,
+    width: u32,
+) -> Result<(u32, (u32, u32))> {
+    let scale = text_scale(&frame.text, font, module_size, width).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Frame text {:?} is too long to fit under the code; shorten it or use a larger --size",
+            frame.text
+        )
+    })?;
+    let (text_width, text_height) = font.text_size(&frame.text, scale);
+    Ok((scale, (text_width + 6 * scale, text_height + 4 * scale)))
+}
+
+/// Width and height of everything `draw` produces for a `code_size` code:
+/// the framed code plus the tab layer below it
+pub fn footprint(
+    frame: &Frame,
+    font: &Font,
+    module_size: u32,
+    code_size: (u32, u32),
+) -> Result<(u32, u32)> {
+    let band = module_size;
+    let width = code_size.0 + 2 * band;
+    let (scale, tab) = tab_layout(frame, font, module_size, width)?;
+    // The tab hangs below a pointer as tall as two text pixels
+    Ok((width, code_size.1 + 2 * band + 2 * scale + tab.1))
+}
+
+/// Surround `code`, quiet zone and all, with `frame`: a rounded band one
+/// module wide. The code itself is copied in untouched; everything outside
+/// the frame is `back_color`. The tab carrying the text comes back as a
+/// separate layer, as wide as the frame, for stacking below it.
+pub fn draw(
+    code: &RgbaImage,
+    frame: &Frame,
+    font: &Font,
+    module_size: u32,
+    back_color: Rgba<u8>,
+) -> Result<(RgbaImage, RgbaImage)> {
+    let band = module_size;
+    let width = code.width() + 2 * band;
+    let (scale, tab) = tab_layout(frame, font, module_size, width)?;
+
+    let framed_height = code.height() + 2 * band;
+    let mut framed = RgbaImage::from_pixel(width, framed_height, back_color);
+    fill_rounded_rect(
+        &mut framed,
+        (0, 0),
+        (width, framed_height),
+        2 * band,
+        frame.color,
+    );
+    framed.copy_from(code, band, band)?;
+
+    // The pointer is as tall as the gap between the frame and the tab
+    let pointer = 2 * scale;
+    let mut below = RgbaImage::from_pixel(width, pointer + tab.1, back_color);
+
+    // Upward triangle from the tab to the bottom of the frame
+    let center = width / 2;
+    for row in 0..pointer {
+        let half = row + 1;
+        for x in center.saturating_sub(half)..(center + half).min(width) {
+            below.put_pixel(x, row, frame.color);
+        }
+    }
+
+    let tab_left = (width - tab.0) / 2;
+    fill_rounded_rect(&mut below, (tab_left, pointer), tab, 2 * scale, frame.color);
+    font.draw_text(
+        &mut below,
+        &frame.text,
+        (tab_left + 3 * scale, pointer + 2 * scale),
+        scale,
+        frame.text_color,
+    );
+
+    Ok((framed, below))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    fn red_frame(text: &str) -> Frame {
+        Frame {
+            text: text.to_string(),
+            color: RED,
+            text_color: WHITE,
+        }
+    }
+
+    #[test]
+    fn test_frame_text() {
+        assert_eq!(frame_text("scan-me"), "SCAN ME");
+        assert_eq!(frame_text("Menu"), "Menu");
+    }
+
+    /// The frame with its tab stacked below, as the renderer lays them out
+    fn draw_stacked(code: &RgbaImage, frame: &Frame) -> Result<RgbaImage> {
+        let (framed, below) = draw(code, frame, &Font::Builtin, 10, WHITE)?;
+        Ok(layout::compose(framed, &[], &[below], WHITE, false).0)
+    }
+
+    #[test]
+    fn test_draw_frame_keeps_code() {
+        let code = RgbaImage::from_pixel(290, 290, BLACK);
+        let img = draw_stacked(&code, &red_frame("SCAN ME")).unwrap();
+
+        // 10px band each side; 5x text is 35px tall in a 55px tab under a 10px pointer
+        assert_eq!(img.dimensions(), (310, 310 + 10 + 55));
+        assert!((10..300).all(|y| (10..300).all(|x| *img.get_pixel(x, y) == BLACK)));
+
+        // Band on each side, corners rounded off
+        assert_eq!(*img.get_pixel(0, 150), RED);
+        assert_eq!(*img.get_pixel(150, 0), RED);
+        assert_eq!(*img.get_pixel(150, 309), RED);
+        assert_eq!(*img.get_pixel(0, 0), WHITE);
+
+        // The pointer joins the frame to the tab, with background beside it
+        assert_eq!(*img.get_pixel(155, 312), RED);
+        assert_eq!(*img.get_pixel(20, 312), WHITE);
+        // Tab text: the top-left pixel of "S" is blank, the next one is drawn
+        let tab_left = (310 - (205 + 30)) / 2;
+        assert_eq!(*img.get_pixel(tab_left + 15, 320 + 10), RED);
+        assert_eq!(*img.get_pixel(tab_left + 15 + 5, 320 + 10), WHITE);
+    }
+
+    #[test]
+    fn test_long_frame_text_shrinks_then_fails() {
+        let code = RgbaImage::from_pixel(290, 290, BLACK);
+        let long = "VISIT OUR WEBSITE TODAY";
+        let img = draw_stacked(&code, &red_frame(long)).unwrap();
+        // Scale 5 would be 685px wide, so it drops to 2
+        assert_eq!(img.height(), 310 + 4 + 22);
+
+        let too_long = "X".repeat(100);
+        assert!(draw_stacked(&code, &red_frame(&too_long)).is_err());
+    }
+
+    #[test]
+    fn test_footprint_matches_draw() {
+        let code = RgbaImage::from_pixel(290, 290, BLACK);
+        for text in ["SCAN ME", "VISIT OUR WEBSITE TODAY"] {
+            let img = draw_stacked(&code, &red_frame(text)).unwrap();
+            let size = footprint(&red_frame(text), &Font::Builtin, 10, (290, 290)).unwrap();
+            assert_eq!(size, img.dimensions());
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use image::{Rgba, RgbaImage};
+
+use crate::{contrast_ratio, parse_color};
+
+/// Points sampled along the gradient when looking for its weakest contrast
+const CONTRAST_SAMPLES: u32 = 64;
+
+/// Which way a gradient runs from its start color to its end color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// Top to bottom
+    Vertical,
+    /// Left to right
+    Horizontal,
+    /// Top-left corner to bottom-right
+    Diagonal,
+}
+
+/// A two-color linear gradient for the light area of the code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gradient {
+    pub start: Rgba<u8>,
+    pub end: Rgba<u8>,
+    pub direction: Direction,
+}
+
+/// Parse `--back-gradient` as two comma-separated colors
+pub fn parse_gradient(spec: &str, direction: &str) -> Result<Gradient> {
+    let (start, end) = spec
+        .split_once(',')
+        .ok_or_else(|| anyhow::anyhow!("expected two colors separated by a comma"))?;
+    let start = start.trim();
+    let end = end.trim();
+
+    Ok(Gradient {
+        start: parse_color(start).with_context(|| format!("Invalid color: {}", start))?,
+        end: parse_color(end).with_context(|| format!("Invalid color: {}", end))?,
+        direction: match direction {
+            "horizontal" => Direction::Horizontal,
+            "diagonal" => Direction::Diagonal,
+            _ => Direction::Vertical,
+        },
+    })
+}
+
+impl Gradient {
+    /// Color `t` of the way from `start` to `end`, with `t` in 0.0-1.0
+    fn mix(&self, t: f32) -> Rgba<u8> {
+        let channel = |i: usize| {
+            (self.start[i] as f32 + (self.end[i] as f32 - self.start[i] as f32) * t).round() as u8
+        };
+        Rgba([channel(0), channel(1), channel(2), channel(3)])
+    }
+
+    /// Color of the pixel at (x, y) in a `width` by `height` gradient image
+    pub fn color_at(&self, x: u32, y: u32, width: u32, height: u32) -> Rgba<u8> {
+        let span = |len: u32| len.saturating_sub(1).max(1) as f32;
+        let t = match self.direction {
+            Direction::Vertical => y as f32 / span(height),
+            Direction::Horizontal => x as f32 / span(width),
+            Direction::Diagonal => (x + y) as f32 / span(width + height - 1),
+        };
+        self.mix(t)
+    }
+
+    /// A `width` by `height` image filled with the gradient
+    pub fn image(&self, width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| self.color_at(x, y, width, height))
+    }
+
+    /// The color along the gradient with the least contrast against `fill`.
+    /// Mixing two hues can dip in luminance between them, so the whole
+    /// gradient is sampled rather than just its ends.
+    pub fn least_contrast(&self, fill: Rgba<u8>) -> Rgba<u8> {
+        (0..=CONTRAST_SAMPLES)
+            .map(|i| self.mix(i as f32 / CONTRAST_SAMPLES as f32))
+            .min_by(|a, b| contrast_ratio(fill, *a).total_cmp(&contrast_ratio(fill, *b)))
+            .unwrap_or(self.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    #[test]
+    fn test_parse_gradient() {
+        let gradient = parse_gradient("white, #000", "diagonal").unwrap();
+        assert_eq!(gradient.start, WHITE);
+        assert_eq!(gradient.end, BLACK);
+        assert_eq!(gradient.direction, Direction::Diagonal);
+
+        assert!(parse_gradient("white", "vertical").is_err());
+        assert!(parse_gradient("white,nope", "vertical").is_err());
+    }
+
+    #[test]
+    fn test_gradient_image_directions() {
+        let gradient = parse_gradient("white,black", "vertical").unwrap();
+        let img = gradient.image(10, 10);
+        assert_eq!(*img.get_pixel(0, 0), WHITE);
+        assert_eq!(*img.get_pixel(9, 0), WHITE);
+        assert_eq!(*img.get_pixel(0, 9), BLACK);
+
+        let gradient = Gradient {
+            direction: Direction::Horizontal,
+            ..gradient
+        };
+        let img = gradient.image(10, 10);
+        assert_eq!(*img.get_pixel(0, 9), WHITE);
+        assert_eq!(*img.get_pixel(9, 0), BLACK);
+
+        let gradient = Gradient {
+            direction: Direction::Diagonal,
+            ..gradient
+        };
+        let img = gradient.image(10, 10);
+        assert_eq!(*img.get_pixel(0, 0), WHITE);
+        assert_eq!(*img.get_pixel(9, 9), BLACK);
+        assert_eq!(img.get_pixel(9, 0), img.get_pixel(0, 9));
+    }
+
+    #[test]
+    fn test_least_contrast_picks_closest_color() {
+        let gradient = parse_gradient("white,#808080", "vertical").unwrap();
+        assert_eq!(gradient.least_contrast(BLACK), Rgba([128, 128, 128, 255]));
+        assert_eq!(gradient.least_contrast(WHITE), WHITE);
+    }
+}
@@ -0,0 +1,161 @@
+use image::{Rgba, RgbaImage};
+
+/// Width of the quiet zone on each side of the code, in modules
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sides {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+impl Sides {
+    pub fn min(&self) -> u32 {
+        self.top.min(self.right).min(self.bottom).min(self.left)
+    }
+
+    pub fn max(&self) -> u32 {
+        self.top.max(self.right).max(self.bottom).max(self.left)
+    }
+}
+
+/// Surround the code with a `color` border `sides` modules wide, each module
+/// `module_size` pixels. The code sits `left` modules in and `top` modules down.
+pub fn pad(code: RgbaImage, sides: Sides, module_size: u32, color: Rgba<u8>) -> RgbaImage {
+    let width = code.width() + (sides.left + sides.right) * module_size;
+    let height = code.height() + (sides.top + sides.bottom) * module_size;
+
+    let mut image = RgbaImage::from_pixel(width, height, color);
+    image::imageops::overlay(
+        &mut image,
+        &code,
+        (sides.left * module_size) as i64,
+        (sides.top * module_size) as i64,
+    );
+    image
+}
+
+/// Stack bands of decoration above and below the rendered code, each centered
+/// horizontally on a `back_color` canvas. The code image keeps its own square
+/// dimensions; with `square` the canvas is padded out to a square around the
+/// stack, otherwise it is only as wide and tall as the layers need. Returns the
+/// canvas and the top-left corner of the code image on it.
+pub fn compose(
+    code: RgbaImage,
+    above: &[RgbaImage],
+    below: &[RgbaImage],
+    back_color: Rgba<u8>,
+    square: bool,
+) -> (RgbaImage, (u32, u32)) {
+    if above.is_empty() && below.is_empty() {
+        return (code, (0, 0));
+    }
+
+    let layers: Vec<&RgbaImage> = above
+        .iter()
+        .chain(std::iter::once(&code))
+        .chain(below)
+        .collect();
+    let stack_width = layers.iter().map(|layer| layer.width()).max().unwrap_or(0);
+    let stack_height: u32 = layers.iter().map(|layer| layer.height()).sum();
+
+    let (width, height) = if square {
+        let side = stack_width.max(stack_height);
+        (side, side)
+    } else {
+        (stack_width, stack_height)
+    };
+
+    let mut image = RgbaImage::from_pixel(width, height, back_color);
+    let mut y = (height - stack_height) / 2;
+    let mut code_origin = (0, 0);
+    for (i, layer) in layers.iter().enumerate() {
+        let x = (width - layer.width()) / 2;
+        image::imageops::overlay(&mut image, *layer, x as i64, y as i64);
+        if i == above.len() {
+            code_origin = (x, y);
+        }
+        y += layer.height();
+    }
+
+    (image, code_origin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    #[test]
+    fn test_pad_asymmetric_sides() {
+        let code = RgbaImage::from_pixel(50, 50, BLACK);
+        let sides = Sides {
+            top: 1,
+            right: 2,
+            bottom: 3,
+            left: 4,
+        };
+
+        let image = pad(code, sides, 10, RED);
+        assert_eq!(image.dimensions(), (110, 90));
+        // The code starts 4 modules in and 1 module down
+        assert_eq!(*image.get_pixel(40, 10), BLACK);
+        assert_eq!(*image.get_pixel(89, 59), BLACK);
+        assert_eq!(*image.get_pixel(39, 10), RED);
+        assert_eq!(*image.get_pixel(40, 9), RED);
+        assert_eq!(*image.get_pixel(90, 59), RED);
+        assert_eq!(*image.get_pixel(89, 60), RED);
+    }
+
+    #[test]
+    fn test_sides_min_max() {
+        let sides = Sides {
+            top: 4,
+            right: 6,
+            bottom: 5,
+            left: 8,
+        };
+        assert_eq!(sides.min(), 4);
+        assert_eq!(sides.max(), 8);
+    }
+
+    #[test]
+    fn test_compose_without_layers_is_unchanged() {
+        let code = RgbaImage::from_pixel(50, 50, BLACK);
+        let (image, code_origin) = compose(code.clone(), &[], &[], WHITE, true);
+        assert_eq!(image, code);
+        assert_eq!(code_origin, (0, 0));
+    }
+
+    #[test]
+    fn test_compose_taller_canvas_keeps_code_square() {
+        let code = RgbaImage::from_pixel(50, 50, BLACK);
+        let band = RgbaImage::from_pixel(30, 20, RED);
+
+        let (image, code_origin) = compose(code, &[], &[band], WHITE, false);
+        assert_eq!(image.dimensions(), (50, 70));
+        assert_eq!(code_origin, (0, 0));
+
+        // The code occupies exactly the top 50x50, the band is centered below it
+        assert!((0..50).all(|y| (0..50).all(|x| *image.get_pixel(x, y) == BLACK)));
+        assert_eq!(*image.get_pixel(25, 60), RED);
+        assert_eq!(*image.get_pixel(5, 60), WHITE);
+    }
+
+    #[test]
+    fn test_compose_square_pads_around_stack() {
+        let code = RgbaImage::from_pixel(50, 50, BLACK);
+        let title = RgbaImage::from_pixel(40, 10, RED);
+        let band = RgbaImage::from_pixel(40, 10, RED);
+
+        let (image, code_origin) = compose(code, &[title], &[band], WHITE, true);
+        assert_eq!(image.dimensions(), (70, 70));
+        // 70 wide leaves 10px either side of the code; the stack starts at y = 0
+        assert_eq!(code_origin, (10, 10));
+        assert_eq!(*image.get_pixel(10, 10), BLACK);
+        assert_eq!(*image.get_pixel(9, 30), WHITE);
+    }
+}
@@ -1,14 +1,40 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use image::{Rgba, RgbaImage};
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use image::{DynamicImage, Rgba, RgbaImage};
+use qrcode::bits::Bits;
+use qrcode::types::QrError;
 use qrcode::EcLevel;
-use qrcode::{Color, QrCode};
-use std::path::PathBuf;
+use qrcode::{Color, QrCode, Version};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use unicode_normalization::UnicodeNormalization;
 
+mod batch;
+mod benchmark;
+mod clipboard;
+mod data_matrix;
+mod ec_map;
+mod finder;
+mod frame;
+mod gradient;
+mod layout;
+mod output;
+mod reencode;
+mod run_log;
+mod scannability;
+mod segments;
+mod shorten;
+mod svg;
 mod templates;
-use templates::apply_template;
+mod text;
+use output::OutputFormat;
+use scannability::ScanSettings;
+use segments::EncodeOptions;
+use templates::{apply_template, FieldOptions, PromptOptions, TemplateOptions, WifiOptions};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     name = "qrgen",
     version = "0.1.0",
@@ -20,46 +46,765 @@ use templates::apply_template;
   qrgen \"https://example.com\"
   qrgen \"Hello World\" -o qr.png
   qrgen \"https://github.com\" --terminal
-  qrgen \"Contact: john@example.com\" -o contact.png --size 15")]
+  qrgen \"Contact: john@example.com\" -o contact.png --size 15
+  qrgen --batch codes.csv -o out/
+  qrgen reencode old.png --size 20 --fill-color darkblue -o new.png
+  qrgen benchmark --size 5 -e H")]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The data to encode in the QR code (text, URL, etc.)
-    data: String,
+    #[arg(required_unless_present_any = ["batch", "from_lines", "from_clipboard", "template_help"])]
+    data: Option<String>,
 
-    /// Output file path (PNG format). Default: qr_code.png
+    /// Output file path; the extension picks the format (.png, .jpg, .svg, .webp,
+    /// .bmp, .tif, or .json for the module matrix). Default: qr_code.png
     #[arg(short, long)]
     output: Option<PathBuf>,
 
+    /// Force the output format regardless of the file extension
+    #[arg(long, value_parser = ["png", "jpeg", "svg", "webp", "bmp", "tiff", "json"])]
+    output_format: Option<String>,
+
+    /// Write each of these formats from a single encode, next to --output with
+    /// the extension swapped (e.g. --emit png,json,svg)
+    #[arg(
+        long,
+        alias = "split-output",
+        value_delimiter = ',',
+        value_parser = ["png", "jpeg", "svg", "webp", "bmp", "tiff", "json"],
+        conflicts_with_all = ["output_format", "batch", "from_lines", "count"]
+    )]
+    emit: Vec<String>,
+
     /// Size of each box in pixels
     #[arg(short, long, default_value = "10")]
     size: u32,
 
+    /// Print width of the whole image including the quiet zone, in millimetres;
+    /// overrides --size
+    #[arg(long)]
+    physical_size: Option<f64>,
+
+    /// Largest width or height of the image in pixels; --size is reduced to
+    /// fit, down to 1 px modules
+    #[arg(long, conflicts_with = "physical_size", value_parser = clap::value_parser!(u32).range(1..))]
+    max_dimension: Option<u32>,
+
+    /// Print resolution for --physical-size, recorded in PNG output [default: 300]
+    #[arg(long)]
+    dpi: Option<u32>,
+
+    /// Largest image to render, in megabytes of RGBA pixel data
+    #[arg(long, default_value = "100")]
+    max_size: u64,
+
+    /// Smallest module size in pixels to render without --allow-tiny; smaller
+    /// modules often fall below what a camera can resolve
+    #[arg(long, default_value = "3")]
+    min_module_pixels: u32,
+
+    /// Render modules smaller than --min-module-pixels anyway
+    #[arg(long)]
+    allow_tiny: bool,
+
     /// Border size in boxes
     #[arg(short, long, default_value = "4")]
     border: u32,
 
-    /// Error correction level: L(7%), M(15%), Q(25%), H(30%)
-    #[arg(short, long, value_parser = parse_error_correction, default_value = "M")]
-    error_correction: EcLevel,
+    /// Quiet zone in boxes the code always keeps; a smaller --border is widened
+    /// to it with a warning
+    #[arg(long, default_value_t = MIN_QUIET_ZONE)]
+    quiet_zone_modules: u32,
+
+    /// Omit the quiet zone entirely (overrides --border; may hurt scanning)
+    #[arg(long)]
+    no_quiet_zone: bool,
+
+    /// Quiet zone above the code in boxes, at least --quiet-zone-modules
+    /// [default: --border]
+    #[arg(long)]
+    border_top: Option<u32>,
+
+    /// Quiet zone right of the code in boxes, at least --quiet-zone-modules
+    /// [default: --border]
+    #[arg(long)]
+    border_right: Option<u32>,
+
+    /// Quiet zone below the code in boxes, at least --quiet-zone-modules
+    /// [default: --border]
+    #[arg(long)]
+    border_bottom: Option<u32>,
+
+    /// Quiet zone left of the code in boxes, at least --quiet-zone-modules
+    /// [default: --border]
+    #[arg(long)]
+    border_left: Option<u32>,
+
+    /// Color of the quiet zone, if it should differ from the back color
+    #[arg(long)]
+    border_color: Option<String>,
+
+    /// Draw only the dark modules on a transparent canvas exactly as wide as
+    /// the module grid, for compositing elsewhere (PNG only; ignores --border
+    /// and --back-color)
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "logo",
+            "background_image",
+            "border_radius",
+            "border_top",
+            "border_right",
+            "border_bottom",
+            "border_left",
+            "border_color",
+        ]
+    )]
+    code_only: bool,
+
+    /// Error correction level: L(7%), M(15%), Q(25%), H(30%), or auto for the highest
+    /// level that doesn't grow the code [default: M, the template's level with
+    /// --template, and at least Q with --logo]
+    #[arg(short, long, value_parser = parse_ec_choice)]
+    error_correction: Option<EcChoice>,
+
+    /// If the data doesn't fit at the chosen error correction level, retry at
+    /// each lower level (H, Q, M, L) and report the one used
+    #[arg(long)]
+    auto_downgrade_ec: bool,
+
+    /// Never produce a code smaller than this QR version (1-40), so short data
+    /// still gets modules large enough to scan from a distance; longer data
+    /// grows past it as usual
+    #[arg(long, value_parser = clap::value_parser!(i16).range(1..=40))]
+    min_version: Option<i16>,
 
     /// Display QR code in terminal using ASCII characters
     #[arg(short, long)]
     terminal: bool,
 
+    /// Background of the terminal for --terminal: "light", "dark", or "auto"
+    /// to read it from COLORFGBG. Dark backgrounds get the code inverted so
+    /// it still reads as dark modules on a light quiet zone.
+    #[arg(long, default_value = "auto", value_parser = ["auto", "light", "dark"])]
+    terminal_theme: String,
+
     /// Fill color for QR code modules (default: black)
-    #[arg(long, default_value = "black")]
-    fill_color: String,
+    #[arg(long)]
+    fill_color: Option<String>,
+
+    /// Color dark modules in diagonal bands from a palette: "rainbow" or a file
+    /// with one color per line
+    #[arg(long, conflicts_with_all = ["fill_color", "color_from_logo"])]
+    palette: Option<String>,
+
+    /// Use the logo's dominant color as the fill color, unless --fill-color is given
+    #[arg(long, requires = "logo")]
+    color_from_logo: bool,
 
     /// Background color for QR code (default: white)
     #[arg(long, default_value = "white")]
     back_color: String,
 
+    /// When the fill and back colors contrast too little, darken the darker one
+    /// and lighten the lighter one, keeping their hues, until they reach 4.5:1
+    #[arg(long)]
+    auto_contrast: bool,
+
+    /// Paint light modules and the quiet zone with a gradient between two colors,
+    /// e.g. "white,#d0e4ff"; dark modules keep the solid fill color (raster output only)
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "background_image",
+            "module_image",
+            "code_only",
+            "border_top",
+            "border_right",
+            "border_bottom",
+            "border_left",
+            "border_color",
+        ]
+    )]
+    back_gradient: Option<String>,
+
+    /// Direction of --back-gradient, from the first color to the second
+    #[arg(
+        long,
+        value_parser = ["vertical", "horizontal", "diagonal"],
+        default_value = "vertical",
+        requires = "back_gradient"
+    )]
+    back_gradient_direction: String,
+
     /// Path to logo image to embed in center of QR code
     #[arg(long)]
     logo: Option<PathBuf>,
 
+    /// With --logo, grow the QR version until the logo covers no more than the
+    /// error correction can recover
+    #[arg(long, requires = "logo")]
+    auto_version: bool,
+
+    /// With --logo, pick the lowest error correction level that can recover the
+    /// modules hidden by the logo, and refuse to generate if none can
+    #[arg(long, requires = "logo")]
+    center_logo_exclusion: bool,
+
+    /// Fade the edge of the logo's white box into the code over this many pixels
+    #[arg(long, default_value = "0", requires = "logo")]
+    logo_feather: u32,
+
+    /// Shape of the logo and its white box
+    #[arg(long, value_parser = ["square", "circle"], default_value = "square", requires = "logo")]
+    logo_shape: String,
+
+    /// Where to place the logo; corners are inset past the finder patterns
+    #[arg(
+        long,
+        value_parser = ["center", "top-left", "top-right", "bottom-left", "bottom-right"],
+        default_value = "center",
+        requires = "logo"
+    )]
+    logo_position: String,
+
+    /// Short text drawn under the logo inside its white box, e.g. "Scan me"
+    #[arg(long, requires = "logo")]
+    logo_caption: Option<String>,
+
+    /// Draw a ring in this color around the logo's white box [default: the
+    /// fill color when --logo-border-width is given]
+    #[arg(long, requires = "logo")]
+    logo_border_color: Option<String>,
+
+    /// Width of the ring around the logo's white box in pixels [default: one
+    /// module when --logo-border-color is given]
+    #[arg(long, requires = "logo", value_parser = clap::value_parser!(u32).range(1..))]
+    logo_border_width: Option<u32>,
+
+    /// Photo to show through the light modules; only dark modules are drawn on top.
+    /// Scanning depends heavily on the photo, so use -e H and --verify
+    #[arg(long)]
+    background_image: Option<PathBuf>,
+
+    /// Blend dark module edges into the background photo with ordered dithering,
+    /// keeping module centers solid
+    #[arg(long, requires = "background_image")]
+    dither: bool,
+
+    /// Image to stamp in place of every dark module outside the finder patterns,
+    /// scaled to the module size (raster output only)
+    #[arg(long, conflicts_with_all = ["background_image", "palette", "module_gap"])]
+    module_image: Option<PathBuf>,
+
+    /// Rotate the finished image clockwise by this many degrees
+    #[arg(long, value_parser = ["0", "90", "180", "270"], default_value = "0")]
+    rotate: String,
+
+    /// Mirror the finished image
+    #[arg(long, value_parser = ["none", "horizontal", "vertical"], default_value = "none")]
+    flip: String,
+
+    /// Round the image corners to transparent with this radius in pixels (PNG only)
+    #[arg(long, default_value = "0")]
+    border_radius: u32,
+
+    /// Draw a rounded frame around the code with a labelled tab below it;
+    /// "scan-me" gives "SCAN ME", anything else is used as the tab text
+    #[arg(long, conflicts_with_all = ["code_only", "border_radius"])]
+    brand_frame: Option<String>,
+
+    /// Color of the --brand-frame band and tab [default: the fill color]
+    #[arg(long, requires = "brand_frame")]
+    frame_color: Option<String>,
+
+    /// Color of the --brand-frame tab text [default: the back color]
+    #[arg(long, requires = "brand_frame")]
+    frame_text_color: Option<String>,
+
+    /// TrueType or OpenType font for --logo-caption and --brand-frame text, in
+    /// place of the built-in pixel font
+    #[arg(long)]
+    font: Option<PathBuf>,
+
+    /// Pad the canvas to a square when layers are stacked above or below the
+    /// code; "off" keeps it only as tall and wide as the layers need
+    #[arg(long, value_parser = ["on", "off"], default_value = "on")]
+    square_canvas: String,
+
     /// Use a template for specific content types
-    #[arg(long, value_parser = ["wifi", "vcard", "sms", "email", "phone"])]
+    #[arg(long, value_parser = template_names())]
     template: Option<String>,
+
+    /// Show the fields, rules and an example for one template, then exit
+    #[arg(long, value_parser = template_names())]
+    template_help: Option<String>,
+
+    /// Character separating template fields; use "tab" for a tab
+    #[arg(long, default_value = ",", value_parser = parse_separator)]
+    separator: char,
+
+    /// Give up on an interactive template prompt after this many seconds
+    #[arg(long, value_parser = parse_seconds)]
+    prompt_timeout: Option<Duration>,
+
+    /// With --template wifi, reject encryption types other than WPA, WEP and
+    /// nopass (WPA2 and WPA3 count as WPA) instead of falling back to WPA
+    #[arg(long, requires = "template")]
+    strict_wifi_encryption: bool,
+
+    /// With --template wifi, mark the network as hidden (adds `H:true`)
+    #[arg(long, requires = "template")]
+    wifi_hidden: bool,
+
+    /// vCard format for --template vcard
+    #[arg(long, value_parser = ["3.0", "4.0"], default_value = "3.0")]
+    vcard_version: String,
+
+    /// SMS payload form for --template sms: "smsto" (SMSTO:number:message) or
+    /// "uri" (sms:number?body=message, percent-encoded)
+    #[arg(long, value_parser = ["smsto", "uri"], default_value = "smsto")]
+    sms_format: String,
+
+    /// With --template calendar, also write the event as an .ics file here
+    #[arg(long, requires = "template", conflicts_with_all = ["batch", "from_lines", "count"])]
+    also_ics: Option<PathBuf>,
+
+    /// Keep whitespace around template fields (e.g. a WiFi password with leading spaces)
+    #[arg(long)]
+    no_trim_fields: bool,
+
+    /// Base64-decode the data and encode the raw bytes
+    #[arg(long, conflicts_with_all = ["template", "data_prefix", "data_suffix", "wrap_data_uri", "input_encoding", "normalize", "strip_control_chars", "clip_data"])]
+    data_base64: bool,
+
+    /// Text prepended to the data (applied after any template)
+    #[arg(long)]
+    data_prefix: Option<String>,
+
+    /// Text appended to the data (applied after any template), e.g. "?utm_source=qr"
+    #[arg(long)]
+    data_suffix: Option<String>,
+
+    /// Wrap the data (after any template) in a base64 data: URI with this
+    /// MIME type, e.g. text/vcard
+    #[arg(long, value_parser = parse_mime_type)]
+    wrap_data_uri: Option<String>,
+
+    /// Compression for TIFF output
+    #[arg(long, value_parser = ["none", "lzw", "deflate"], default_value = "none")]
+    tiff_compression: String,
+
+    /// Byte encoding used for the data inside the QR code; latin1 is strict
+    /// ISO-8859-1, so characters such as € are an error
+    #[arg(long, value_parser = ["utf8", "latin1", "shift-jis"], default_value = "utf8")]
+    input_encoding: String,
+
+    /// Unicode normalization applied to the payload text, so equivalent
+    /// accented characters always encode to the same bytes
+    #[arg(long, value_parser = ["nfc", "nfd", "none"], default_value = "nfc")]
+    normalize: String,
+
+    /// Remove control characters and invisible formatting characters such as
+    /// a BOM or zero-width space from the data; tabs and line breaks are kept
+    #[arg(long)]
+    strip_control_chars: bool,
+
+    /// ECI designator to prepend so readers know the byte encoding (e.g. 26 for UTF-8, 3 for Latin-1, 20 for Shift-JIS)
+    #[arg(long)]
+    eci: Option<u32>,
+
+    /// Search for the cheapest mix of numeric, alphanumeric and byte segments,
+    /// which can fit mixed payloads into a smaller code
+    #[arg(long)]
+    optimize_segments: bool,
+
+    /// Decode the generated image and refuse to save it unless it reads back correctly
+    #[arg(long)]
+    verify: bool,
+
+    /// Print a JSON report of the encoding mode, version and capacity used to
+    /// stderr, or write it to a file with --encoding-report=<FILE>
+    #[arg(long, num_args = 0..=1, require_equals = true)]
+    encoding_report: Option<Option<PathBuf>>,
+
+    /// Print the final bitstream (data and error correction codewords) as hex and binary to stderr
+    #[arg(long)]
+    dump_bits: bool,
+
+    /// Print the module grid to stderr as rows of 1 (dark) and 0 (light), after
+    /// a header with the version and width
+    #[arg(long)]
+    show_matrix: bool,
+
+    /// Draw data modules in blue and error correction modules in red to show
+    /// how much of the symbol is redundancy; the result is not meant to scan
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "logo",
+            "background_image",
+            "back_gradient",
+            "palette",
+            "module_image",
+            "finder_shape",
+            "brand_frame",
+            "verify",
+        ]
+    )]
+    visualize_ec: bool,
+
+    /// Print a JSON description of each file written (path, format, version,
+    /// width_px, ec_level, bytes) to stdout instead of the "saved to" line;
+    /// batch modes and --emit with several formats print an array
+    #[arg(long, conflicts_with_all = ["terminal", "copy"])]
+    json_output: bool,
+
+    /// Append a line per generated code to this file: a timestamp, the SHA-256 of
+    /// the data (never the data itself), output path, version, error correction
+    /// and dimensions
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// Write a manifest of every code generated in this run, with its path,
+    /// payload and SHA-256: CSV for .csv, JSON lines for .jsonl or .ndjson,
+    /// otherwise a JSON array
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
+    /// Record the qrgen version, data length, error correction and QR version in a PNG tEXt chunk
+    #[arg(long)]
+    embed_metadata: bool,
+
+    /// Tag PNG output as sRGB so color-managed apps and print workflows show
+    /// the fill and back colors as specified
+    #[arg(long)]
+    embed_srgb: bool,
+
+    /// Write PNGs with fixed encoder settings so the same inputs always give
+    /// byte-identical files, for pipelines that hash their outputs
+    #[arg(long, alias = "deterministic-png")]
+    reproducible: bool,
+
+    /// Use the text on the system clipboard as the data
+    #[arg(long, conflicts_with_all = ["data", "batch", "from_lines"])]
+    from_clipboard: bool,
+
+    /// Copy the image to the system clipboard; a file is written too only if
+    /// --output is given
+    #[arg(long, conflicts_with_all = ["batch", "from_lines"])]
+    copy: bool,
+
+    /// Check the input through the template and payload steps without writing
+    /// anything; batch modes report each row, and any failure exits non-zero
+    #[arg(long, conflicts_with_all = ["terminal", "copy", "preview"])]
+    validate_only: bool,
+
+    /// Open the saved image in the default viewer (skipped when not interactive)
+    #[arg(long, conflicts_with_all = ["batch", "from_lines", "count"])]
+    preview: bool,
+
+    /// Gap between dark modules as a fraction of the module size (0.0-0.5)
+    #[arg(long, default_value = "0.0")]
+    module_gap: f32,
+
+    /// Shape of the three corner finder patterns; anything but square may not
+    /// scan on every reader
+    #[arg(
+        long,
+        value_parser = ["square", "rounded", "circle", "leaf"],
+        default_value = "square",
+        conflicts_with_all = ["background_image", "module_image"]
+    )]
+    finder_shape: String,
+
+    /// Merge horizontal runs of dark modules into single rectangles in SVG output
+    #[arg(long)]
+    svg_optimize: bool,
+
+    /// Style SVG output with CSS classes (`qr-module`, `qr-background`) and a
+    /// `<style>` block instead of inline fills
+    #[arg(long)]
+    svg_css: bool,
+
+    /// Background of SVG output: a rect in the back color, or "none" to leave
+    /// light modules transparent
+    #[arg(long, value_parser = ["back-color", "none"], default_value = "back-color")]
+    svg_background: String,
+
+    /// Truncate data that doesn't fit in the largest QR code instead of failing
+    #[arg(long)]
+    clip_data: bool,
+
+    /// Fail before encoding if the payload, after templates and affixes, is
+    /// longer than this many bytes
+    #[arg(long, alias = "payload-max-bytes", value_parser = clap::value_parser!(u64).range(1..))]
+    max_payload_bytes: Option<u64>,
+
+    /// Barcode symbology to generate; Data Matrix supports raster output only
+    #[arg(long, value_parser = ["qr", "datamatrix"], default_value = "qr")]
+    symbology: String,
+
+    /// Refuse to generate if a URL payload (one starting with `scheme://`) is
+    /// malformed, or if --shorten can't shorten it
+    #[arg(long)]
+    strict: bool,
+
+    /// Encode a short URL from --shortener-url in place of a long http(s) URL
+    /// payload (needs qrgen built with the `net` feature)
+    #[arg(long, requires = "shortener_url")]
+    shorten: bool,
+
+    /// Shortener endpoint --shorten POSTs to, with {url} standing in for the
+    /// long URL; the reply body is the short URL
+    #[arg(long, value_parser = shorten::parse_endpoint)]
+    shortener_url: Option<String>,
+
+    /// Refuse to overwrite an existing output file; batch modes skip those rows instead
+    #[arg(long, conflicts_with = "force")]
+    no_clobber: bool,
+
+    /// Overwrite existing output files (the default)
+    #[arg(long)]
+    force: bool,
+
+    /// Insert the first 8 hex digits of the payload's SHA-256 before the file
+    /// extension, e.g. qr_code.a1b2c3d4.png, so names follow the content
+    #[arg(long)]
+    hash_suffix: bool,
+
+    /// Retry a failed write this many times, waiting 100 ms and doubling each
+    /// time, when the filesystem reports an I/O error (e.g. a network share)
+    #[arg(long, default_value = "0")]
+    write_retries: u32,
+
+    /// Generate a code even when the data is empty
+    #[arg(long)]
+    allow_empty: bool,
+
+    /// Don't print advisories about settings that may make the code hard to scan
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// CSV file with a header row and `data` (plus optional `filename`) columns;
+    /// generates one code per row into the --output directory
+    #[arg(long, group = "multi", group = "many", conflicts_with_all = ["data", "terminal"])]
+    batch: Option<PathBuf>,
+
+    /// Text file with one payload per line; blank lines and lines starting with
+    /// `#` are skipped. Generates one code per line into the --output directory
+    #[arg(long, group = "multi", group = "many", conflicts_with_all = ["data", "terminal"])]
+    from_lines: Option<PathBuf>,
+
+    /// Batch output filename pattern; `{column}` is replaced with that CSV column's
+    /// value, e.g. "code_{sku}.png" (overrides the `filename` column). With
+    /// --from-lines the fields are `{n}` and `{data}`
+    #[arg(long, requires = "multi")]
+    output_template: Option<String>,
+
+    /// Generate this many codes, replacing `{n}` in the data and --output with a
+    /// counter
+    #[arg(
+        long,
+        group = "many",
+        conflicts_with_all = ["batch", "from_lines", "terminal", "copy"]
+    )]
+    count: Option<u64>,
+
+    /// First counter value for --count
+    #[arg(long, default_value = "1", requires = "count")]
+    start: u64,
+
+    /// Zero-pad the --count counter to this many digits
+    #[arg(long, default_value = "0", requires = "count")]
+    pad: usize,
+
+    /// Codes to generate at once with --batch, --from-lines or --count
+    /// [default: number of CPUs]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: Option<u32>,
+
+    /// With --batch, --from-lines or --count, list rows whose payload repeats an
+    /// earlier row's and stop before writing anything; =warn lists them and
+    /// carries on
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "error",
+        value_parser = ["error", "warn"],
+        requires = "many"
+    )]
+    verify_unique: Option<String>,
+}
+
+/// Values accepted by --template and --template-help
+fn template_names() -> clap::builder::PossibleValuesParser {
+    clap::builder::PossibleValuesParser::new(templates::TEMPLATES.iter().map(|info| info.name))
+}
+
+impl Cli {
+    /// Resolution to record in the output, if the user asked for print sizing
+    fn print_dpi(&self) -> Option<u32> {
+        self.dpi.or(self.physical_size.map(|_| DEFAULT_DPI))
+    }
+
+    fn field_options(&self) -> FieldOptions {
+        FieldOptions {
+            separator: self.separator,
+            trim: !self.no_trim_fields,
+        }
+    }
+
+    fn encode_opts(&self) -> EncodeOptions {
+        EncodeOptions {
+            eci: self.eci,
+            optimize_segments: self.optimize_segments,
+            min_version: self.min_version,
+        }
+    }
+
+    /// Level to encode `data` at, stepped down with --auto-downgrade-ec when the
+    /// chosen one can't hold it
+    fn error_correction_for(&self, opts: &RenderOptions, data: &[u8]) -> EcLevel {
+        let level = opts.error_correction.level_for(data, self.encode_opts());
+        if !self.auto_downgrade_ec {
+            return level;
+        }
+
+        let fitted = downgrade_error_correction(data, level, self.encode_opts());
+        if fitted != level {
+            eprintln!(
+                "Warning: data is too long for a QR code at error correction {:?}; using EC {:?}",
+                level, fitted
+            );
+        }
+        fitted
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Time in-memory generation across a range of QR versions without writing files
+    Benchmark(benchmark::BenchmarkArgs),
+    /// Decode the QR code in an existing image and generate it again with new options
+    Reencode(reencode::ReencodeArgs),
+}
+
+/// Error correction as requested on the command line
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EcChoice {
+    Level(EcLevel),
+    /// Highest level that still fits in the version the data needs at L
+    Auto,
+}
+
+impl EcChoice {
+    fn level_for(self, data: &[u8], encode_opts: EncodeOptions) -> EcLevel {
+        match self {
+            EcChoice::Level(level) => level,
+            EcChoice::Auto => auto_error_correction(data, encode_opts),
+        }
+    }
+}
+
+/// Back color of --code-only output, so light modules let the design show through
+const TRANSPARENT: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+/// File written when no --output is given
+const DEFAULT_OUTPUT: &str = "qr_code.png";
+
+/// Fill/background contrast below which scanning gets unreliable
+const MIN_CONTRAST_RATIO: f64 = 3.0;
+
+/// Contrast --auto-contrast adjusts colors up to, the WCAG level for normal text
+const AUTO_CONTRAST_RATIO: f64 = 4.5;
+
+/// Resolution assumed by --physical-size when --dpi isn't given
+const DEFAULT_DPI: u32 = 300;
+
+/// Quiet zone, in modules, that the QR spec requires: the default for
+/// --quiet-zone-modules
+const MIN_QUIET_ZONE: u32 = 4;
+
+/// Largest --module-gap accepted; beyond this modules shrink below half size
+const MAX_MODULE_GAP: f32 = 0.5;
+
+/// Built-in `--palette rainbow`, dark enough for contrast against white
+const RAINBOW: [Rgba<u8>; 6] = [
+    Rgba([198, 40, 40, 255]),
+    Rgba([211, 84, 0, 255]),
+    Rgba([46, 125, 50, 255]),
+    Rgba([21, 101, 192, 255]),
+    Rgba([57, 73, 171, 255]),
+    Rgba([123, 31, 162, 255]),
+];
+
+/// Image settings resolved once from the command line and shared by every code generated
+struct RenderOptions {
+    error_correction: EcChoice,
+    fill_color: Rgba<u8>,
+    /// Colors for dark modules from --palette; empty means just `fill_color`
+    palette: Vec<Rgba<u8>>,
+    back_color: Rgba<u8>,
+    /// Light area from --back-gradient, drawn in place of `back_color`
+    back_gradient: Option<gradient::Gradient>,
+    /// Quiet zone drawn with the code; 0 when `padding` adds it afterwards
+    border: u32,
+    /// Per-side quiet zone from --border-top etc. or --border-color, added
+    /// around the drawn code in `border_color`
+    padding: Option<layout::Sides>,
+    border_color: Rgba<u8>,
+    background: Option<DynamicImage>,
+    module_gap: f32,
+    /// Sprite from --module-image, at its original size
+    module_image: Option<RgbaImage>,
+    finder_shape: finder::Shape,
+    /// Frame and tab from --brand-frame, drawn around the quiet zone
+    frame: Option<frame::Frame>,
+    /// Typeface for the logo caption and frame text
+    font: text::Font,
+    /// Color of the ring around the logo box, if one is drawn
+    logo_border_color: Option<Rgba<u8>>,
+}
+
+impl RenderOptions {
+    /// Colors to paint dark modules with, picked per module by `palette_color`
+    fn fill(&self) -> &[Rgba<u8>] {
+        if self.palette.is_empty() {
+            std::slice::from_ref(&self.fill_color)
+        } else {
+            &self.palette
+        }
+    }
+
+    /// Narrowest quiet zone on any side of the finished code, in modules
+    fn quiet_zone(&self) -> u32 {
+        self.padding.map_or(self.border, |sides| sides.min())
+    }
+
+    /// Quiet zone added to the code's width, left and right together
+    fn horizontal_border(&self) -> u32 {
+        self.padding
+            .map_or(2 * self.border, |sides| sides.left + sides.right)
+    }
+
+    /// Quiet zone added to the code's height, top and bottom together
+    fn vertical_border(&self) -> u32 {
+        self.padding
+            .map_or(2 * self.border, |sides| sides.top + sides.bottom)
+    }
+
+    /// Width in pixels of the ring around the logo box; 0 without one
+    fn logo_ring_width(&self, cli: &Cli) -> u32 {
+        match self.logo_border_color {
+            Some(_) => cli.logo_border_width.unwrap_or(cli.size),
+            None => 0,
+        }
+    }
 }
 
 fn parse_error_correction(s: &str) -> Result<EcLevel> {
@@ -72,6 +817,169 @@ fn parse_error_correction(s: &str) -> Result<EcLevel> {
     }
 }
 
+fn parse_ec_choice(s: &str) -> Result<EcChoice> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(EcChoice::Auto);
+    }
+    parse_error_correction(s).map(EcChoice::Level)
+}
+
+/// Parse a positive number of seconds, fractions allowed
+fn parse_seconds(s: &str) -> Result<Duration> {
+    let seconds: f64 = s
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid number of seconds: {}", s))?;
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| anyhow::anyhow!("Timeout must be a positive number of seconds"))
+}
+
+fn parse_separator(s: &str) -> Result<char> {
+    if s.eq_ignore_ascii_case("tab") || s == "\\t" {
+        return Ok('\t');
+    }
+
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(anyhow::anyhow!(
+            "Separator must be a single character or \"tab\", got {:?}",
+            s
+        )),
+    }
+}
+
+/// Parse a bare `type/subtype` MIME type; parameters are not supported
+fn parse_mime_type(s: &str) -> Result<String> {
+    let valid = s.split_once('/').is_some_and(|(kind, subtype)| {
+        !kind.is_empty()
+            && !subtype.is_empty()
+            && !subtype.contains('/')
+            && !s.contains(|c: char| c.is_whitespace() || matches!(c, ';' | ','))
+    });
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "MIME type must look like type/subtype (e.g. text/vcard), got {:?}",
+            s
+        ));
+    }
+    Ok(s.to_string())
+}
+
+/// Border drawn around the code, in modules. Scanners rely on the quiet zone,
+/// so it's only dropped when asked explicitly, and a --border narrower than
+/// --quiet-zone-modules is widened to it.
+fn resolve_border(cli: &Cli) -> u32 {
+    if cli.code_only {
+        0
+    } else if cli.no_quiet_zone {
+        eprintln!("Warning: --no-quiet-zone removes the border; scanning may be degraded unless the surrounding layout provides a margin");
+        0
+    } else if cli.border < cli.quiet_zone_modules {
+        eprintln!(
+            "Warning: --border {} is narrower than the {}-box quiet zone scanners need; using {}",
+            cli.border, cli.quiet_zone_modules, cli.quiet_zone_modules
+        );
+        cli.quiet_zone_modules
+    } else {
+        cli.border
+    }
+}
+
+/// Quiet zone per side when --border-top etc. or --border-color are given,
+/// with unset sides falling back to `border`. Explicit sides must leave
+/// --quiet-zone-modules unless --no-quiet-zone is given.
+fn resolve_padding(cli: &Cli, border: u32) -> Result<Option<layout::Sides>> {
+    let explicit = [
+        ("--border-top", cli.border_top),
+        ("--border-right", cli.border_right),
+        ("--border-bottom", cli.border_bottom),
+        ("--border-left", cli.border_left),
+    ];
+    if cli.border_color.is_none() && explicit.iter().all(|(_, side)| side.is_none()) {
+        return Ok(None);
+    }
+
+    if !cli.no_quiet_zone {
+        if let Some((flag, Some(side))) = explicit
+            .iter()
+            .find(|(_, side)| side.is_some_and(|side| side < cli.quiet_zone_modules))
+        {
+            return Err(anyhow::anyhow!(
+                "{} {} is narrower than the {}-box quiet zone scanners need; use at least {} or pass --no-quiet-zone",
+                flag,
+                side,
+                cli.quiet_zone_modules,
+                cli.quiet_zone_modules
+            ));
+        }
+    }
+
+    Ok(Some(layout::Sides {
+        top: cli.border_top.unwrap_or(border),
+        right: cli.border_right.unwrap_or(border),
+        bottom: cli.border_bottom.unwrap_or(border),
+        left: cli.border_left.unwrap_or(border),
+    }))
+}
+
+/// Pick the error correction level: an explicit choice always wins, otherwise
+/// the template's default (or M), raised to Q with a logo so the covered
+/// modules can be recovered
+fn resolve_error_correction(
+    explicit: Option<EcChoice>,
+    has_logo: bool,
+    template: Option<&str>,
+) -> EcChoice {
+    if let Some(choice) = explicit {
+        return choice;
+    }
+
+    let level = template
+        .and_then(templates::default_error_correction)
+        .unwrap_or(EcLevel::M);
+    if has_logo {
+        EcChoice::Level(level.max(EcLevel::Q))
+    } else {
+        EcChoice::Level(level)
+    }
+}
+
+/// Spend any spare capacity on error correction: find the version the data
+/// needs at L, then the highest level that still fits in it
+fn auto_error_correction(data: &[u8], encode_opts: EncodeOptions) -> EcLevel {
+    let Ok(smallest) = encode_smallest(data, EcLevel::L, encode_opts) else {
+        // Too long even at L; let the real encode report the error
+        return EcLevel::L;
+    };
+
+    [EcLevel::H, EcLevel::Q, EcLevel::M]
+        .into_iter()
+        .find(|&level| encode_at_version(data, smallest.version(), level, encode_opts).is_ok())
+        .unwrap_or(EcLevel::L)
+}
+
+/// The first of `level` and the levels below it that the data fits at, or
+/// `level` itself if even L is too small
+fn downgrade_error_correction(data: &[u8], level: EcLevel, encode_opts: EncodeOptions) -> EcLevel {
+    [EcLevel::H, EcLevel::Q, EcLevel::M, EcLevel::L]
+        .into_iter()
+        .skip_while(|&candidate| candidate != level)
+        .find(|&candidate| encode_smallest(data, candidate, encode_opts).is_ok())
+        .unwrap_or(level)
+}
+
+/// Approximate share of codewords each level can restore
+fn ec_recovery_fraction(level: EcLevel) -> f64 {
+    match level {
+        EcLevel::L => 0.07,
+        EcLevel::M => 0.15,
+        EcLevel::Q => 0.25,
+        EcLevel::H => 0.30,
+    }
+}
+
 fn parse_color(color_str: &str) -> Result<Rgba<u8>> {
     // Handle hex colors
     if color_str.starts_with('#') {
@@ -82,6 +990,13 @@ fn parse_color(color_str: &str) -> Result<Rgba<u8>> {
             let b = u8::from_str_radix(&hex[4..6], 16)?;
             return Ok(Rgba([r, g, b, 255]));
         }
+        // CSS shorthand: #abc expands to #aabbcc
+        if hex.len() == 3 && hex.is_ascii() {
+            let r = u8::from_str_radix(&hex[0..1], 16)? * 0x11;
+            let g = u8::from_str_radix(&hex[1..2], 16)? * 0x11;
+            let b = u8::from_str_radix(&hex[2..3], 16)? * 0x11;
+            return Ok(Rgba([r, g, b, 255]));
+        }
     }
 
     // Handle named colors
@@ -102,41 +1017,699 @@ fn parse_color(color_str: &str) -> Result<Rgba<u8>> {
     }
 }
 
-fn create_qr_code(
-    data: &str,
-    error_correction: EcLevel,
+/// Load `--palette`: the built-in `rainbow` or a file with one color per line.
+/// Every color must keep enough contrast against the back color to scan.
+fn load_palette(spec: &str, back_color: Rgba<u8>) -> Result<Vec<Rgba<u8>>> {
+    let palette = if spec == "rainbow" {
+        RAINBOW.to_vec()
+    } else {
+        let contents = std::fs::read_to_string(spec)
+            .with_context(|| format!("Failed to read palette file {}", spec))?;
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                parse_color(line).with_context(|| format!("Invalid palette color: {}", line))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    if palette.is_empty() {
+        return Err(anyhow::anyhow!("Palette {} has no colors", spec));
+    }
+    for &color in &palette {
+        let ratio = contrast_ratio(color, back_color);
+        if ratio < MIN_CONTRAST_RATIO {
+            return Err(anyhow::anyhow!(
+                "Palette color #{:02x}{:02x}{:02x} has too little contrast against the background ({:.1}:1)",
+                color[0],
+                color[1],
+                color[2],
+                ratio
+            ));
+        }
+    }
+    Ok(palette)
+}
+
+/// Fill color for the dark module at (x, y): the palette is spread in
+/// diagonal bands from the top-left corner to the bottom-right
+fn palette_color(palette: &[Rgba<u8>], x: u32, y: u32, qr_width: u32) -> Rgba<u8> {
+    let n = palette.len() as u32;
+    let band = (x + y) * n / (2 * qr_width - 1);
+    palette[band.min(n - 1) as usize]
+}
+
+fn draw_qr_code(
+    code: &QrCode,
     size: u32,
     border: u32,
-    fill_color: Rgba<u8>,
+    fill: &[Rgba<u8>],
     back_color: Rgba<u8>,
-) -> Result<RgbaImage> {
-    let code = QrCode::with_error_correction_level(data, error_correction)
-        .context("Failed to generate QR code")?;
-
-    let qr_width = code.width() as u32;
-    let img_size = (qr_width + 2 * border) * size;
+    module_gap: f32,
+) -> RgbaImage {
+    let img_size = (code.width() as u32 + 2 * border) * size;
 
     let mut img = RgbaImage::from_pixel(img_size, img_size, back_color);
+    draw_modules(&mut img, code, size, border, fill, module_gap);
 
-    for y in 0..qr_width {
-        for x in 0..qr_width {
-            if code[(x as usize, y as usize)] == Color::Dark {
-                let px = (x + border) * size;
-                let py = (y + border) * size;
+    img
+}
 
-                for dy in 0..size {
-                    for dx in 0..size {
-                        img.put_pixel(px + dx, py + dy, fill_color);
+/// Draw only the dark modules over a photo scaled to fill the canvas, so the
+/// photo shows through the light modules and quiet zone
+fn draw_on_background(
+    code: &QrCode,
+    size: u32,
+    border: u32,
+    fill: &[Rgba<u8>],
+    background: &DynamicImage,
+    module_gap: f32,
+    dither: bool,
+) -> RgbaImage {
+    let img_size = (code.width() as u32 + 2 * border) * size;
+
+    let photo = background
+        .resize_to_fill(img_size, img_size, image::imageops::FilterType::Lanczos3)
+        .to_rgba8();
+    let mut img = photo.clone();
+    draw_modules(&mut img, code, size, border, fill, module_gap);
+    if dither {
+        dither_module_edges(&mut img, &photo, code, size, border);
+    }
+
+    img
+}
+
+/// Draw the dark modules over `gradient`, which fills the light modules and
+/// the quiet zone
+fn draw_on_gradient(
+    code: &QrCode,
+    size: u32,
+    border: u32,
+    fill: &[Rgba<u8>],
+    gradient: &gradient::Gradient,
+    module_gap: f32,
+) -> RgbaImage {
+    let img_size = (code.width() as u32 + 2 * border) * size;
+
+    let mut img = gradient.image(img_size, img_size);
+    draw_modules(&mut img, code, size, border, fill, module_gap);
+
+    img
+}
+
+/// 4x4 Bayer matrix for ordered dithering, values 0-15
+const BAYER_4X4: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Let the photo back through the outer quarter of each dark data module in
+/// an ordered-dither pattern that thins out towards the module edge. Centers
+/// and function patterns (finders, timing, alignment) stay solid so scanners
+/// can still locate and sample the grid.
+fn dither_module_edges(
+    img: &mut RgbaImage,
+    photo: &RgbaImage,
+    code: &QrCode,
+    size: u32,
+    border: u32,
+) {
+    let qr_width = code.width() as u32;
+    let band = size / 4;
+    if band == 0 {
+        return;
+    }
+
+    for y in 0..qr_width {
+        for x in 0..qr_width {
+            if code[(x as usize, y as usize)] != Color::Dark
+                || code.is_functional(x as usize, y as usize)
+            {
+                continue;
+            }
+
+            let px = (x + border) * size;
+            let py = (y + border) * size;
+            for dy in 0..size {
+                for dx in 0..size {
+                    let edge = dx.min(dy).min(size - 1 - dx).min(size - 1 - dy);
+                    if edge >= band {
+                        continue;
+                    }
+
+                    // Coverage rises from about half at the edge towards solid
+                    let (ix, iy) = (px + dx, py + dy);
+                    let threshold = BAYER_4X4[(iy % 4) as usize][(ix % 4) as usize];
+                    if threshold * (2 * band + 1) >= (band + edge + 1) * 16 {
+                        img.put_pixel(ix, iy, *photo.get_pixel(ix, iy));
                     }
                 }
             }
         }
     }
+}
 
-    Ok(img)
+/// Whether the module is part of one of the three 7x7 finder patterns
+fn in_finder_pattern(x: u32, y: u32, qr_width: u32) -> bool {
+    let near = |v: u32| v < 7;
+    let far = |v: u32| v >= qr_width - 7;
+    (near(x) && near(y)) || (far(x) && near(y)) || (near(x) && far(y))
+}
+
+/// Paint each dark module, inset on every side by half of `module_gap` (a
+/// fraction of the module size) so neighbouring modules are separated. Finder
+/// patterns stay solid since scanners locate the code by their run lengths.
+/// With more than one fill color, each module takes its `palette_color`.
+fn draw_modules(
+    img: &mut RgbaImage,
+    code: &QrCode,
+    size: u32,
+    border: u32,
+    fill: &[Rgba<u8>],
+    module_gap: f32,
+) {
+    let qr_width = code.width() as u32;
+    let inset = ((size as f32 * module_gap / 2.0).round() as u32).min(size / 2);
+
+    for y in 0..qr_width {
+        for x in 0..qr_width {
+            if code[(x as usize, y as usize)] == Color::Dark {
+                let inset = if in_finder_pattern(x, y, qr_width) {
+                    0
+                } else {
+                    inset
+                };
+                let color = palette_color(fill, x, y, qr_width);
+                fill_module(img, x, y, size, border, inset, color);
+            }
+        }
+    }
+}
+
+/// Draw the code with `sprite`, scaled once to the module size, stamped on
+/// each dark module over the back color. Finder patterns keep a solid
+/// `fill_color` so scanners can still find the code.
+fn draw_module_images(
+    code: &QrCode,
+    size: u32,
+    border: u32,
+    fill_color: Rgba<u8>,
+    back_color: Rgba<u8>,
+    sprite: &RgbaImage,
+) -> RgbaImage {
+    let qr_width = code.width() as u32;
+    let img_size = (qr_width + 2 * border) * size;
+    let sprite = image::imageops::resize(sprite, size, size, image::imageops::FilterType::Lanczos3);
+
+    let mut img = RgbaImage::from_pixel(img_size, img_size, back_color);
+
+    for y in 0..qr_width {
+        for x in 0..qr_width {
+            if code[(x as usize, y as usize)] != Color::Dark {
+                continue;
+            }
+            if in_finder_pattern(x, y, qr_width) {
+                fill_module(&mut img, x, y, size, border, 0, fill_color);
+            } else {
+                let px = (x + border) * size;
+                let py = (y + border) * size;
+                image::imageops::overlay(&mut img, &sprite, px as i64, py as i64);
+            }
+        }
+    }
+
+    img
+}
+
+/// Paint the module at grid position (x, y), leaving `inset` pixels of each
+/// side unpainted
+fn fill_module(
+    img: &mut RgbaImage,
+    x: u32,
+    y: u32,
+    size: u32,
+    border: u32,
+    inset: u32,
+    color: Rgba<u8>,
+) {
+    let px = (x + border) * size;
+    let py = (y + border) * size;
+
+    for dy in inset..size - inset {
+        for dx in inset..size - inset {
+            img.put_pixel(px + dx, py + dy, color);
+        }
+    }
+}
+
+/// Pixel scale of a logo caption, sized to the logo's white box
+fn caption_scale(logo_bg_size: u32) -> u32 {
+    (logo_bg_size / 40).max(1)
+}
+
+/// Width and height of the white box `embed_logo` draws in an `img_size`
+/// image: a square around the logo, widened and extended downwards to fit
+/// any caption
+fn logo_box_size(img_size: u32, caption: Option<&str>, font: &text::Font) -> (u32, u32) {
+    // Logo should be about 1/5 of QR code size
+    let logo_size = img_size / 5;
+    let logo_bg_size = (logo_size as f32 * 1.2) as u32;
+
+    match caption {
+        Some(caption) => {
+            let scale = caption_scale(logo_bg_size);
+            let (text_width, text_height) = font.text_size(caption, scale);
+            let padding = 2 * scale;
+            (
+                logo_bg_size.max(text_width + 2 * padding),
+                logo_bg_size + text_height + padding,
+            )
+        }
+        None => (logo_bg_size, logo_bg_size),
+    }
+}
+
+/// Fraction of the code area hidden behind the box `embed_logo` draws, and
+/// the `ring` pixels around it, for a code `qr_width` modules wide rendered
+/// with the given border and module size
+fn logo_coverage_fraction(
+    qr_width: u32,
+    border: u32,
+    size: u32,
+    caption: Option<&str>,
+    font: &text::Font,
+    ring: u32,
+) -> f64 {
+    let img_size = (qr_width + 2 * border) * size;
+    let (box_width, box_height) = logo_box_size(img_size, caption, font);
+    let (box_width, box_height) = (box_width + 2 * ring, box_height + 2 * ring);
+    let code_size = qr_width * size;
+
+    (box_width as f64 * box_height as f64 / (code_size as f64).powi(2)).min(1.0)
+}
+
+/// `logo_coverage_fraction` for the logo, caption and ring on the command line
+fn logo_coverage(cli: &Cli, opts: &RenderOptions, qr_width: u32) -> f64 {
+    logo_coverage_fraction(
+        qr_width,
+        opts.border,
+        cli.size,
+        cli.logo_caption.as_deref(),
+        &opts.font,
+        opts.logo_ring_width(cli),
+    )
+}
+
+/// Error out if the logo box alone is recoverable at `level` but its ring
+/// pushes the hidden area past what error correction can restore
+fn check_logo_ring(cli: &Cli, opts: &RenderOptions, qr_width: u32, level: EcLevel) -> Result<()> {
+    let ring = opts.logo_ring_width(cli);
+    if ring == 0 {
+        return Ok(());
+    }
+
+    let recoverable = ec_recovery_fraction(level);
+    let without_ring = logo_coverage_fraction(
+        qr_width,
+        opts.border,
+        cli.size,
+        cli.logo_caption.as_deref(),
+        &opts.font,
+        0,
+    );
+    let with_ring = logo_coverage(cli, opts, qr_width);
+    if without_ring <= recoverable && with_ring > recoverable {
+        return Err(anyhow::anyhow!(
+            "A {} px logo border would hide about {:.0}% of the code, more than error correction {:?} recovers (about {:.0}%); use a narrower --logo-border-width or a higher -e",
+            ring,
+            with_ring * 100.0,
+            level,
+            recoverable * 100.0
+        ));
+    }
+    Ok(())
+}
+
+/// Encode at a fixed version, optionally starting with an ECI designator
+fn encode_at_version(
+    data: &[u8],
+    version: Version,
+    error_correction: EcLevel,
+    encode_opts: EncodeOptions,
+) -> Result<QrCode, QrError> {
+    let mut bits = Bits::new(version);
+    segments::push_data(&mut bits, data, encode_opts)?;
+    bits.push_terminator(error_correction)?;
+    QrCode::with_bits(bits, error_correction)
+}
+
+/// Encode in the smallest version the data fits, starting from the minimum
+/// version if one is set
+fn encode_smallest(
+    data: &[u8],
+    error_correction: EcLevel,
+    encode_opts: EncodeOptions,
+) -> Result<QrCode, QrError> {
+    for v in encode_opts.min_version.unwrap_or(1)..=40 {
+        match encode_at_version(data, Version::Normal(v), error_correction, encode_opts) {
+            Err(QrError::DataTooLong) => continue,
+            result => return result,
+        }
+    }
+    Err(QrError::DataTooLong)
+}
+
+/// Error out if a logo hiding `coverage` of the code is more than `level` can
+/// recover
+fn check_logo_coverage(coverage: f64, level: EcLevel) -> Result<()> {
+    let recoverable = ec_recovery_fraction(level);
+    if coverage > recoverable {
+        return Err(anyhow::anyhow!(
+            "Logo covers about {:.0}% of the code but error correction {:?} only recovers about {:.0}%",
+            coverage * 100.0,
+            level,
+            recoverable * 100.0
+        ));
+    }
+    Ok(())
+}
+
+/// Lowest error correction level whose smallest code can recover the modules
+/// behind the logo, or H if none can
+fn plan_logo_error_correction(cli: &Cli, opts: &RenderOptions, data: &[u8]) -> EcLevel {
+    [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H]
+        .into_iter()
+        .find(|&level| {
+            encode_smallest(data, level, cli.encode_opts()).is_ok_and(|code| {
+                let coverage = logo_coverage(cli, opts, code.width() as u32);
+                check_logo_coverage(coverage, level).is_ok()
+            })
+        })
+        .unwrap_or(EcLevel::H)
+}
+
+fn encode_data(cli: &Cli, opts: &RenderOptions, data: &[u8]) -> Result<QrCode> {
+    // An explicit level is kept (and checked below); otherwise plan around the logo
+    let error_correction = match cli.error_correction {
+        None | Some(EcChoice::Auto) if cli.center_logo_exclusion => {
+            plan_logo_error_correction(cli, opts, data)
+        }
+        _ => cli.error_correction_for(opts, data),
+    };
+    let code = encode_for_logo(cli, opts, data, error_correction)?;
+
+    if cli.center_logo_exclusion {
+        let coverage = logo_coverage(cli, opts, code.width() as u32);
+        check_logo_coverage(coverage, error_correction)?;
+    }
+    if cli.logo.is_some() {
+        check_logo_ring(cli, opts, code.width() as u32, error_correction)?;
+    }
+
+    if !cli.quiet {
+        let settings = ScanSettings {
+            fill_color: opts.fill_color,
+            // Judge a gradient by its weakest point against the fill
+            back_color: opts.back_gradient.map_or(opts.back_color, |gradient| {
+                gradient.least_contrast(opts.fill_color)
+            }),
+            error_correction,
+            logo_coverage: cli
+                .logo
+                .as_ref()
+                .map(|_| logo_coverage(cli, opts, code.width() as u32)),
+            module_gap: opts.module_gap,
+            background_image: opts.background.is_some(),
+            module_image: opts.module_image.is_some(),
+            styled_finders: opts.finder_shape != finder::Shape::Square,
+        };
+        scannability::print_warnings(scannability::scannability_warnings(&settings));
+    }
+
+    Ok(code)
+}
+
+/// Encode in the smallest version, or with --auto-version the smallest one
+/// where the logo hides no more than the error correction can recover
+fn encode_for_logo(
+    cli: &Cli,
+    opts: &RenderOptions,
+    data: &[u8],
+    error_correction: EcLevel,
+) -> Result<QrCode> {
+    let code = encode_smallest(data, error_correction, cli.encode_opts())
+        .context("Failed to generate QR code")?;
+
+    if cli.logo.is_none() || !cli.auto_version {
+        return Ok(code);
+    }
+
+    let recoverable = ec_recovery_fraction(error_correction);
+    let coverage = |width: usize| logo_coverage(cli, opts, width as u32);
+    if coverage(code.width()) <= recoverable {
+        return Ok(code);
+    }
+
+    // Larger versions shrink the logo relative to the module grid
+    if let Version::Normal(natural) = code.version() {
+        for v in natural + 1..=40 {
+            let version = Version::Normal(v);
+            if coverage(version.width() as usize) <= recoverable {
+                return encode_at_version(data, version, error_correction, cli.encode_opts())
+                    .context("Failed to generate QR code");
+            }
+        }
+    }
+
+    Ok(code)
 }
 
-fn embed_logo(mut qr_img: RgbaImage, logo_path: &PathBuf) -> Result<RgbaImage> {
+/// Most common color among the logo's visible pixels, ignoring near-white
+/// background. Colors are bucketed coarsely and the winning bucket averaged.
+fn dominant_color(img: &RgbaImage) -> Rgba<u8> {
+    let mut buckets: std::collections::HashMap<[u8; 3], (u64, [u64; 3])> =
+        std::collections::HashMap::new();
+
+    for pixel in img.pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a < 128 || (r > 230 && g > 230 && b > 230) {
+            continue;
+        }
+        let entry = buckets.entry([r >> 5, g >> 5, b >> 5]).or_default();
+        entry.0 += 1;
+        entry.1[0] += r as u64;
+        entry.1[1] += g as u64;
+        entry.1[2] += b as u64;
+    }
+
+    // An all-white or transparent logo has nothing to sample
+    let Some((count, sums)) = buckets.into_values().max_by_key(|(count, _)| *count) else {
+        return Rgba([0, 0, 0, 255]);
+    };
+    Rgba([
+        (sums[0] / count) as u8,
+        (sums[1] / count) as u8,
+        (sums[2] / count) as u8,
+        255,
+    ])
+}
+
+/// WCAG relative luminance, from 0.0 (black) to 1.0 (white)
+fn luminance(color: Rgba<u8>) -> f64 {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(color[0]) + 0.7152 * channel(color[1]) + 0.0722 * channel(color[2])
+}
+
+/// WCAG contrast ratio between two colors, from 1.0 (identical) to 21.0
+fn contrast_ratio(a: Rgba<u8>, b: Rgba<u8>) -> f64 {
+    let (la, lb) = (luminance(a), luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// Push `fill` and `back` apart until they contrast by at least `target`:
+/// the darker color is scaled towards black and the lighter one mixed
+/// towards white in equal small steps, which keeps each color's hue. Pairs
+/// that already contrast enough come back unchanged.
+fn adjust_contrast(fill: Rgba<u8>, back: Rgba<u8>, target: f64) -> (Rgba<u8>, Rgba<u8>) {
+    const STEPS: u32 = 100;
+
+    let fill_is_dark = luminance(fill) <= luminance(back);
+    let (dark, light) = if fill_is_dark {
+        (fill, back)
+    } else {
+        (back, fill)
+    };
+    let shade = |c: u8, t: f64| (c as f64 * (1.0 - t)).round() as u8;
+    let tint = |c: u8, t: f64| (c as f64 + (255.0 - c as f64) * t).round() as u8;
+
+    let mut adjusted = (dark, light);
+    for step in 0..=STEPS {
+        let t = step as f64 / STEPS as f64;
+        adjusted = (
+            Rgba([
+                shade(dark[0], t),
+                shade(dark[1], t),
+                shade(dark[2], t),
+                dark[3],
+            ]),
+            Rgba([
+                tint(light[0], t),
+                tint(light[1], t),
+                tint(light[2], t),
+                light[3],
+            ]),
+        );
+        if contrast_ratio(adjusted.0, adjusted.1) >= target {
+            break;
+        }
+    }
+
+    if fill_is_dark {
+        adjusted
+    } else {
+        (adjusted.1, adjusted.0)
+    }
+}
+
+/// Ramp the alpha of the outer `feather` pixels of the box from nearly clear
+/// at the edge to opaque
+fn feather_edges(img: &mut RgbaImage, feather: u32) {
+    if feather == 0 {
+        return;
+    }
+
+    let (width, height) = img.dimensions();
+    for y in 0..height {
+        for x in 0..width {
+            let edge_distance = x.min(y).min(width - 1 - x).min(height - 1 - y);
+            if edge_distance < feather {
+                let alpha = (edge_distance as f32 + 0.5) / feather as f32;
+                let pixel = img.get_pixel_mut(x, y);
+                pixel[3] = (pixel[3] as f32 * alpha).round() as u8;
+            }
+        }
+    }
+}
+
+/// Clear everything outside the circle inscribed in the image, fading the
+/// outer `feather` pixels of the circle
+fn clip_to_circle(img: &mut RgbaImage, feather: u32) {
+    let (width, height) = img.dimensions();
+    let radius = width.min(height) as f32 / 2.0;
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as f32 + 0.5 - cx;
+            let dy = y as f32 + 0.5 - cy;
+            let inside = radius - (dx * dx + dy * dy).sqrt();
+
+            let alpha = if feather == 0 {
+                if inside >= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            } else {
+                (inside / feather as f32).clamp(0.0, 1.0)
+            };
+            let pixel = img.get_pixel_mut(x, y);
+            pixel[3] = (pixel[3] as f32 * alpha).round() as u8;
+        }
+    }
+}
+
+/// Top-left corner of a logo box for `position`. Corner positions sit
+/// `inset` pixels in from both edges.
+fn logo_origin(
+    position: &str,
+    width: u32,
+    height: u32,
+    (box_width, box_height): (u32, u32),
+    inset: u32,
+) -> Result<(u32, u32)> {
+    let right = width.saturating_sub(inset + box_width);
+    let bottom = height.saturating_sub(inset + box_height);
+    match position {
+        "center" => Ok((
+            width.saturating_sub(box_width) / 2,
+            height.saturating_sub(box_height) / 2,
+        )),
+        "top-left" => Ok((inset, inset)),
+        "top-right" => Ok((right, inset)),
+        "bottom-left" => Ok((inset, bottom)),
+        "bottom-right" => Ok((right, bottom)),
+        _ => Err(anyhow::anyhow!("Invalid logo position: {}", position)),
+    }
+}
+
+/// Whether a box at (x, y) covers any of the three finder patterns of an
+/// image with the given quiet zone and module size
+fn overlaps_finder(
+    (x, y): (u32, u32),
+    (box_width, box_height): (u32, u32),
+    (width, height): (u32, u32),
+    quiet_zone_px: u32,
+    module_size: u32,
+) -> bool {
+    let finder = 7 * module_size;
+    let far_x = width.saturating_sub(quiet_zone_px + finder);
+    let far_y = height.saturating_sub(quiet_zone_px + finder);
+    let overlaps = |start: u32, end: u32, finder_start: u32| {
+        start < finder_start + finder && finder_start < end
+    };
+
+    [
+        (quiet_zone_px, quiet_zone_px),
+        (far_x, quiet_zone_px),
+        (quiet_zone_px, far_y),
+    ]
+    .iter()
+    .any(|&(fx, fy)| overlaps(x, x + box_width, fx) && overlaps(y, y + box_height, fy))
+}
+
+/// Paint the ring around the logo box: a `size` rectangle at `origin`, or the
+/// circle inscribed in it for circular logos. The box is pasted over the
+/// middle afterwards, leaving just the ring showing.
+fn draw_logo_ring(
+    img: &mut RgbaImage,
+    origin: (u32, u32),
+    size: (u32, u32),
+    shape: &str,
+    color: Rgba<u8>,
+) {
+    let mut ring = RgbaImage::from_pixel(size.0, size.1, color);
+    if shape == "circle" {
+        clip_to_circle(&mut ring, 0);
+    }
+    image::imageops::overlay(img, &ring, origin.0 as i64, origin.1 as i64);
+}
+
+/// How the logo and its white box are drawn
+struct LogoStyle<'a> {
+    feather: u32,
+    shape: &'a str,
+    position: &'a str,
+    caption: Option<&'a str>,
+    font: &'a text::Font,
+    /// Width in pixels and color of a ring drawn around the box
+    ring: Option<(u32, Rgba<u8>)>,
+}
+
+fn embed_logo(
+    mut qr_img: RgbaImage,
+    logo_path: &PathBuf,
+    style: &LogoStyle,
+    quiet_zone_px: u32,
+    module_size: u32,
+) -> Result<RgbaImage> {
     let logo = image::open(logo_path)
         .context("Failed to open logo image")?
         .to_rgba8();
@@ -148,221 +1721,2766 @@ fn embed_logo(mut qr_img: RgbaImage, logo_path: &PathBuf) -> Result<RgbaImage> {
     let logo_size = qr_width.min(qr_height) / 5;
 
     // Resize logo maintaining aspect ratio
-    let resized_logo = image::imageops::resize(
+    let mut resized_logo = image::imageops::resize(
         &logo,
         logo_size,
         logo_size,
         image::imageops::FilterType::Lanczos3,
     );
 
-    // Create white background for logo
+    // Create white background for logo, with room for the caption below it
     let logo_bg_size = (logo_size as f32 * 1.2) as u32;
-    let mut logo_bg = RgbaImage::from_pixel(logo_bg_size, logo_bg_size, Rgba([255, 255, 255, 255]));
+    let box_size = logo_box_size(qr_width.min(qr_height), style.caption, style.font);
+    if box_size.0 > qr_width || box_size.1 > qr_height {
+        return Err(anyhow::anyhow!(
+            "Logo caption is too long to fit in the code; shorten it or use a larger code"
+        ));
+    }
+    let mut logo_bg = RgbaImage::from_pixel(box_size.0, box_size.1, Rgba([255, 255, 255, 255]));
+    match (style.shape, style.caption) {
+        ("square", _) => feather_edges(&mut logo_bg, style.feather),
+        ("circle", None) => {
+            clip_to_circle(&mut resized_logo, 0);
+            clip_to_circle(&mut logo_bg, style.feather);
+        }
+        ("circle", Some(_)) => {
+            return Err(anyhow::anyhow!(
+                "--logo-caption only works with --logo-shape square"
+            ))
+        }
+        (shape, _) => return Err(anyhow::anyhow!("Invalid logo shape: {}", shape)),
+    }
 
-    // Paste logo onto white background
-    let logo_x = (logo_bg_size - resized_logo.width()) / 2;
+    // Paste logo onto white background, centered in the square above the caption
+    let logo_x = (box_size.0 - resized_logo.width()) / 2;
     let logo_y = (logo_bg_size - resized_logo.height()) / 2;
     image::imageops::overlay(&mut logo_bg, &resized_logo, logo_x as i64, logo_y as i64);
 
-    // Paste logo background onto QR code
-    let qr_logo_x = (qr_width - logo_bg_size) / 2;
-    let qr_logo_y = (qr_height - logo_bg_size) / 2;
-    image::imageops::overlay(&mut qr_img, &logo_bg, qr_logo_x as i64, qr_logo_y as i64);
+    if let Some(caption) = style.caption {
+        let scale = caption_scale(logo_bg_size);
+        let (text_width, _) = style.font.text_size(caption, scale);
+        let text_x = (box_size.0 - text_width) / 2;
+        style.font.draw_text(
+            &mut logo_bg,
+            caption,
+            (text_x, logo_bg_size),
+            scale,
+            Rgba([0, 0, 0, 255]),
+        );
+    }
+
+    // Paste logo background onto QR code, inside its ring; corners skip the
+    // finder pattern and its separator
+    let ring = style.ring.map_or(0, |(width, _)| width);
+    let footprint = (box_size.0 + 2 * ring, box_size.1 + 2 * ring);
+    let inset = quiet_zone_px + 8 * module_size;
+    let outer = logo_origin(style.position, qr_width, qr_height, footprint, inset)?;
+    if style.position != "center"
+        && overlaps_finder(
+            outer,
+            footprint,
+            (qr_width, qr_height),
+            quiet_zone_px,
+            module_size,
+        )
+    {
+        eprintln!(
+            "Warning: the logo at {} overlaps a finder pattern; the code may not scan. Consider --logo-position center",
+            style.position
+        );
+    }
+    if let Some((_, color)) = style.ring {
+        draw_logo_ring(&mut qr_img, outer, footprint, style.shape, color);
+    }
+    let origin = (outer.0 + ring, outer.1 + ring);
+    image::imageops::overlay(&mut qr_img, &logo_bg, origin.0 as i64, origin.1 as i64);
 
     Ok(qr_img)
 }
 
-fn print_terminal(data: &str, error_correction: EcLevel) -> Result<()> {
-    let code = QrCode::with_error_correction_level(data, error_correction)
-        .context("Failed to generate QR code for terminal display")?;
+/// Rotate then flip the whole composed image, for printers that feed media sideways
+fn transform_image(img: RgbaImage, rotate: &str, flip: &str) -> Result<RgbaImage> {
+    let img = match rotate {
+        "0" => img,
+        "90" => image::imageops::rotate90(&img),
+        "180" => image::imageops::rotate180(&img),
+        "270" => image::imageops::rotate270(&img),
+        _ => return Err(anyhow::anyhow!("Invalid rotation: {}", rotate)),
+    };
 
-    // Use Unicode block elements for better terminal display
-    let qr_string = code
-        .render::<char>()
-        .quiet_zone(true)
-        .module_dimensions(2, 1)
-        .build();
+    match flip {
+        "none" => Ok(img),
+        "horizontal" => Ok(image::imageops::flip_horizontal(&img)),
+        "vertical" => Ok(image::imageops::flip_vertical(&img)),
+        _ => Err(anyhow::anyhow!("Invalid flip: {}", flip)),
+    }
+}
+
+/// Whether pixel (x, y) of a `width`x`height` image falls outside the rounded
+/// corners of the given radius, judged at the pixel center
+fn outside_radius(x: u32, y: u32, width: u32, height: u32, radius: u32) -> bool {
+    let r = radius as f32;
+    // Distance into the corner square, measured from the nearest edge
+    let dx = r - (x.min(width - 1 - x) as f32 + 0.5);
+    let dy = r - (y.min(height - 1 - y) as f32 + 0.5);
+
+    dx > 0.0 && dy > 0.0 && dx * dx + dy * dy > r * r
+}
+
+/// Make the pixels outside the rounded corners fully transparent. The radius
+/// must fit inside the quiet zone so no modules are cut off.
+fn round_corners(mut img: RgbaImage, radius: u32, quiet_zone_px: u32) -> Result<RgbaImage> {
+    if radius == 0 {
+        return Ok(img);
+    }
+
+    let (width, height) = img.dimensions();
+    if outside_radius(quiet_zone_px, quiet_zone_px, width, height, radius) {
+        return Err(anyhow::anyhow!(
+            "--border-radius {} would cut into the QR code; use a smaller radius or a larger --border",
+            radius
+        ));
+    }
+
+    for y in 0..height {
+        for x in 0..width {
+            if outside_radius(x, y, width, height, radius) {
+                img.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+            }
+        }
+    }
+
+    Ok(img)
+}
+
+/// Read the raw bytes stored in the first QR code found in the image
+fn decode_qr_image(img: &RgbaImage) -> Result<Vec<u8>> {
+    // Flatten onto white first: dropping the alpha would turn the
+    // transparent background of --code-only into black
+    let luma = image::GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *img.get_pixel(x, y);
+        let gray = (r as u32 * 2126 + g as u32 * 7152 + b as u32 * 722) / 10000;
+        let gray = (gray * a as u32 + 255 * (255 - a as u32)) / 255;
+        image::Luma([gray as u8])
+    });
+    let mut prepared = rqrr::PreparedImage::prepare(luma);
+    let grids = prepared.detect_grids();
+
+    let grid = grids
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("no QR code detected"))?;
+    let mut decoded = Vec::new();
+    grid.decode_to(&mut decoded)
+        .map_err(|e| anyhow::anyhow!("could not decode QR code ({})", e))?;
+    Ok(decoded)
+}
+
+fn verify_qr_code(img: &RgbaImage, expected: &[u8]) -> Result<()> {
+    let decoded =
+        decode_qr_image(img).map_err(|e| anyhow::anyhow!("Verification failed: {}", e))?;
+
+    if decoded != expected {
+        return Err(anyhow::anyhow!(
+            "Verification failed: decoded data {:?} does not match input {:?}",
+            String::from_utf8_lossy(&decoded),
+            String::from_utf8_lossy(expected)
+        ));
+    }
 
-    println!("{}", qr_string);
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// Drop control characters other than tabs and line breaks, plus invisible
+/// formatting characters that spreadsheets and web pages leave behind,
+/// returning the cleaned text and how many characters were removed. Joiners
+/// are kept since emoji sequences and some scripts need them.
+fn strip_control_chars(data: &str) -> (String, usize) {
+    let stray = |c: char| {
+        (c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+            || matches!(c, '\u{ad}' | '\u{200b}' | '\u{2060}' | '\u{feff}')
+    };
+    let cleaned: String = data.chars().filter(|&c| !stray(c)).collect();
+    let removed = data.chars().count() - cleaned.chars().count();
+    (cleaned, removed)
+}
+
+/// Bring the text to one Unicode normalization form; NFC composes accented
+/// characters, which also keeps the payload short
+fn normalize_text(data: &str, form: &str) -> String {
+    match form {
+        "nfc" => data.nfc().collect(),
+        "nfd" => data.nfd().collect(),
+        _ => data.to_string(),
+    }
+}
 
-    // Apply template if specified
+fn wrap_data(data: &str, prefix: Option<&str>, suffix: Option<&str>) -> String {
+    format!(
+        "{}{}{}",
+        prefix.unwrap_or_default(),
+        data,
+        suffix.unwrap_or_default()
+    )
+}
+
+/// Encode `data` as a `data:<mime>;base64,...` URI
+fn data_uri(data: &str, mime: &str) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(data)
+    )
+}
+
+/// Turn raw user input into the final payload: stray control characters
+/// out first, then template expansion, then the data URI wrapper, then
+/// prefix/suffix so they wrap the result
+fn prepare_payload(cli: &Cli, input: &str) -> Result<String> {
+    let input = if cli.strip_control_chars {
+        let (cleaned, removed) = strip_control_chars(input);
+        if removed > 0 {
+            eprintln!("Removed {} control character(s) from the data", removed);
+        }
+        cleaned
+    } else {
+        input.to_string()
+    };
     let data = if let Some(template) = &cli.template {
-        apply_template(template, &cli.data)?
+        let options = TemplateOptions {
+            fields: cli.field_options(),
+            vcard_version: &cli.vcard_version,
+            sms_format: &cli.sms_format,
+            prompts: PromptOptions {
+                // Rows of the multi-code modes run on worker threads, where
+                // prompts would interleave on one terminal
+                enabled: !cli.validate_only
+                    && cli.batch.is_none()
+                    && cli.from_lines.is_none()
+                    && cli.count.is_none(),
+                timeout: cli.prompt_timeout,
+            },
+            wifi: WifiOptions {
+                strict_encryption: cli.strict_wifi_encryption,
+                hidden: cli.wifi_hidden,
+            },
+        };
+        apply_template(template, &input, &options)?
     } else {
-        cli.data.clone()
+        input
     };
 
-    // Determine output path
-    let output_path = if !cli.terminal && cli.output.is_none() {
-        Some(PathBuf::from("qr_code.png"))
-    } else {
-        cli.output.clone()
+    let data = match &cli.wrap_data_uri {
+        Some(mime) => data_uri(&normalize_text(&data, &cli.normalize), mime),
+        None => data,
     };
+    let data = wrap_data(
+        &data,
+        cli.data_prefix.as_deref(),
+        cli.data_suffix.as_deref(),
+    );
+    let data = normalize_text(&data, &cli.normalize);
 
-    // Parse colors
-    let fill_color = parse_color(&cli.fill_color)
-        .with_context(|| format!("Invalid fill color: {}", cli.fill_color))?;
-    let back_color = parse_color(&cli.back_color)
-        .with_context(|| format!("Invalid back color: {}", cli.back_color))?;
+    if cli.strict {
+        validate_url(&data)?;
+    }
 
-    // Display in terminal if requested
-    if cli.terminal {
-        print_terminal(&data, cli.error_correction)?;
+    // --validate-only checks the input without going out to the network
+    match &cli.shortener_url {
+        Some(endpoint) if cli.shorten && !cli.validate_only && shorten::is_web_url(&data) => {
+            shorten_url(cli, endpoint, data)
+        }
+        _ => Ok(data),
     }
+}
 
-    // Save to file if output path provided
-    if let Some(output) = output_path {
-        let mut img = create_qr_code(
-            &data,
-            cli.error_correction,
-            cli.size,
-            cli.border,
-            fill_color,
-            back_color,
-        )?;
+/// Swap `url` for its short form, keeping the long URL with a warning if
+/// the shortener fails, or erroring under --strict
+fn shorten_url(cli: &Cli, endpoint: &str, url: String) -> Result<String> {
+    match shorten::shorten(endpoint, &url) {
+        Ok(short) => Ok(short),
+        Err(e) if cli.strict => Err(e.context("--strict refuses to fall back to the long URL")),
+        Err(e) => {
+            eprintln!("Warning: {:#}; encoding the original URL", e);
+            Ok(url)
+        }
+    }
+}
+
+/// Whether the payload starts with a URL scheme followed by `://`
+fn looks_like_url(data: &str) -> bool {
+    let Some((scheme, _)) = data.split_once("://") else {
+        return false;
+    };
+
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// Reject URL payloads that wouldn't survive a round trip through a URL parser,
+/// such as embedded spaces or an invalid host. Other payloads pass untouched.
+fn validate_url(data: &str) -> Result<()> {
+    if !looks_like_url(data) {
+        return Ok(());
+    }
+
+    // The parser silently strips these, but a scanner would keep them
+    if data.chars().any(|c| c.is_whitespace()) {
+        return Err(anyhow::anyhow!(
+            "Invalid URL {:?}: contains whitespace",
+            data
+        ));
+    }
+
+    url::Url::parse(data).map_err(|e| anyhow::anyhow!("Invalid URL {:?}: {}", data, e))?;
+    Ok(())
+}
+
+/// Convert the payload to the bytes stored in the QR code. Legacy readers may
+/// expect Latin-1 or Shift-JIS rather than UTF-8.
+fn encode_text(data: &str, encoding: &str) -> Result<Vec<u8>> {
+    let target = match encoding {
+        "utf8" => return Ok(data.as_bytes().to_vec()),
+        // encoding_rs only has Windows-1252 under this name, which puts € and
+        // curly quotes at 0x80-0x9F where ISO-8859-1 (ECI 3) has controls
+        "latin1" => {
+            return data
+                .chars()
+                .map(|c| u8::try_from(c).ok())
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Data contains characters that can't be represented in {}",
+                        encoding
+                    )
+                })
+        }
+        "shift-jis" => encoding_rs::SHIFT_JIS,
+        _ => return Err(anyhow::anyhow!("Unknown input encoding: {}", encoding)),
+    };
+
+    let (bytes, _, had_errors) = target.encode(data);
+    if had_errors {
+        return Err(anyhow::anyhow!(
+            "Data contains characters that can't be represented in {}",
+            encoding
+        ));
+    }
+
+    Ok(bytes.into_owned())
+}
+
+/// Drop trailing characters until the payload fits in a version 40 code at the
+/// given level. Returns the clipped text and how many encoded bytes were removed.
+fn clip_to_capacity(
+    data: &str,
+    encoding: &str,
+    error_correction: EcLevel,
+    encode_opts: EncodeOptions,
+) -> Result<(String, usize)> {
+    let full_len = encode_text(data, encoding)?.len();
+    let fits = |end: usize| -> Result<bool> {
+        let bytes = encode_text(&data[..end], encoding)?;
+        Ok(encode_at_version(&bytes, Version::Normal(40), error_correction, encode_opts).is_ok())
+    };
+
+    if fits(data.len())? {
+        return Ok((data.to_string(), 0));
+    }
+
+    // Binary search over character boundaries: the first `lo` characters fit,
+    // the first `hi` don't
+    let boundaries: Vec<usize> = data.char_indices().map(|(i, _)| i).collect();
+    let (mut lo, mut hi) = (0, boundaries.len());
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        if fits(boundaries[mid])? {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    let clipped = data[..boundaries[lo]].to_string();
+    let dropped = full_len - encode_text(&clipped, encoding)?.len();
+    Ok((clipped, dropped))
+}
+
+/// Error out if the finished payload is longer than --max-payload-bytes
+fn check_payload_length(cli: &Cli, data: &[u8]) -> Result<()> {
+    match cli.max_payload_bytes {
+        Some(max) if data.len() as u64 > max => Err(anyhow::anyhow!(
+            "Payload is {} bytes, more than the --max-payload-bytes limit of {}",
+            data.len(),
+            max
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn check_not_empty(cli: &Cli, data: &[u8]) -> Result<()> {
+    if data.is_empty() && !cli.allow_empty {
+        return Err(anyhow::anyhow!(
+            "Data is empty; pass --allow-empty to generate a blank code anyway"
+        ));
+    }
+    Ok(())
+}
+
+/// Turn raw input into the bytes to encode: apply the template and affixes,
+/// clip to capacity if asked, then transcode and check the length limit
+fn payload_bytes(cli: &Cli, opts: &RenderOptions, input: &str) -> Result<Vec<u8>> {
+    if cli.data_base64 {
+        // Tools like `base64` wrap their output, so ignore line breaks
+        let encoded: String = input.split_whitespace().collect();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .context("Invalid base64 data")?;
+        check_not_empty(cli, &bytes)?;
+        check_payload_length(cli, &bytes)?;
+        return Ok(bytes);
+    }
+
+    let mut data = prepare_payload(cli, input)?;
+    check_not_empty(cli, data.as_bytes())?;
+
+    if cli.clip_data {
+        // Auto and --auto-downgrade-ec only settle on a level once the data
+        // fits, which means L
+        let error_correction = match opts.error_correction {
+            EcChoice::Level(level) if !cli.auto_downgrade_ec => level,
+            _ => EcLevel::L,
+        };
+        let (clipped, dropped) = clip_to_capacity(
+            &data,
+            &cli.input_encoding,
+            error_correction,
+            cli.encode_opts(),
+        )?;
+        if dropped > 0 {
+            eprintln!(
+                "Warning: data is too long for a QR code at error correction {:?}; dropped the last {} bytes",
+                error_correction, dropped
+            );
+        }
+        data = clipped;
+    }
+
+    let bytes = encode_text(&data, &cli.input_encoding)?;
+    check_payload_length(cli, &bytes)?;
+    Ok(bytes)
+}
+
+/// Pixels per module so that `modules` modules (quiet zone included) print
+/// `mm` millimetres wide at `dpi`
+fn module_size_for_print(mm: f64, dpi: u32, modules: u32) -> Result<u32> {
+    let pixels = mm / 25.4 * dpi as f64;
+    let size = (pixels / modules as f64).round() as u32;
+    if size == 0 {
+        return Err(anyhow::anyhow!(
+            "{} mm at {} DPI is too small for a code {} modules wide; increase --physical-size or --dpi",
+            mm,
+            dpi,
+            modules
+        ));
+    }
+
+    Ok(size)
+}
+
+/// Largest module size no bigger than `size` that keeps a code `modules`
+/// wide within `max_px`, but never below 1 px
+fn module_size_for_max(size: u32, modules: u32, max_px: u32) -> u32 {
+    size.min(max_px / modules).max(1)
+}
+
+/// Width and height in pixels of a code `width` modules across drawn with
+/// `size` px modules: the quiet zone or padding, then any brand frame
+fn output_dimensions(opts: &RenderOptions, width: u32, size: u32) -> Result<(u32, u32)> {
+    let code_size = (
+        (width + opts.horizontal_border()).saturating_mul(size),
+        (width + opts.vertical_border()).saturating_mul(size),
+    );
+    match &opts.frame {
+        Some(frame) => frame::footprint(frame, &opts.font, size, code_size),
+        None => Ok(code_size),
+    }
+}
+
+/// With --physical-size or --max-dimension, a copy of the options with `size`
+/// chosen so the image prints at that width or stays within the cap. Needs
+/// the module count, so encodes up front.
+fn sized_for_output(cli: &Cli, opts: &RenderOptions, data: &[u8]) -> Result<Cli> {
+    let mut sized = cli.clone();
+    if cli.physical_size.is_none() && cli.max_dimension.is_none() {
+        return Ok(sized);
+    }
+
+    let width = if cli.symbology == "datamatrix" {
+        data_matrix::symbol_width(data)?
+    } else {
+        encode_data(cli, opts, data)?.width() as u32
+    };
+    if let (Some(mm), Some(dpi)) = (cli.physical_size, cli.print_dpi()) {
+        sized.size = module_size_for_print(mm, dpi, width + opts.horizontal_border())?;
+    }
+    if let Some(max_px) = cli.max_dimension {
+        let modules = width + opts.horizontal_border().max(opts.vertical_border());
+        // A frame's band and tab don't scale exactly with the module size, so
+        // step down from the quiet-zone estimate until the whole image fits
+        let longest_side =
+            |size| output_dimensions(opts, width, size).map_or(u32::MAX, |(w, h)| w.max(h));
+        sized.size = (1..=module_size_for_max(cli.size, modules, max_px))
+            .rev()
+            .find(|&size| longest_side(size) <= max_px)
+            .unwrap_or(1);
+        if longest_side(sized.size) > max_px {
+            eprintln!(
+                "Warning: a code {} modules wide can't fit in --max-dimension {} even with 1 px modules; the image will be {} px",
+                modules,
+                max_px,
+                longest_side(1)
+            );
+        } else if sized.size == 1 && cli.size > 1 {
+            eprintln!(
+                "Warning: --max-dimension {} leaves 1 px modules, which many cameras can't resolve",
+                max_px
+            );
+        }
+    }
+    Ok(sized)
+}
+
+/// Refuse canvases whose pixel buffer would exceed `max_mb` megabytes, before
+/// anything is allocated
+fn check_canvas_size(qr_width: u32, border: u32, size: u32, max_mb: u64) -> Result<()> {
+    let side = (qr_width as u64 + 2 * border as u64) * size as u64;
+    check_image_size((side, side), size, max_mb)
+}
+
+/// Refuse a `width` by `height` image drawn with `size` px modules when its
+/// pixel buffer would exceed `max_mb` megabytes
+fn check_image_size((width, height): (u64, u64), size: u32, max_mb: u64) -> Result<()> {
+    let bytes = width.saturating_mul(height).saturating_mul(4);
+    let max_bytes = max_mb.saturating_mul(1024 * 1024);
+
+    if bytes > max_bytes {
+        // The pixel count grows with the square of the module size
+        let max_size = (size as f64 * (max_bytes as f64 / bytes as f64).sqrt()) as u64;
+        return Err(anyhow::anyhow!(
+            "Image would be {}x{} pixels (~{} MB), over the --max-size limit of {} MB; use --size {} or smaller",
+            width,
+            height,
+            bytes / (1024 * 1024),
+            max_mb,
+            max_size.max(1)
+        ));
+    }
+
+    Ok(())
+}
+
+/// Refuse module sizes too small to scan reliably, unless --allow-tiny
+fn check_module_pixels(cli: &Cli) -> Result<()> {
+    if cli.allow_tiny || cli.size >= cli.min_module_pixels {
+        return Ok(());
+    }
+
+    let cause = match cli.physical_size {
+        Some(mm) => format!(
+            "--physical-size {} at {} DPI",
+            mm,
+            cli.print_dpi().unwrap_or(DEFAULT_DPI)
+        ),
+        None => match cli.max_dimension {
+            Some(max_px) => format!("--max-dimension {}", max_px),
+            None => format!("--size {}", cli.size),
+        },
+    };
+    Err(anyhow::anyhow!(
+        "{} gives {} px modules, smaller than the {} px cameras need to scan reliably (--min-module-pixels); use a larger size or pass --allow-tiny",
+        cause,
+        cli.size,
+        cli.min_module_pixels
+    ))
+}
+
+fn render_image(cli: &Cli, opts: &RenderOptions, code: &QrCode, data: &[u8]) -> Result<RgbaImage> {
+    // The bare code first, which also keeps absurd sizes away from the
+    // pixel arithmetic below, then the whole image: padding, frame, tab and
+    // the square canvas around them
+    let widest_border = opts.padding.map_or(opts.border, |sides| sides.max());
+    check_canvas_size(code.width() as u32, widest_border, cli.size, cli.max_size)?;
+    let (width, height) = output_dimensions(opts, code.width() as u32, cli.size)?;
+    let (width, height) = if opts.frame.is_some() && cli.square_canvas == "on" {
+        (width.max(height), width.max(height))
+    } else {
+        (width, height)
+    };
+    check_image_size((width as u64, height as u64), cli.size, cli.max_size)?;
+
+    let mut img = match (&opts.background, &opts.module_image) {
+        _ if cli.visualize_ec => {
+            eprintln!(
+                "EC visualization: {}; this image is not meant to be scanned",
+                ec_map::summary(code)?
+            );
+            ec_map::draw(code, cli.size, opts.border)?
+        }
+        (_, Some(sprite)) => draw_module_images(
+            code,
+            cli.size,
+            opts.border,
+            opts.fill_color,
+            opts.back_color,
+            sprite,
+        ),
+        (Some(background), None) => draw_on_background(
+            code,
+            cli.size,
+            opts.border,
+            opts.fill(),
+            background,
+            opts.module_gap,
+            cli.dither,
+        ),
+        (None, None) => match &opts.back_gradient {
+            Some(gradient) => draw_on_gradient(
+                code,
+                cli.size,
+                opts.border,
+                opts.fill(),
+                gradient,
+                opts.module_gap,
+            ),
+            None => draw_qr_code(
+                code,
+                cli.size,
+                opts.border,
+                opts.fill(),
+                opts.back_color,
+                opts.module_gap,
+            ),
+        },
+    };
+
+    if opts.finder_shape != finder::Shape::Square {
+        let (width, height) = img.dimensions();
+        let light = |x, y| match &opts.back_gradient {
+            Some(gradient) => gradient.color_at(x, y, width, height),
+            None => opts.back_color,
+        };
+        finder::restyle(
+            &mut img,
+            code.width() as u32,
+            cli.size,
+            opts.border,
+            opts.fill(),
+            opts.finder_shape,
+            light,
+        );
+    }
+
+    // Embed logo if provided
+    if let Some(logo_path) = &cli.logo {
+        img = embed_logo(
+            img,
+            logo_path,
+            &LogoStyle {
+                feather: cli.logo_feather,
+                shape: &cli.logo_shape,
+                position: &cli.logo_position,
+                caption: cli.logo_caption.as_deref(),
+                font: &opts.font,
+                ring: opts
+                    .logo_border_color
+                    .map(|color| (opts.logo_ring_width(cli), color)),
+            },
+            opts.border * cli.size,
+            cli.size,
+        )?;
+    }
+
+    if let Some(sides) = opts.padding {
+        img = layout::pad(img, sides, cli.size, opts.border_color);
+    }
+    let mut below = Vec::new();
+    if let Some(frame) = &opts.frame {
+        let (framed, tab) = frame::draw(&img, frame, &opts.font, cli.size, opts.back_color)?;
+        img = framed;
+        below.push(tab);
+    }
+    (img, _) = layout::compose(img, &[], &below, opts.back_color, cli.square_canvas == "on");
+    img = transform_image(img, &cli.rotate, &cli.flip)?;
+    img = round_corners(img, cli.border_radius, opts.quiet_zone() * cli.size)?;
+
+    // Make sure the final image actually scans before writing it
+    if cli.verify {
+        verify_qr_code(&img, data)?;
+    }
+
+    Ok(img)
+}
+
+/// Generate the code for `data` and write it in the format the output path
+/// (or `--output-format`) calls for, or in every `--emit` format next to it,
+/// returning the `--json-output` summary for each file
+fn write_output(
+    cli: &Cli,
+    opts: &RenderOptions,
+    data: &[u8],
+    path: &Path,
+) -> Result<Vec<serde_json::Value>> {
+    let targets = if cli.emit.is_empty() {
+        let format = output::resolve_format(cli.output_format.as_deref(), path)?;
+        vec![(path.to_path_buf(), format)]
+    } else {
+        emit_targets(path, &cli.emit)
+    };
+    write_outputs(cli, opts, data, &targets)
+}
+
+/// Write `data` to every (path, format) in `targets` from a single encode,
+/// returning a `--json-output` summary for each
+fn write_outputs(
+    cli: &Cli,
+    opts: &RenderOptions,
+    data: &[u8],
+    targets: &[(PathBuf, OutputFormat)],
+) -> Result<Vec<serde_json::Value>> {
+    if cli.no_clobber {
+        if let Some((path, _)) = targets.iter().find(|(path, _)| path.exists()) {
+            return Err(anyhow::anyhow!(
+                "{} already exists; remove it or drop --no-clobber",
+                path.display()
+            ));
+        }
+    }
+    let cli = &sized_for_output(cli, opts, data)?;
+    for (path, format) in targets {
+        check_format(cli, opts, path, *format)?;
+    }
+
+    let entries = save_code(cli, opts, data, targets)?;
+    let mut summaries = Vec::new();
+    for entry in &entries {
+        if let Some(log_path) = &cli.log_file {
+            run_log::append(log_path, entry)?;
+        }
+        summaries.push(output::output_summary(entry));
+    }
+    Ok(summaries)
+}
+
+/// Refuse options `format` can't carry, and warn about ones it ignores
+fn check_format(cli: &Cli, opts: &RenderOptions, path: &Path, format: OutputFormat) -> Result<()> {
+    // A JSON matrix has no pixels
+    if format != OutputFormat::Json {
+        check_module_pixels(cli)?;
+    }
+    if cli.border_radius > 0 && format != OutputFormat::Png {
+        return Err(anyhow::anyhow!("--border-radius needs PNG output"));
+    }
+    if cli.code_only && format != OutputFormat::Png {
+        return Err(anyhow::anyhow!("--code-only needs PNG output"));
+    }
+    if cli.module_image.is_some() && matches!(format, OutputFormat::Svg | OutputFormat::Json) {
+        return Err(anyhow::anyhow!(
+            "--module-image needs a raster output format"
+        ));
+    }
+    if opts.back_gradient.is_some() && matches!(format, OutputFormat::Svg | OutputFormat::Json) {
+        return Err(anyhow::anyhow!(
+            "--back-gradient needs a raster output format"
+        ));
+    }
+    if opts.finder_shape != finder::Shape::Square
+        && matches!(format, OutputFormat::Svg | OutputFormat::Json)
+    {
+        return Err(anyhow::anyhow!(
+            "--finder-shape needs a raster output format"
+        ));
+    }
+    if cli.visualize_ec && matches!(format, OutputFormat::Svg | OutputFormat::Json) {
+        return Err(anyhow::anyhow!(
+            "--visualize-ec needs a raster output format"
+        ));
+    }
+    if opts.frame.is_some() && matches!(format, OutputFormat::Svg | OutputFormat::Json) {
+        return Err(anyhow::anyhow!(
+            "--brand-frame needs a raster output format"
+        ));
+    }
+    if opts.padding.is_some() && matches!(format, OutputFormat::Svg | OutputFormat::Json) {
+        return Err(anyhow::anyhow!(
+            "--border-top, --border-right, --border-bottom, --border-left and --border-color need a raster output format"
+        ));
+    }
+    if cli.embed_metadata && format != OutputFormat::Png {
+        eprintln!(
+            "Warning: --embed-metadata only applies to PNG output; {} will be saved without it",
+            path.display()
+        );
+    }
+    if cli.embed_srgb && format != OutputFormat::Png {
+        eprintln!(
+            "Warning: --embed-srgb only applies to PNG output; {} will be saved without it",
+            path.display()
+        );
+    }
+    if cli.reproducible && format != OutputFormat::Png {
+        eprintln!(
+            "Warning: --reproducible only applies to PNG output; {} is saved with the default encoder settings",
+            path.display()
+        );
+    }
+    if cli.svg_background == "none" && format != OutputFormat::Svg {
+        eprintln!(
+            "Warning: --svg-background only applies to SVG output; {} keeps its background",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// The files `--emit` writes: `output` with its extension swapped for each
+/// format, in the order given and without repeats
+fn emit_targets(output: &Path, formats: &[String]) -> Vec<(PathBuf, OutputFormat)> {
+    let mut targets: Vec<(PathBuf, OutputFormat)> = Vec::new();
+    for name in formats {
+        let Some(format) = OutputFormat::from_name(name) else {
+            continue;
+        };
+        if !targets.iter().any(|&(_, seen)| seen == format) {
+            targets.push((output.with_extension(format.name()), format));
+        }
+    }
+    targets
+}
+
+/// Encode once, then render and save each of `targets`, returning what
+/// `--log-file` records for each
+fn save_code<'a>(
+    cli: &Cli,
+    opts: &RenderOptions,
+    data: &'a [u8],
+    targets: &'a [(PathBuf, OutputFormat)],
+) -> Result<Vec<run_log::LogEntry<'a>>> {
+    if cli.symbology == "datamatrix" {
+        if targets
+            .iter()
+            .any(|(_, format)| matches!(format, OutputFormat::Svg | OutputFormat::Json))
+        {
+            return Err(anyhow::anyhow!(
+                "Data Matrix output must be a raster image format"
+            ));
+        }
+        let img = data_matrix::render_image(cli, opts, data)?;
+        let width = data_matrix::symbol_width(data)?;
+        let symbol = format!("Data Matrix {}x{}", width, width);
+        let metadata = cli
+            .embed_metadata
+            .then(|| output::metadata_text(data.len(), &symbol));
+        let mut entries = Vec::new();
+        for (path, format) in targets {
+            output::with_write_retries(cli.write_retries, || {
+                output::save_image(cli, &img, path, *format, metadata.as_deref())
+            })?;
+            entries.push(run_log::LogEntry {
+                data,
+                output: path,
+                format: *format,
+                symbol: symbol.clone(),
+                version: None,
+                error_correction: None,
+                dimensions: img.dimensions(),
+            });
+        }
+        return Ok(entries);
+    }
+
+    let code = encode_data(cli, opts, data)?;
+    if let Some(report_path) = &cli.encoding_report {
+        let report = output::encoding_report(&code, data, cli.encode_opts())?;
+        output::write_encoding_report(&report, report_path.as_deref())?;
+    }
+    if cli.dump_bits {
+        eprint!(
+            "{}",
+            output::bitstream_dump(&code, data, cli.encode_opts())?
+        );
+    }
+    if cli.show_matrix {
+        eprint!("{}", output::matrix_dump(&code));
+    }
+
+    let (Version::Normal(version) | Version::Micro(version)) = code.version();
+    // Raster formats share one rendering
+    let mut img = None;
+    let mut entries = Vec::new();
+    for (path, format) in targets {
+        let dimensions = match format {
+            OutputFormat::Json => {
+                output::with_write_retries(cli.write_retries, || {
+                    output::save_matrix_json(&code, path)
+                })?;
+                (code.width() as u32, code.width() as u32)
+            }
+            OutputFormat::Svg => {
+                let svg = svg::render_svg(
+                    &code,
+                    cli.size,
+                    opts.border,
+                    opts.fill(),
+                    opts.back_color,
+                    &svg::SvgOptions {
+                        module_gap: opts.module_gap,
+                        optimize: cli.svg_optimize,
+                        css: cli.svg_css,
+                        transparent: cli.svg_background == "none",
+                    },
+                );
+                output::with_write_retries(cli.write_retries, || svg::save_svg(&svg, path))?;
+                let side = (code.width() as u32 + 2 * opts.border) * cli.size;
+                (side, side)
+            }
+            _ => {
+                if img.is_none() {
+                    img = Some(render_image(cli, opts, &code, data)?);
+                }
+                let img = img.as_ref().unwrap();
+                let metadata = cli
+                    .embed_metadata
+                    .then(|| output::qr_metadata(&code, data.len()));
+                output::with_write_retries(cli.write_retries, || {
+                    output::save_image(cli, img, path, *format, metadata.as_deref())
+                })?;
+                img.dimensions()
+            }
+        };
+
+        entries.push(run_log::LogEntry {
+            data,
+            output: path,
+            format: *format,
+            symbol: format!("QR version {}", version),
+            version: Some(version),
+            error_correction: Some(format!("{:?}", code.error_correction_level())),
+            dimensions,
+        });
+    }
+    Ok(entries)
+}
+
+/// Whether a COLORFGBG value ("fg;bg", sometimes "fg;default;bg") names a
+/// dark background: colors 0-6 and 8 are the dark half of the ANSI palette
+fn colorfgbg_is_dark(value: &str) -> Option<bool> {
+    let background: u8 = value.rsplit(';').next()?.trim().parse().ok()?;
+    Some(matches!(background, 0..=6 | 8))
+}
+
+/// Whether --terminal should draw for a dark background. `auto` trusts
+/// COLORFGBG when the terminal sets it and otherwise assumes a light
+/// background, which is how codes were always printed.
+fn terminal_is_dark(theme: &str) -> bool {
+    match theme {
+        "dark" => true,
+        "light" => false,
+        _ => std::env::var("COLORFGBG")
+            .ok()
+            .and_then(|value| colorfgbg_is_dark(&value))
+            .unwrap_or(false),
+    }
+}
+
+/// The code as block characters. Blocks are drawn in the terminal's
+/// foreground color, so on a dark terminal they mark the light modules
+/// instead of the dark ones.
+fn terminal_string(code: &QrCode, dark_terminal: bool) -> String {
+    let (dark, light) = if dark_terminal {
+        (' ', '█')
+    } else {
+        ('█', ' ')
+    };
+    code.render::<char>()
+        .quiet_zone(true)
+        .module_dimensions(2, 1)
+        .dark_color(dark)
+        .light_color(light)
+        .build()
+}
+
+fn print_terminal(
+    data: &[u8],
+    error_correction: EcLevel,
+    encode_opts: EncodeOptions,
+    dark_terminal: bool,
+) -> Result<()> {
+    let code = encode_smallest(data, error_correction, encode_opts)
+        .context("Failed to generate QR code for terminal display")?;
+
+    println!("{}", terminal_string(&code, dark_terminal));
+    Ok(())
+}
+
+/// Open the written file in the default viewer, unless nobody is there to see
+/// it (piped output or CI)
+fn preview_output(path: &Path) {
+    if std::env::var_os("CI").is_some() || !std::io::stdout().is_terminal() {
+        return;
+    }
+    open_preview(path);
+}
+
+/// A missing viewer shouldn't fail a command whose file was already written
+fn open_preview(path: &Path) {
+    if let Err(e) = open::that_detached(path) {
+        eprintln!(
+            "Warning: couldn't open {} for preview: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Reject options that only make sense for QR codes when another symbology is chosen
+fn check_symbology_options(cli: &Cli) -> Result<()> {
+    if cli.symbology == "qr" {
+        return Ok(());
+    }
+
+    let qr_only = [
+        ("--error-correction", cli.error_correction.is_some()),
+        ("--auto-downgrade-ec", cli.auto_downgrade_ec),
+        ("--encoding-report", cli.encoding_report.is_some()),
+        ("--dump-bits", cli.dump_bits),
+        ("--show-matrix", cli.show_matrix),
+        ("--visualize-ec", cli.visualize_ec),
+        ("--logo", cli.logo.is_some()),
+        ("--background-image", cli.background_image.is_some()),
+        ("--back-gradient", cli.back_gradient.is_some()),
+        ("--palette", cli.palette.is_some()),
+        ("--module-gap", cli.module_gap != 0.0),
+        ("--module-image", cli.module_image.is_some()),
+        ("--finder-shape", cli.finder_shape != "square"),
+        ("--brand-frame", cli.brand_frame.is_some()),
+        ("--border-top", cli.border_top.is_some()),
+        ("--border-right", cli.border_right.is_some()),
+        ("--border-bottom", cli.border_bottom.is_some()),
+        ("--border-left", cli.border_left.is_some()),
+        ("--border-color", cli.border_color.is_some()),
+        ("--eci", cli.eci.is_some()),
+        ("--optimize-segments", cli.optimize_segments),
+        ("--min-version", cli.min_version.is_some()),
+        ("--verify", cli.verify),
+        ("--clip-data", cli.clip_data),
+        ("--code-only", cli.code_only),
+    ];
+    match qr_only.iter().find(|(_, used)| *used) {
+        Some((flag, _)) => Err(anyhow::anyhow!(
+            "{} is only supported with --symbology qr",
+            flag
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Reject the WiFi template's options when another template is chosen
+fn check_wifi_options(cli: &Cli) -> Result<()> {
+    if cli.template.as_deref() == Some("wifi") {
+        return Ok(());
+    }
+    let wifi_only = [
+        ("--strict-wifi-encryption", cli.strict_wifi_encryption),
+        ("--wifi-hidden", cli.wifi_hidden),
+    ];
+    match wifi_only.iter().find(|(_, set)| *set) {
+        Some((flag, _)) => Err(anyhow::anyhow!("{} needs --template wifi", flag)),
+        None => Ok(()),
+    }
+}
+
+/// The `--also-ics` invite for the event in the calendar template input
+fn calendar_invite(cli: &Cli, input: &str, path: &Path) -> Result<String> {
+    if cli.template.as_deref() != Some("calendar") {
+        return Err(anyhow::anyhow!("--also-ics needs --template calendar"));
+    }
+    if cli.no_clobber && path.exists() {
+        return Err(anyhow::anyhow!(
+            "{} already exists; remove it or drop --no-clobber",
+            path.display()
+        ));
+    }
+    templates::calendar_ics(input, cli.field_options(), SystemTime::now())
+}
+
+/// Save the `--also-ics` invite once the code itself has been written
+fn write_ics(cli: &Cli, ics: &str, path: &Path) -> Result<()> {
+    output::with_write_retries(cli.write_retries, || {
+        std::fs::write(path, ics)
+            .with_context(|| format!("Failed to save calendar invite to {:?}", path))
+    })?;
+    // Keep stdout to the JSON document with --json-output
+    if !cli.json_output {
+        println!("Calendar invite saved to: {}", path.display());
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Benchmark(args)) => return benchmark::run(args),
+        Some(Command::Reencode(args)) => cli = reencode::cli_for(args)?,
+        None => {}
+    }
+    if let Some(name) = &cli.template_help {
+        // Names are limited to the table by clap
+        print!("{}", templates::template_help(name).unwrap_or_default());
+        return Ok(());
+    }
+    if cli.from_clipboard {
+        cli.data = Some(clipboard::read_text()?);
+    }
+    check_symbology_options(&cli)?;
+    check_wifi_options(&cli)?;
+
+    // Parse colors
+    let back_color = parse_color(&cli.back_color)
+        .with_context(|| format!("Invalid back color: {}", cli.back_color))?;
+    let fill_color = match (&cli.fill_color, &cli.logo) {
+        (Some(color), _) => {
+            if cli.color_from_logo {
+                eprintln!("Warning: --fill-color overrides --color-from-logo");
+            }
+            parse_color(color).with_context(|| format!("Invalid fill color: {}", color))?
+        }
+        (None, Some(logo_path)) if cli.color_from_logo => {
+            let logo = image::open(logo_path)
+                .context("Failed to open logo image")?
+                .to_rgba8();
+            dominant_color(&logo)
+        }
+        (None, _) => parse_color("black")?,
+    };
+
+    let (fill_color, back_color) = if cli.auto_contrast {
+        let (fill, back) = adjust_contrast(fill_color, back_color, AUTO_CONTRAST_RATIO);
+        if (fill, back) != (fill_color, back_color) {
+            eprintln!(
+                "Adjusted colors for contrast: fill {}, back {} ({:.1}:1)",
+                svg::hex_color(fill),
+                svg::hex_color(back),
+                contrast_ratio(fill, back)
+            );
+        }
+        (fill, back)
+    } else {
+        (fill_color, back_color)
+    };
+
+    let border = resolve_border(&cli);
+    let padding = resolve_padding(&cli, border)?;
+    let border_color = match &cli.border_color {
+        Some(color) => {
+            parse_color(color).with_context(|| format!("Invalid border color: {}", color))?
+        }
+        None => back_color,
+    };
+
+    let error_correction = resolve_error_correction(
+        cli.error_correction,
+        cli.logo.is_some(),
+        cli.template.as_deref(),
+    );
+
+    let background = match &cli.background_image {
+        Some(path) => Some(image::open(path).context("Failed to open background image")?),
+        None => None,
+    };
+
+    // Wide gaps leave too little dark area for scanners to threshold reliably
+    let module_gap = if !(0.0..=MAX_MODULE_GAP).contains(&cli.module_gap) {
+        let clamped = cli.module_gap.clamp(0.0, MAX_MODULE_GAP);
+        eprintln!(
+            "Warning: --module-gap must be between 0.0 and {}; using {}",
+            MAX_MODULE_GAP, clamped
+        );
+        clamped
+    } else {
+        cli.module_gap
+    };
+
+    let palette = match &cli.palette {
+        Some(spec) => load_palette(spec, back_color)?,
+        None => Vec::new(),
+    };
+
+    let back_gradient = match &cli.back_gradient {
+        Some(spec) => Some(
+            gradient::parse_gradient(spec, &cli.back_gradient_direction)
+                .with_context(|| format!("Invalid back gradient: {}", spec))?,
+        ),
+        None => None,
+    };
+
+    let frame = match &cli.brand_frame {
+        Some(spec) => {
+            let color = match &cli.frame_color {
+                Some(color) => {
+                    parse_color(color).with_context(|| format!("Invalid frame color: {}", color))?
+                }
+                None => fill_color,
+            };
+            let text_color = match &cli.frame_text_color {
+                Some(color) => parse_color(color)
+                    .with_context(|| format!("Invalid frame text color: {}", color))?,
+                None => back_color,
+            };
+            Some(frame::Frame {
+                text: frame::frame_text(spec),
+                color,
+                text_color,
+            })
+        }
+        None => None,
+    };
+
+    let module_image = match &cli.module_image {
+        Some(path) => Some(
+            image::open(path)
+                .context("Failed to open module image")?
+                .to_rgba8(),
+        ),
+        None => None,
+    };
+
+    let logo_border_color = match (&cli.logo_border_color, cli.logo_border_width) {
+        (Some(color), _) => Some(
+            parse_color(color).with_context(|| format!("Invalid logo border color: {}", color))?,
+        ),
+        (None, Some(_)) => Some(fill_color),
+        (None, None) => None,
+    };
+
+    // A missing or broken font only changes how the text looks, so fall back
+    // rather than fail
+    let font = match &cli.font {
+        Some(path) => text::Font::load(path).unwrap_or_else(|e| {
+            eprintln!("Warning: {:#}; using the built-in font", e);
+            text::Font::Builtin
+        }),
+        None => text::Font::Builtin,
+    };
+
+    let opts = RenderOptions {
+        error_correction,
+        fill_color,
+        palette,
+        back_color: if cli.code_only {
+            TRANSPARENT
+        } else {
+            back_color
+        },
+        back_gradient,
+        // The padding step draws the quiet zone instead
+        border: if padding.is_some() { 0 } else { border },
+        padding,
+        border_color,
+        background,
+        module_gap,
+        module_image,
+        finder_shape: finder::Shape::from_name(&cli.finder_shape),
+        frame,
+        font,
+        logo_border_color,
+    };
+
+    if let Some(batch_path) = &cli.batch {
+        return batch::run_batch(&cli, &opts, batch_path);
+    }
+
+    if let Some(lines_path) = &cli.from_lines {
+        return batch::run_lines(&cli, &opts, lines_path);
+    }
+
+    if let Some(count) = cli.count {
+        return batch::run_count(&cli, &opts, count);
+    }
+
+    // Apply template and prefix/suffix
+    let input = cli.data.clone().unwrap_or_default();
+    let data = payload_bytes(&cli, &opts, &input)?;
+    if cli.validate_only {
+        println!("Data is valid");
+        return Ok(());
+    }
+    // Build the invite now so a bad event fails before anything is written
+    let invite = match &cli.also_ics {
+        Some(ics_path) => Some((calendar_invite(&cli, &input, ics_path)?, ics_path)),
+        None => None,
+    };
+
+    // Determine output path
+    let mut output_path = if !cli.terminal && !cli.copy && cli.output.is_none() {
+        Some(PathBuf::from(DEFAULT_OUTPUT))
+    } else {
+        cli.output.clone()
+    };
+    if !cli.emit.is_empty() && output_path.is_none() {
+        return Err(anyhow::anyhow!(
+            "--emit writes files next to --output; add -o when using --terminal or --copy"
+        ));
+    }
+
+    // Display in terminal if requested
+    if cli.terminal {
+        let dark_terminal = terminal_is_dark(&cli.terminal_theme);
+        if cli.symbology == "datamatrix" {
+            data_matrix::print_terminal(&data, dark_terminal)?;
+        } else {
+            let error_correction = cli.error_correction_for(&opts, &data);
+            print_terminal(&data, error_correction, cli.encode_opts(), dark_terminal)?;
+        }
+    }
+
+    if cli.copy {
+        let cli = sized_for_output(&cli, &opts, &data)?;
+        check_module_pixels(&cli)?;
+        let img = if cli.symbology == "datamatrix" {
+            data_matrix::render_image(&cli, &opts, &data)?
+        } else {
+            let code = encode_data(&cli, &opts, &data)?;
+            render_image(&cli, &opts, &code, &data)?
+        };
+
+        match clipboard::copy_image(&img) {
+            Ok(()) => println!("QR code copied to clipboard"),
+            Err(e) => {
+                eprintln!("Warning: {:#}; saving to a file instead", e);
+                output_path.get_or_insert_with(|| PathBuf::from(DEFAULT_OUTPUT));
+            }
+        }
+    }
+
+    // Save to file if output path provided
+    if let Some(output) = output_path {
+        let output = output::output_path(&cli, &output, &data);
+        let summaries = write_output(&cli, &opts, &data, &output)?;
+        if let Some(manifest_path) = &cli.manifest {
+            let entries: Vec<_> = summaries
+                .iter()
+                .map(|summary| output::manifest_entry(summary, &data))
+                .collect();
+            output::write_manifest(manifest_path, &entries, cli.write_retries)?;
+        }
+        if cli.json_output {
+            match summaries.as_slice() {
+                [summary] => println!("{}", serde_json::to_string_pretty(summary)?),
+                _ => println!("{}", serde_json::to_string_pretty(&summaries)?),
+            }
+        }
+        let paths: Vec<&str> = summaries
+            .iter()
+            .filter_map(|summary| summary["path"].as_str())
+            .collect();
+        if !cli.json_output {
+            for path in &paths {
+                println!("QR code saved to: {}", path);
+            }
+        }
+
+        if cli.preview {
+            preview_output(Path::new(paths[0]));
+        }
+    }
+
+    if let Some((ics, ics_path)) = invite {
+        write_ics(&cli, &ics, ics_path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plain black-on-white options with a 4-module border at EC M, for
+    /// tests to override with struct-update syntax
+    fn test_render_options() -> RenderOptions {
+        RenderOptions {
+            error_correction: EcChoice::Level(EcLevel::M),
+            fill_color: Rgba([0, 0, 0, 255]),
+            palette: Vec::new(),
+            back_color: Rgba([255, 255, 255, 255]),
+            border: 4,
+            padding: None,
+            border_color: Rgba([255, 255, 255, 255]),
+            background: None,
+            module_gap: 0.0,
+            module_image: None,
+            back_gradient: None,
+            finder_shape: finder::Shape::Square,
+            frame: None,
+            font: text::Font::Builtin,
+            logo_border_color: None,
+        }
+    }
+
+    /// Encode and draw in one step with default encoding settings
+    fn create_qr_code(
+        data: &str,
+        error_correction: EcLevel,
+        size: u32,
+        border: u32,
+        fill_color: Rgba<u8>,
+        back_color: Rgba<u8>,
+    ) -> Result<RgbaImage> {
+        let code = QrCode::with_error_correction_level(data, error_correction)
+            .context("Failed to generate QR code")?;
+
+        Ok(draw_qr_code(
+            &code,
+            size,
+            border,
+            &[fill_color],
+            back_color,
+            0.0,
+        ))
+    }
+
+    #[test]
+    fn test_parse_error_correction_l() {
+        let result = parse_error_correction("L").unwrap();
+        assert_eq!(result, EcLevel::L);
+    }
+
+    #[test]
+    fn test_parse_error_correction_m() {
+        let result = parse_error_correction("M").unwrap();
+        assert_eq!(result, EcLevel::M);
+    }
+
+    #[test]
+    fn test_parse_error_correction_q() {
+        let result = parse_error_correction("Q").unwrap();
+        assert_eq!(result, EcLevel::Q);
+    }
+
+    #[test]
+    fn test_parse_error_correction_h() {
+        let result = parse_error_correction("H").unwrap();
+        assert_eq!(result, EcLevel::H);
+    }
+
+    #[test]
+    fn test_parse_error_correction_lowercase() {
+        let result = parse_error_correction("l").unwrap();
+        assert_eq!(result, EcLevel::L);
+    }
+
+    #[test]
+    fn test_parse_error_correction_invalid() {
+        let result = parse_error_correction("X");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_seconds() {
+        assert_eq!(parse_seconds("1").unwrap(), Duration::from_secs(1));
+        assert_eq!(parse_seconds("0.5").unwrap(), Duration::from_millis(500));
+        assert!(parse_seconds("0").is_err());
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("soon").is_err());
+    }
+
+    #[test]
+    fn test_parse_ec_choice_auto() {
+        assert_eq!(parse_ec_choice("auto").unwrap(), EcChoice::Auto);
+        assert_eq!(parse_ec_choice("AUTO").unwrap(), EcChoice::Auto);
+        assert_eq!(parse_ec_choice("q").unwrap(), EcChoice::Level(EcLevel::Q));
+        assert!(parse_ec_choice("X").is_err());
+    }
+
+    #[test]
+    fn test_auto_error_correction_short_data_gets_h() {
+        assert_eq!(
+            auto_error_correction(b"hi", EncodeOptions::default()),
+            EcLevel::H
+        );
+    }
+
+    #[test]
+    fn test_auto_error_correction_near_capacity() {
+        // 14 bytes fill version 1 at M, so Q and H would need version 2
+        assert_eq!(
+            auto_error_correction(b"hello world!!!", EncodeOptions::default()),
+            EcLevel::M
+        );
+        // 17 bytes only fit version 1 at L
+        assert_eq!(
+            auto_error_correction(b"hello world!!!!!!", EncodeOptions::default()),
+            EcLevel::L
+        );
+    }
+
+    #[test]
+    fn test_downgrade_error_correction() {
+        let opts = EncodeOptions::default();
+        // Version 40 holds 2953 bytes at L but only 2331 at M
+        let long = "abcdefghij".repeat(290);
+        assert_eq!(
+            downgrade_error_correction(long.as_bytes(), EcLevel::H, opts),
+            EcLevel::L
+        );
+        assert_eq!(
+            downgrade_error_correction(b"hello", EcLevel::Q, opts),
+            EcLevel::Q
+        );
+        // Never steps up, and gives up at L
+        let too_long = "abcdefghij".repeat(400);
+        assert_eq!(
+            downgrade_error_correction(too_long.as_bytes(), EcLevel::M, opts),
+            EcLevel::M
+        );
+    }
+
+    #[test]
+    fn test_resolve_border_keeps_quiet_zone() {
+        assert_eq!(resolve_border(&Cli::parse_from(["qrgen", "x"])), 4);
+        assert_eq!(
+            resolve_border(&Cli::parse_from(["qrgen", "x", "-b", "10"])),
+            10
+        );
+        // A narrow border is widened to the quiet zone unless that is lowered
+        assert_eq!(
+            resolve_border(&Cli::parse_from(["qrgen", "x", "-b", "1"])),
+            4
+        );
+        let cli = Cli::parse_from(["qrgen", "x", "-b", "1", "--quiet-zone-modules", "2"]);
+        assert_eq!(resolve_border(&cli), 2);
+        let cli = Cli::parse_from(["qrgen", "x", "-b", "1", "--quiet-zone-modules", "0"]);
+        assert_eq!(resolve_border(&cli), 1);
+        // Dropping the quiet zone still has to be asked for explicitly
+        let cli = Cli::parse_from(["qrgen", "x", "-b", "1", "--no-quiet-zone"]);
+        assert_eq!(resolve_border(&cli), 0);
+    }
+
+    #[test]
+    fn test_resolve_padding() {
+        let cli = Cli::parse_from(["qrgen", "x"]);
+        assert_eq!(resolve_padding(&cli, 4).unwrap(), None);
+
+        let cli = Cli::parse_from(["qrgen", "x", "--border-left", "10", "--border-top", "6"]);
+        assert_eq!(
+            resolve_padding(&cli, 4).unwrap(),
+            Some(layout::Sides {
+                top: 6,
+                right: 4,
+                bottom: 4,
+                left: 10,
+            })
+        );
+
+        let cli = Cli::parse_from(["qrgen", "x", "--border-right", "2"]);
+        assert!(resolve_padding(&cli, 4).is_err());
+
+        let cli = Cli::parse_from(["qrgen", "x", "--border-right", "2", "--no-quiet-zone"]);
+        assert_eq!(resolve_padding(&cli, 0).unwrap().unwrap().right, 2);
+
+        let cli = Cli::parse_from([
+            "qrgen",
+            "x",
+            "--border-right",
+            "2",
+            "--quiet-zone-modules",
+            "2",
+        ]);
+        assert_eq!(resolve_padding(&cli, 4).unwrap().unwrap().right, 2);
+    }
+
+    #[test]
+    fn test_resolve_error_correction_default() {
+        assert_eq!(
+            resolve_error_correction(None, false, None),
+            EcChoice::Level(EcLevel::M)
+        );
+    }
+
+    #[test]
+    fn test_resolve_error_correction_logo_raises_to_q() {
+        assert_eq!(
+            resolve_error_correction(None, true, None),
+            EcChoice::Level(EcLevel::Q)
+        );
+    }
+
+    #[test]
+    fn test_resolve_error_correction_template_default() {
+        assert_eq!(
+            resolve_error_correction(None, false, Some("wifi")),
+            EcChoice::Level(EcLevel::L)
+        );
+        assert_eq!(
+            resolve_error_correction(None, false, Some("spotify")),
+            EcChoice::Level(EcLevel::H)
+        );
+        // A logo still needs at least Q
+        assert_eq!(
+            resolve_error_correction(None, true, Some("wifi")),
+            EcChoice::Level(EcLevel::Q)
+        );
+        assert_eq!(
+            resolve_error_correction(None, true, Some("app")),
+            EcChoice::Level(EcLevel::H)
+        );
+    }
+
+    #[test]
+    fn test_resolve_error_correction_explicit_wins() {
+        assert_eq!(
+            resolve_error_correction(Some(EcChoice::Level(EcLevel::M)), true, None),
+            EcChoice::Level(EcLevel::M)
+        );
+        assert_eq!(
+            resolve_error_correction(Some(EcChoice::Level(EcLevel::H)), false, Some("wifi")),
+            EcChoice::Level(EcLevel::H)
+        );
+        assert_eq!(
+            resolve_error_correction(Some(EcChoice::Auto), true, Some("vcard")),
+            EcChoice::Auto
+        );
+    }
+
+    #[test]
+    fn test_parse_color_black() {
+        let color = parse_color("black").unwrap();
+        assert_eq!(color, Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_white() {
+        let color = parse_color("white").unwrap();
+        assert_eq!(color, Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_red() {
+        let color = parse_color("red").unwrap();
+        assert_eq!(color, Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_blue() {
+        let color = parse_color("blue").unwrap();
+        assert_eq!(color, Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_hex_lowercase() {
+        let color = parse_color("#ff5733").unwrap();
+        assert_eq!(color, Rgba([255, 87, 51, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_hex_uppercase() {
+        let color = parse_color("#FF5733").unwrap();
+        assert_eq!(color, Rgba([255, 87, 51, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_hex_mixed_case() {
+        let color = parse_color("#FfA500").unwrap();
+        assert_eq!(color, Rgba([255, 165, 0, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_darkblue() {
+        let color = parse_color("darkblue").unwrap();
+        assert_eq!(color, Rgba([0, 0, 139, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_invalid() {
+        let result = parse_color("notacolor");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_color_invalid_hex() {
+        let result = parse_color("#GGGGGG");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_color_invalid_hex_length() {
+        let result = parse_color("#FFFF");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_color_hex_shorthand() {
+        let color = parse_color("#FFF").unwrap();
+        assert_eq!(color, Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_hex_shorthand_red() {
+        let color = parse_color("#f00").unwrap();
+        assert_eq!(color, Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_hex_shorthand_mixed() {
+        let color = parse_color("#abc").unwrap();
+        assert_eq!(color, Rgba([0xaa, 0xbb, 0xcc, 255]));
+    }
+
+    #[test]
+    fn test_parse_color_invalid_hex_shorthand() {
+        let result = parse_color("#GGG");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_create_qr_code_basic() {
+        let img = create_qr_code(
+            "test",
+            EcLevel::M,
+            10,
+            4,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        );
+        assert!(img.is_ok());
+        let img = img.unwrap();
+        assert!(img.width() > 0);
+        assert!(img.height() > 0);
+    }
+
+    #[test]
+    fn test_create_qr_code_url() {
+        let img = create_qr_code(
+            "https://example.com",
+            EcLevel::M,
+            10,
+            4,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        );
+        assert!(img.is_ok());
+    }
+
+    #[test]
+    fn test_create_qr_code_custom_size() {
+        let img = create_qr_code(
+            "test",
+            EcLevel::M,
+            20,
+            4,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        )
+        .unwrap();
+
+        // Larger size should produce larger image
+        let img_small = create_qr_code(
+            "test",
+            EcLevel::M,
+            10,
+            4,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        )
+        .unwrap();
+
+        assert!(img.width() > img_small.width());
+    }
+
+    #[test]
+    fn test_create_qr_code_custom_border() {
+        let img = create_qr_code(
+            "test",
+            EcLevel::M,
+            10,
+            10,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        )
+        .unwrap();
+
+        let img_small_border = create_qr_code(
+            "test",
+            EcLevel::M,
+            10,
+            4,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        )
+        .unwrap();
+
+        assert!(img.width() > img_small_border.width());
+    }
+
+    #[test]
+    fn test_create_qr_code_all_error_levels() {
+        for level in [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H] {
+            let img = create_qr_code(
+                "test",
+                level,
+                10,
+                4,
+                Rgba([0, 0, 0, 255]),
+                Rgba([255, 255, 255, 255]),
+            );
+            assert!(img.is_ok());
+        }
+    }
+
+    #[test]
+    fn test_create_qr_code_custom_colors() {
+        let img = create_qr_code(
+            "test",
+            EcLevel::M,
+            10,
+            4,
+            Rgba([0, 0, 255, 255]),   // Blue
+            Rgba([255, 255, 0, 255]), // Yellow
+        );
+        assert!(img.is_ok());
+    }
+
+    #[test]
+    fn test_create_qr_code_empty_string() {
+        let img = create_qr_code(
+            "",
+            EcLevel::M,
+            10,
+            4,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        );
+        assert!(img.is_ok());
+    }
+
+    #[test]
+    fn test_create_qr_code_long_text() {
+        let long_text = "A".repeat(1000);
+        let img = create_qr_code(
+            &long_text,
+            EcLevel::M,
+            10,
+            4,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        );
+        assert!(img.is_ok());
+    }
+
+    #[test]
+    fn test_create_qr_code_special_characters() {
+        let img = create_qr_code(
+            "Hello! @#$%^&*() 你好 🎉",
+            EcLevel::M,
+            10,
+            4,
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        );
+        assert!(img.is_ok());
+    }
+
+    #[test]
+    fn test_feather_edges_ramps_alpha() {
+        let mut img = RgbaImage::from_pixel(20, 20, Rgba([255, 255, 255, 255]));
+        feather_edges(&mut img, 4);
+
+        assert_eq!(img.get_pixel(0, 10)[3], 32);
+        assert_eq!(img.get_pixel(3, 10)[3], 223);
+        assert_eq!(img.get_pixel(10, 10)[3], 255);
+    }
+
+    fn logo_style<'a>(feather: u32, shape: &'a str, position: &'a str) -> LogoStyle<'a> {
+        LogoStyle {
+            feather,
+            shape,
+            position,
+            caption: None,
+            font: &text::Font::Builtin,
+            ring: None,
+        }
+    }
+
+    /// An 8x8 logo of a single `color`, saved as logo.png in `dir`
+    fn logo_file(dir: &tempfile::TempDir, color: Rgba<u8>) -> PathBuf {
+        let path = dir.path().join("logo.png");
+        RgbaImage::from_pixel(8, 8, color).save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_embed_logo_feather_blends_box_edge() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let logo_path = logo_file(&dir, Rgba([255, 0, 0, 255]));
+
+        // On a 100px black canvas the 24px box starts at x = 38
+        let canvas = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 0, 255]));
+        let hard = embed_logo(
+            canvas.clone(),
+            &logo_path,
+            &logo_style(0, "square", "center"),
+            0,
+            1,
+        )
+        .unwrap();
+        let soft =
+            embed_logo(canvas, &logo_path, &logo_style(2, "square", "center"), 0, 1).unwrap();
+
+        assert_eq!(hard.get_pixel(38, 50)[0], 255);
+        let edge = soft.get_pixel(38, 50)[0];
+        assert!(edge > 0 && edge < 255, "edge value {}", edge);
+        // The logo itself stays opaque
+        assert_eq!(*soft.get_pixel(50, 50), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_embed_logo_top_left() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let logo_path = logo_file(&dir, Rgba([255, 0, 0, 255]));
+
+        // A version 10 code (57 modules) at size 4 with a 4-module quiet zone
+        // is 260px; the 62px box starts 16 + 8 * 4 = 48px in
+        let canvas = RgbaImage::from_pixel(260, 260, Rgba([0, 0, 0, 255]));
+        let img = embed_logo(
+            canvas,
+            &logo_path,
+            &logo_style(0, "square", "top-left"),
+            16,
+            4,
+        )
+        .unwrap();
+
+        let white = Rgba([255, 255, 255, 255]);
+        assert_eq!(*img.get_pixel(48, 48), white);
+        assert_eq!(*img.get_pixel(47, 47), Rgba([0, 0, 0, 255]));
+        let lit: Vec<(u32, u32)> = img
+            .enumerate_pixels()
+            .filter(|(_, _, p)| **p != Rgba([0, 0, 0, 255]))
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(lit.iter().all(|&(x, y)| x < 130 && y < 130));
+    }
+
+    #[test]
+    fn test_embed_logo_caption() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let logo_path = logo_file(&dir, Rgba([255, 0, 0, 255]));
+
+        // On a 100px blue canvas the box is 24px wide and 24 + 7px of caption
+        // + 2px of padding tall, so it spans 38..62 by 33..66
+        let blue = Rgba([0, 0, 255, 255]);
+        let canvas = RgbaImage::from_pixel(100, 100, blue);
+        let style = LogoStyle {
+            caption: Some("HI"),
+            ..logo_style(0, "square", "center")
+        };
+        let img = embed_logo(canvas, &logo_path, &style, 0, 1).unwrap();
+
+        assert_eq!(
+            logo_box_size(100, Some("HI"), &text::Font::Builtin),
+            (24, 33)
+        );
+        let region: Vec<Rgba<u8>> = (33..66)
+            .flat_map(|y| (38..62).map(move |x| (x, y)))
+            .map(|(x, y)| *img.get_pixel(x, y))
+            .collect();
+        assert!(region.contains(&Rgba([255, 0, 0, 255])));
+        assert!(region.contains(&Rgba([0, 0, 0, 255])));
+        assert!(!region.contains(&blue));
+        // The caption sits below the logo square
+        assert!((57..64).any(|y| *img.get_pixel(45, y) == Rgba([0, 0, 0, 255])));
+    }
+
+    #[test]
+    fn test_embed_logo_ring() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let logo_path = logo_file(&dir, Rgba([0, 255, 0, 255]));
+
+        // The 24px box spans 38..62; a 3px ring takes 35..38 and 62..65
+        let blue = Rgba([0, 0, 255, 255]);
+        let red = Rgba([255, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        let ringed = |shape| {
+            let style = LogoStyle {
+                ring: Some((3, red)),
+                ..logo_style(0, shape, "center")
+            };
+            let canvas = RgbaImage::from_pixel(100, 100, blue);
+            embed_logo(canvas, &logo_path, &style, 0, 1).unwrap()
+        };
+
+        let img = ringed("square");
+        for (x, y) in [(37, 50), (35, 50), (62, 50), (50, 37), (50, 64), (35, 35)] {
+            assert_eq!(*img.get_pixel(x, y), red, "({x}, {y})");
+        }
+        assert_eq!(*img.get_pixel(34, 50), blue);
+        assert_eq!(*img.get_pixel(65, 50), blue);
+        assert_eq!(*img.get_pixel(38, 50), white);
+
+        // Circular logos get a circular ring, leaving the corners alone
+        let img = ringed("circle");
+        assert_eq!(*img.get_pixel(36, 50), red);
+        assert_eq!(*img.get_pixel(38, 50), white);
+        assert_eq!(*img.get_pixel(35, 35), blue);
+    }
+
+    #[test]
+    fn test_logo_ring_counts_towards_coverage() {
+        let font = text::Font::Builtin;
+        let plain = logo_coverage_fraction(21, 4, 10, None, &font, 0);
+        let ringed = logo_coverage_fraction(21, 4, 10, None, &font, 10);
+        assert!(ringed > plain);
+
+        // At version 1 the 69px box hides about 11% of the code, which M
+        // recovers; a 20px ring takes it to about 27%, which only H does
+        let cli = Cli::parse_from([
+            "qrgen",
+            "x",
+            "--logo",
+            "logo.png",
+            "--logo-border-width",
+            "20",
+        ]);
+        let opts = RenderOptions {
+            logo_border_color: Some(Rgba([255, 0, 0, 255])),
+            ..test_render_options()
+        };
+        assert!(check_logo_ring(&cli, &opts, 21, EcLevel::H).is_ok());
+        assert!(check_logo_ring(&cli, &opts, 21, EcLevel::M).is_err());
+    }
+
+    #[test]
+    fn test_logo_caption_adds_coverage() {
+        let plain = logo_coverage_fraction(21, 4, 10, None, &text::Font::Builtin, 0);
+        let captioned = logo_coverage_fraction(21, 4, 10, Some("Scan me"), &text::Font::Builtin, 0);
+        assert!(captioned > plain);
+    }
+
+    #[test]
+    fn test_logo_corner_overlaps_finder() {
+        // Version 1 at size 10: 290px image, 40px quiet zone, 69px box
+        let inset = 40 + 8 * 10;
+        let top_right = logo_origin("top-right", 290, 290, (69, 69), inset).unwrap();
+        assert!(overlaps_finder(top_right, (69, 69), (290, 290), 40, 10));
+
+        // Version 10 leaves room in the corner
+        let width = (57 + 8) * 10;
+        let top_left = logo_origin("top-left", width, width, (156, 156), inset).unwrap();
+        assert!(!overlaps_finder(
+            top_left,
+            (156, 156),
+            (width, width),
+            40,
+            10
+        ));
+    }
+
+    #[test]
+    fn test_embed_logo_circle() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let logo_path = logo_file(&dir, Rgba([255, 0, 0, 255]));
+
+        // On a 100px black canvas the 24px box spans 38..62 and the 20px logo 40..60
+        let canvas = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 0, 255]));
+        let square = embed_logo(
+            canvas.clone(),
+            &logo_path,
+            &logo_style(0, "square", "center"),
+            0,
+            1,
+        )
+        .unwrap();
+        let circle =
+            embed_logo(canvas, &logo_path, &logo_style(0, "circle", "center"), 0, 1).unwrap();
+
+        let black = Rgba([0, 0, 0, 255]);
+        assert_eq!(*square.get_pixel(38, 38), Rgba([255, 255, 255, 255]));
+        assert_eq!(*square.get_pixel(40, 40), Rgba([255, 0, 0, 255]));
+        for (x, y) in [(38, 38), (61, 38), (38, 61), (61, 61)] {
+            assert_eq!(*circle.get_pixel(x, y), black, "box corner ({}, {})", x, y);
+        }
+        // Inside the box circle but outside the logo circle is white
+        assert_eq!(*circle.get_pixel(44, 41), Rgba([255, 255, 255, 255]));
+        assert_eq!(*circle.get_pixel(50, 50), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_dominant_color_blue_logo() {
+        // Mostly blue with a white background border and a red accent
+        let mut logo = RgbaImage::from_pixel(20, 20, Rgba([255, 255, 255, 255]));
+        for y in 2..18 {
+            for x in 2..18 {
+                logo.put_pixel(x, y, Rgba([20, 60, 200, 255]));
+            }
+        }
+        for x in 2..18 {
+            logo.put_pixel(x, 10, Rgba([220, 20, 20, 255]));
+        }
+
+        let color = dominant_color(&logo);
+        assert!(
+            color[2] > 150 && color[0] < 80 && color[1] < 100,
+            "{:?}",
+            color
+        );
+    }
+
+    #[test]
+    fn test_dominant_color_ignores_transparent_and_white() {
+        let logo = RgbaImage::from_pixel(4, 4, Rgba([0, 200, 0, 0]));
+        assert_eq!(dominant_color(&logo), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_contrast_ratio() {
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        assert!((contrast_ratio(black, white) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(white, white) - 1.0).abs() < 0.01);
+        assert!(contrast_ratio(Rgba([255, 255, 0, 255]), white) < MIN_CONTRAST_RATIO);
+    }
+
+    /// Hue in degrees, for checking that adjusted colors keep their tint
+    fn hue(color: Rgba<u8>) -> f64 {
+        let [r, g, b] = [color[0], color[1], color[2]].map(|c| c as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        }
+    }
+
+    #[test]
+    fn test_adjust_contrast_gray_pair() {
+        let gray = Rgba([128, 128, 128, 255]);
+        let lightgray = parse_color("lightgray").unwrap();
+        assert!(contrast_ratio(gray, lightgray) < AUTO_CONTRAST_RATIO);
+
+        let (fill, back) = adjust_contrast(gray, lightgray, AUTO_CONTRAST_RATIO);
+        assert!(contrast_ratio(fill, back) >= AUTO_CONTRAST_RATIO);
+        // Still grays: darker fill, lighter back, no tint introduced
+        assert!(fill[0] < gray[0] && back[0] > lightgray[0]);
+        assert!(fill[0] == fill[1] && fill[1] == fill[2]);
+        assert!(back[0] == back[1] && back[1] == back[2]);
+    }
+
+    #[test]
+    fn test_adjust_contrast_keeps_hues() {
+        let fill = Rgba([96, 128, 192, 255]);
+        let back = Rgba([160, 200, 230, 255]);
+        let (adjusted_fill, adjusted_back) = adjust_contrast(fill, back, AUTO_CONTRAST_RATIO);
+        assert!(contrast_ratio(adjusted_fill, adjusted_back) >= AUTO_CONTRAST_RATIO);
+        assert!((hue(adjusted_fill) - hue(fill)).abs() < 5.0);
+        assert!((hue(adjusted_back) - hue(back)).abs() < 5.0);
+    }
+
+    #[test]
+    fn test_adjust_contrast_leaves_good_pairs_and_inverted_codes() {
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        assert_eq!(
+            adjust_contrast(black, white, AUTO_CONTRAST_RATIO),
+            (black, white)
+        );
+
+        // A light fill on a dark back gets lighter, not darker
+        let fill = Rgba([150, 150, 150, 255]);
+        let back = Rgba([90, 90, 90, 255]);
+        let (adjusted_fill, adjusted_back) = adjust_contrast(fill, back, AUTO_CONTRAST_RATIO);
+        assert!(adjusted_fill[0] > fill[0] && adjusted_back[0] < back[0]);
+    }
+
+    #[test]
+    fn test_round_corners() {
+        let back = Rgba([255, 255, 255, 255]);
+        let img = RgbaImage::from_pixel(100, 100, back);
+        let img = round_corners(img, 20, 40).unwrap();
+
+        for (x, y) in [(0, 0), (99, 0), (0, 99), (99, 99), (2, 3)] {
+            assert_eq!(img.get_pixel(x, y)[3], 0, "corner pixel ({}, {})", x, y);
+        }
+        // Edges away from the corners and the center are untouched
+        assert_eq!(*img.get_pixel(0, 50), back);
+        assert_eq!(*img.get_pixel(50, 0), back);
+        assert_eq!(*img.get_pixel(50, 50), back);
+        assert_eq!(*img.get_pixel(15, 15), back);
+    }
+
+    #[test]
+    fn test_round_corners_zero_is_noop() {
+        let img = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        assert_eq!(round_corners(img.clone(), 0, 0).unwrap(), img);
+    }
+
+    #[test]
+    fn test_round_corners_rejects_radius_into_code() {
+        let img = RgbaImage::from_pixel(290, 290, Rgba([255, 255, 255, 255]));
+        // A 40px quiet zone allows up to 138px before the code corner is cut
+        assert!(round_corners(img.clone(), 130, 40).is_ok());
+        assert!(round_corners(img, 150, 40).is_err());
+    }
+
+    #[test]
+    fn test_check_symbology_options() {
+        let cli = Cli::parse_from(["qrgen", "x", "--symbology", "datamatrix"]);
+        assert!(check_symbology_options(&cli).is_ok());
+
+        let cli = Cli::parse_from(["qrgen", "x", "--symbology", "datamatrix", "--verify"]);
+        let err = check_symbology_options(&cli).unwrap_err();
+        assert!(err.to_string().contains("--verify"));
+
+        let cli = Cli::parse_from(["qrgen", "x", "--symbology", "datamatrix", "--code-only"]);
+        let err = check_symbology_options(&cli).unwrap_err();
+        assert!(err.to_string().contains("--code-only"));
+
+        let cli = Cli::parse_from(["qrgen", "x", "--verify"]);
+        assert!(check_symbology_options(&cli).is_ok());
+    }
+
+    #[test]
+    fn test_module_size_for_print() {
+        // 50 mm at 300 DPI is 590.55 px; 29 modules gives 20 px each
+        assert_eq!(module_size_for_print(50.0, 300, 29).unwrap(), 20);
+        assert!(module_size_for_print(1.0, 72, 29).is_err());
+    }
+
+    #[test]
+    fn test_module_size_for_max() {
+        // 29 modules at 10 px is 290 px, over a 200 px cap
+        assert_eq!(module_size_for_max(10, 29, 200), 6);
+        assert_eq!(module_size_for_max(5, 29, 200), 5);
+        assert_eq!(module_size_for_max(10, 125, 100), 1);
+    }
+
+    #[test]
+    fn test_sized_for_output() {
+        let cli = Cli::parse_from(["qrgen", "x", "--physical-size", "25.4", "--dpi", "290"]);
+        let opts = test_render_options();
+
+        // Version 1: 21 modules + 8 quiet zone = 29, so one inch at 290 DPI is 10 px each
+        let sized = sized_for_output(&cli, &opts, b"x").unwrap();
+        assert_eq!(sized.size, 10);
+    }
+
+    #[test]
+    fn test_sized_for_output_counts_frame() {
+        let cli = Cli::parse_from(["qrgen", "x", "--max-dimension", "200"]);
+        let opts = RenderOptions {
+            frame: Some(frame::Frame {
+                text: "SCAN ME".to_string(),
+                color: Rgba([0, 0, 0, 255]),
+                text_color: Rgba([255, 255, 255, 255]),
+            }),
+            ..test_render_options()
+        };
+
+        // 29 modules alone would allow 6 px, but the frame and tab push a
+        // 6 px code to 186x225
+        let sized = sized_for_output(&cli, &opts, b"x").unwrap();
+        assert_eq!(sized.size, 5);
+        let (width, height) = output_dimensions(&opts, 21, sized.size).unwrap();
+        assert!(width <= 200 && height <= 200);
+    }
+
+    #[test]
+    fn test_parse_separator() {
+        assert_eq!(parse_separator(",").unwrap(), ',');
+        assert_eq!(parse_separator("|").unwrap(), '|');
+        assert_eq!(parse_separator("tab").unwrap(), '\t');
+        assert_eq!(parse_separator("\\t").unwrap(), '\t');
+        assert_eq!(parse_separator("\t").unwrap(), '\t');
+        assert!(parse_separator("").is_err());
+        assert!(parse_separator("||").is_err());
+    }
+
+    #[test]
+    fn test_separator_vcard_name_with_comma() {
+        let cli = Cli::parse_from(["qrgen", "x", "--template", "vcard", "--separator", "|"]);
+        let result = prepare_payload(&cli, "Doe, John|+1234567890|john@example.com").unwrap();
+        assert!(result.contains("FN:Doe, John\n"));
+        assert!(result.contains("TEL:+1234567890\n"));
+        assert!(result.contains("EMAIL:john@example.com\n"));
+    }
+
+    #[test]
+    fn test_check_payload_length() {
+        let cli = Cli::parse_from(["qrgen", "x", "--max-payload-bytes", "8"]);
+        assert!(check_payload_length(&cli, b"12345678").is_ok());
+        let err = check_payload_length(&cli, b"123456789").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Payload is 9 bytes, more than the --max-payload-bytes limit of 8"
+        );
+
+        let cli = Cli::parse_from(["qrgen", "x"]);
+        assert!(check_payload_length(&cli, &[0; 4096]).is_ok());
+        assert!(Cli::try_parse_from(["qrgen", "x", "--max-payload-bytes", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_wifi_options_need_wifi_template() {
+        for flag in ["--strict-wifi-encryption", "--wifi-hidden"] {
+            assert!(Cli::try_parse_from(["qrgen", "x", flag]).is_err());
+            let cli = Cli::parse_from(["qrgen", "x", flag, "--template", "vcard"]);
+            assert!(check_wifi_options(&cli).is_err());
+            let cli = Cli::parse_from(["qrgen", "x", flag, "--template", "wifi"]);
+            assert!(check_wifi_options(&cli).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_verify_unique_needs_many_codes() {
+        assert!(Cli::try_parse_from(["qrgen", "x", "--verify-unique"]).is_err());
+        for mode in [["--batch", "rows.csv"], ["--from-lines", "rows.txt"]] {
+            assert!(Cli::try_parse_from(["qrgen", mode[0], mode[1], "--verify-unique"]).is_ok());
+        }
+        let cli =
+            Cli::try_parse_from(["qrgen", "T{n}", "--count", "3", "--verify-unique=warn"]).unwrap();
+        assert_eq!(cli.verify_unique.as_deref(), Some("warn"));
+    }
+
+    #[test]
+    fn test_emit_targets() {
+        let cli = Cli::parse_from(["qrgen", "x", "--emit", "png,json,svg,png"]);
+        assert_eq!(
+            emit_targets(Path::new("out/code.png"), &cli.emit),
+            vec![
+                (PathBuf::from("out/code.png"), OutputFormat::Png),
+                (PathBuf::from("out/code.json"), OutputFormat::Json),
+                (PathBuf::from("out/code.svg"), OutputFormat::Svg),
+            ]
+        );
+        assert!(Cli::try_parse_from(["qrgen", "x", "--emit", "png,gif"]).is_err());
+        assert!(
+            Cli::try_parse_from(["qrgen", "x", "--emit", "png", "--output-format", "svg"]).is_err()
+        );
+    }
+
+    // Opens a real viewer where one exists, so only run with --features preview-tests
+    #[cfg(feature = "preview-tests")]
+    #[test]
+    fn test_open_preview_is_non_fatal() {
+        open_preview(Path::new("/nonexistent/qrgen-preview.png"));
+        preview_output(Path::new("/nonexistent/qrgen-preview.png"));
+    }
+
+    #[test]
+    fn test_check_canvas_size() {
+        // 21 modules + 8 border at 10px is well under the default limit
+        assert!(check_canvas_size(21, 4, 10, 100).is_ok());
+
+        let err = check_canvas_size(21, 4, 100_000, 100).unwrap_err();
+        assert!(err.to_string().contains("use --size 176 or smaller"));
+
+        // A limit too large to convert to bytes doesn't overflow
+        assert!(check_canvas_size(21, 4, 10, u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_render_image_counts_frame_in_max_size() {
+        // 29 modules at 100 px is a 2900 px square, ~32 MB; the frame band
+        // and tab make it 3100x3750, squared to ~53 MB
+        let cli = Cli::parse_from(["qrgen", "x", "--size", "100", "--max-size", "40"]);
+        let opts = test_render_options();
+        let code = encode_data(&cli, &opts, b"x").unwrap();
+        assert!(check_canvas_size(code.width() as u32, 4, 100, 40).is_ok());
+
+        let framed = RenderOptions {
+            frame: Some(frame::Frame {
+                text: "SCAN ME".to_string(),
+                color: Rgba([0, 0, 0, 255]),
+                text_color: Rgba([255, 255, 255, 255]),
+            }),
+            ..test_render_options()
+        };
+        let err = render_image(&cli, &framed, &code, b"x").unwrap_err();
+        assert!(err.to_string().contains("--max-size"));
+    }
+
+    #[test]
+    fn test_render_image_absurd_size_errors() {
+        let cli = Cli::parse_from(["qrgen", "test", "--size", "100000"]);
+        let opts = RenderOptions {
+            error_correction: EcChoice::Level(EcLevel::L),
+            ..test_render_options()
+        };
+
+        let code = encode_data(&cli, &opts, b"test").unwrap();
+        assert!(render_image(&cli, &opts, &code, b"test").is_err());
+    }
+
+    #[test]
+    fn test_dither_blends_edges_keeps_centers() {
+        let code = QrCode::new("dither test").unwrap();
+        let fill = Rgba([0, 0, 0, 255]);
+        let photo =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 10, Rgba([200, 50, 50, 255])));
+
+        let solid = draw_on_background(&code, 12, 4, &[fill], &photo, 0.0, false);
+        let dithered = draw_on_background(&code, 12, 4, &[fill], &photo, 0.0, true);
+        assert_ne!(solid, dithered);
+
+        let qr_width = code.width() as u32;
+        let mut edge_changed = false;
+        for y in 0..qr_width {
+            for x in 0..qr_width {
+                if code[(x as usize, y as usize)] != Color::Dark {
+                    continue;
+                }
+                let (px, py) = ((x + 4) * 12, (y + 4) * 12);
+                assert_eq!(
+                    *dithered.get_pixel(px + 6, py + 6),
+                    fill,
+                    "center of ({}, {})",
+                    x,
+                    y
+                );
+                if (0..12).any(|dx| *dithered.get_pixel(px + dx, py) != fill) {
+                    edge_changed = true;
+                    assert!(!code.is_functional(x as usize, y as usize));
+                }
+            }
+        }
+        assert!(edge_changed);
+    }
+
+    #[test]
+    fn test_module_gap_reduces_filled_pixels() {
+        let code = QrCode::new("gap test").unwrap();
+        let fill = Rgba([0, 0, 0, 255]);
+        let back = Rgba([255, 255, 255, 255]);
+        let count_fill = |img: &RgbaImage| img.pixels().filter(|&&p| p == fill).count();
 
-        // Embed logo if provided
-        if let Some(logo_path) = &cli.logo {
-            img = embed_logo(img, logo_path)?;
-        }
+        let solid = draw_qr_code(&code, 10, 4, &[fill], back, 0.0);
+        let gapped = draw_qr_code(&code, 10, 4, &[fill], back, 0.2);
 
-        img.save(&output)
-            .with_context(|| format!("Failed to save QR code to {:?}", output))?;
-        println!("QR code saved to: {}", output.display());
+        assert_eq!(solid.dimensions(), gapped.dimensions());
+        assert!(count_fill(&gapped) < count_fill(&solid));
+
+        // Finder patterns stay solid: the top-left corner of the outer ring
+        // is filled, where a gapped module would leave back color
+        assert_eq!(*gapped.get_pixel(40, 40), fill);
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_module_gap_keeps_whole_finders_solid() {
+        let code = QrCode::new("finder test").unwrap();
+        let fill = Rgba([0, 0, 0, 255]);
+        let back = Rgba([255, 255, 255, 255]);
+        let (size, border) = (10, 4);
+        let img = draw_qr_code(&code, size, border, &[fill], back, 0.4);
+        let qr_width = code.width() as u32;
+        let module_pixels = |x: u32, y: u32| {
+            image::imageops::crop_imm(&img, (x + border) * size, (y + border) * size, size, size)
+                .to_image()
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Every module of the three finders is painted edge to edge, so
+        // each finder is drawn exactly as a plain square pattern would be
+        for y in 0..qr_width {
+            for x in 0..qr_width {
+                if !in_finder_pattern(x, y, qr_width) {
+                    continue;
+                }
+                let expected = if code[(x as usize, y as usize)] == Color::Dark {
+                    fill
+                } else {
+                    back
+                };
+                assert!(
+                    module_pixels(x, y).pixels().all(|&p| p == expected),
+                    "({x}, {y})"
+                );
+            }
+        }
 
-    #[test]
-    fn test_parse_error_correction_l() {
-        let result = parse_error_correction("L").unwrap();
-        assert_eq!(result, EcLevel::L);
+        // Data modules outside the finders are inset by the gap
+        assert_eq!(code[(8, 6)], Color::Dark);
+        let timing = module_pixels(8, 6);
+        assert_eq!(*timing.get_pixel(0, 0), back);
+        assert_eq!(*timing.get_pixel(size / 2, size / 2), fill);
     }
 
     #[test]
-    fn test_parse_error_correction_m() {
-        let result = parse_error_correction("M").unwrap();
-        assert_eq!(result, EcLevel::M);
+    fn test_module_image_stamps_dark_modules() {
+        let code = QrCode::new("sprite test").unwrap();
+        let fill = Rgba([0, 0, 0, 255]);
+        let back = Rgba([255, 255, 255, 255]);
+        // Left half red, right half blue
+        let sprite = RgbaImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+
+        let img = draw_module_images(&code, 8, 4, fill, back, &sprite);
+        let module = |x: u32, y: u32| {
+            image::imageops::crop_imm(&img, (x + 4) * 8, (y + 4) * 8, 8, 8).to_image()
+        };
+
+        // The timing pattern alternates dark and light from (8, 6)
+        assert_eq!(code[(8, 6)], Color::Dark);
+        assert_eq!(module(8, 6), sprite);
+        assert_eq!(code[(9, 6)], Color::Light);
+        assert!(module(9, 6).pixels().all(|&p| p == back));
+        // Finder patterns stay solid
+        assert!(module(0, 0).pixels().all(|&p| p == fill));
     }
 
     #[test]
-    fn test_parse_error_correction_q() {
-        let result = parse_error_correction("Q").unwrap();
-        assert_eq!(result, EcLevel::Q);
+    fn test_rainbow_palette_uses_several_colors() {
+        let white = Rgba([255, 255, 255, 255]);
+        let code = QrCode::new("https://example.com").unwrap();
+        let img = draw_qr_code(&code, 4, 4, &RAINBOW, white, 0.0);
+
+        let colors: std::collections::HashSet<_> = img.pixels().filter(|&&p| p != white).collect();
+        assert!(colors.len() >= 3, "only {} fill colors", colors.len());
+        assert!(colors.iter().all(|c| RAINBOW.contains(c)));
     }
 
     #[test]
-    fn test_parse_error_correction_h() {
-        let result = parse_error_correction("H").unwrap();
-        assert_eq!(result, EcLevel::H);
+    fn test_load_palette() {
+        let white = Rgba([255, 255, 255, 255]);
+        assert_eq!(load_palette("rainbow", white).unwrap(), RAINBOW);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("palette.txt");
+        std::fs::write(&path, "#000080\n\ndarkgreen\n").unwrap();
+        assert_eq!(
+            load_palette(path.to_str().unwrap(), white).unwrap(),
+            [Rgba([0, 0, 128, 255]), Rgba([0, 100, 0, 255])]
+        );
+
+        // Yellow on white is too faint to scan
+        std::fs::write(&path, "black\nyellow\n").unwrap();
+        assert!(load_palette(path.to_str().unwrap(), white).is_err());
     }
 
     #[test]
-    fn test_parse_error_correction_lowercase() {
-        let result = parse_error_correction("l").unwrap();
-        assert_eq!(result, EcLevel::L);
+    fn test_draw_on_background() {
+        let code = QrCode::new("https://example.com").unwrap();
+        let photo_color = Rgba([10, 200, 30, 255]);
+        let fill = Rgba([0, 0, 128, 255]);
+        let photo = DynamicImage::ImageRgba8(RgbaImage::from_pixel(50, 80, photo_color));
+
+        let img = draw_on_background(&code, 10, 4, &[fill], &photo, 0.0, false);
+        let border = 4;
+        let size = 10;
+        assert_eq!(img.width(), (code.width() as u32 + 8) * size);
+
+        // Quiet zone shows the photo
+        assert_eq!(*img.get_pixel(0, 0), photo_color);
+
+        for y in 0..code.width() {
+            for x in 0..code.width() {
+                let px = (x as u32 + border) * size + size / 2;
+                let py = (y as u32 + border) * size + size / 2;
+                let expected = if code[(x, y)] == Color::Dark {
+                    fill
+                } else {
+                    photo_color
+                };
+                assert_eq!(*img.get_pixel(px, py), expected);
+            }
+        }
     }
 
     #[test]
-    fn test_parse_error_correction_invalid() {
-        let result = parse_error_correction("X");
-        assert!(result.is_err());
+    fn test_logo_coverage_shrinks_with_version() {
+        let small = logo_coverage_fraction(21, 4, 10, None, &text::Font::Builtin, 0);
+        let large = logo_coverage_fraction(177, 4, 10, None, &text::Font::Builtin, 0);
+        assert!(large < small);
+        assert!(small < 0.25);
     }
 
     #[test]
-    fn test_parse_color_black() {
-        let color = parse_color("black").unwrap();
-        assert_eq!(color, Rgba([0, 0, 0, 255]));
+    fn test_check_logo_coverage() {
+        assert!(check_logo_coverage(0.30, EcLevel::L).is_err());
+        assert!(check_logo_coverage(0.30, EcLevel::H).is_ok());
     }
 
     #[test]
-    fn test_parse_color_white() {
-        let color = parse_color("white").unwrap();
-        assert_eq!(color, Rgba([255, 255, 255, 255]));
+    fn test_center_logo_exclusion_plans_error_correction() {
+        let opts = RenderOptions {
+            error_correction: EcChoice::Level(EcLevel::Q),
+            ..test_render_options()
+        };
+
+        // A version 1 code at size 10 loses about 11% to the logo, which M covers
+        let cli = Cli::parse_from([
+            "qrgen",
+            "test",
+            "--logo",
+            "logo.png",
+            "--center-logo-exclusion",
+        ]);
+        let code = encode_data(&cli, &opts, b"test").unwrap();
+        assert_eq!(code.error_correction_level(), EcLevel::M);
+
+        // An explicit level that can't cover the logo is an error
+        let cli = Cli::parse_from([
+            "qrgen",
+            "test",
+            "--logo",
+            "logo.png",
+            "--center-logo-exclusion",
+            "-e",
+            "L",
+        ]);
+        let opts = RenderOptions {
+            error_correction: EcChoice::Level(EcLevel::L),
+            ..opts
+        };
+        assert!(encode_data(&cli, &opts, b"test").is_err());
     }
 
     #[test]
-    fn test_parse_color_red() {
-        let color = parse_color("red").unwrap();
-        assert_eq!(color, Rgba([255, 0, 0, 255]));
+    fn test_encode_data_auto_version_grows_for_logo() {
+        let cli = Cli::parse_from([
+            "qrgen",
+            "test",
+            "--logo",
+            "logo.png",
+            "-e",
+            "L",
+            "--auto-version",
+        ]);
+        let opts = RenderOptions {
+            error_correction: EcChoice::Level(EcLevel::L),
+            ..test_render_options()
+        };
+
+        let code = encode_data(&cli, &opts, b"test").unwrap();
+        assert!(code.width() > 21);
+        assert!(
+            logo_coverage_fraction(code.width() as u32, 4, 10, None, &text::Font::Builtin, 0)
+                <= ec_recovery_fraction(EcLevel::L)
+        );
     }
 
     #[test]
-    fn test_parse_color_blue() {
-        let color = parse_color("blue").unwrap();
-        assert_eq!(color, Rgba([0, 0, 255, 255]));
+    fn test_encode_smallest_matches_default_encoder() {
+        let ours =
+            encode_smallest(b"https://example.com", EcLevel::M, EncodeOptions::default()).unwrap();
+        let theirs =
+            QrCode::with_error_correction_level("https://example.com", EcLevel::M).unwrap();
+        assert_eq!(ours.to_colors(), theirs.to_colors());
+    }
+
+    fn with_eci(designator: u32) -> EncodeOptions {
+        EncodeOptions {
+            eci: Some(designator),
+            ..EncodeOptions::default()
+        }
     }
 
     #[test]
-    fn test_parse_color_hex_lowercase() {
-        let color = parse_color("#ff5733").unwrap();
-        assert_eq!(color, Rgba([255, 87, 51, 255]));
+    fn test_encode_smallest_min_version() {
+        let opts = EncodeOptions {
+            min_version: Some(5),
+            ..EncodeOptions::default()
+        };
+        let code = encode_smallest(b"hi", EcLevel::M, opts).unwrap();
+        assert_eq!(code.version(), Version::Normal(5));
+
+        // Data that needs more than the floor still grows past it
+        let long = "abcdefghij".repeat(20);
+        let code = encode_smallest(long.as_bytes(), EcLevel::M, opts).unwrap();
+        assert_eq!(
+            code.version(),
+            encode_smallest(long.as_bytes(), EcLevel::M, EncodeOptions::default())
+                .unwrap()
+                .version()
+        );
+        assert!(code.width() > 37);
     }
 
     #[test]
-    fn test_parse_color_hex_uppercase() {
-        let color = parse_color("#FF5733").unwrap();
-        assert_eq!(color, Rgba([255, 87, 51, 255]));
+    fn test_auto_error_correction_uses_min_version_capacity() {
+        // 17 bytes only fit version 1 at L, but version 5 has room for H
+        let opts = EncodeOptions {
+            min_version: Some(5),
+            ..EncodeOptions::default()
+        };
+        assert_eq!(
+            auto_error_correction(b"hello world!!!!!!", opts),
+            EcLevel::H
+        );
     }
 
     #[test]
-    fn test_parse_color_hex_mixed_case() {
-        let color = parse_color("#FfA500").unwrap();
-        assert_eq!(color, Rgba([255, 165, 0, 255]));
+    fn test_encode_smallest_eci_changes_bitstream() {
+        let plain =
+            encode_smallest("héllo".as_bytes(), EcLevel::M, EncodeOptions::default()).unwrap();
+        let eci = encode_smallest("héllo".as_bytes(), EcLevel::M, with_eci(26)).unwrap();
+        assert_ne!(plain.to_colors(), eci.to_colors());
     }
 
     #[test]
-    fn test_parse_color_darkblue() {
-        let color = parse_color("darkblue").unwrap();
-        assert_eq!(color, Rgba([0, 0, 139, 255]));
+    fn test_encode_smallest_eci_grows_version_at_capacity() {
+        // 14 bytes exactly fill a version 1 code at EC M
+        let plain =
+            encode_smallest(b"hello world!!!", EcLevel::M, EncodeOptions::default()).unwrap();
+        let eci = encode_smallest(b"hello world!!!", EcLevel::M, with_eci(26)).unwrap();
+        assert_eq!(plain.version(), Version::Normal(1));
+        assert_eq!(eci.version(), Version::Normal(2));
     }
 
     #[test]
-    fn test_parse_color_invalid() {
-        let result = parse_color("notacolor");
-        assert!(result.is_err());
+    fn test_encode_smallest_invalid_eci() {
+        assert!(encode_smallest(b"test", EcLevel::M, with_eci(1_000_000)).is_err());
     }
 
     #[test]
-    fn test_parse_color_invalid_hex() {
-        let result = parse_color("#GGGGGG");
-        assert!(result.is_err());
+    fn test_transform_image_rotate_90_swaps_dimensions() {
+        // Stand-in for a captioned (taller than wide) image
+        let img = RgbaImage::from_pixel(100, 140, Rgba([255, 255, 255, 255]));
+        let rotated = transform_image(img, "90", "none").unwrap();
+        assert_eq!(rotated.dimensions(), (140, 100));
     }
 
     #[test]
-    fn test_parse_color_invalid_hex_length() {
-        let result = parse_color("#FFF");
-        assert!(result.is_err());
+    fn test_transform_image_rotate_180_keeps_dimensions() {
+        let mut img = RgbaImage::from_pixel(100, 140, Rgba([255, 255, 255, 255]));
+        img.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+        let rotated = transform_image(img, "180", "none").unwrap();
+        assert_eq!(rotated.dimensions(), (100, 140));
+        assert_eq!(*rotated.get_pixel(99, 139), Rgba([0, 0, 0, 255]));
     }
 
     #[test]
-    fn test_create_qr_code_basic() {
-        let img = create_qr_code(
-            "test",
-            EcLevel::M,
-            10,
-            4,
-            Rgba([0, 0, 0, 255]),
-            Rgba([255, 255, 255, 255]),
-        );
-        assert!(img.is_ok());
-        let img = img.unwrap();
-        assert!(img.width() > 0);
-        assert!(img.height() > 0);
+    fn test_transform_image_flip() {
+        let mut img = RgbaImage::from_pixel(10, 20, Rgba([255, 255, 255, 255]));
+        img.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+
+        let horizontal = transform_image(img.clone(), "0", "horizontal").unwrap();
+        assert_eq!(*horizontal.get_pixel(9, 0), Rgba([0, 0, 0, 255]));
+
+        let vertical = transform_image(img, "0", "vertical").unwrap();
+        assert_eq!(*vertical.get_pixel(0, 19), Rgba([0, 0, 0, 255]));
     }
 
     #[test]
-    fn test_create_qr_code_url() {
+    fn test_verify_qr_code_valid() {
         let img = create_qr_code(
             "https://example.com",
             EcLevel::M,
@@ -370,26 +4488,30 @@ mod tests {
             4,
             Rgba([0, 0, 0, 255]),
             Rgba([255, 255, 255, 255]),
-        );
-        assert!(img.is_ok());
+        )
+        .unwrap();
+        assert!(verify_qr_code(&img, b"https://example.com").is_ok());
     }
 
     #[test]
-    fn test_create_qr_code_custom_size() {
+    fn test_verify_qr_code_wrong_data() {
         let img = create_qr_code(
-            "test",
+            "https://example.com",
             EcLevel::M,
-            20,
+            10,
             4,
             Rgba([0, 0, 0, 255]),
             Rgba([255, 255, 255, 255]),
         )
         .unwrap();
+        assert!(verify_qr_code(&img, b"https://example.org").is_err());
+    }
 
-        // Larger size should produce larger image
-        let img_small = create_qr_code(
-            "test",
-            EcLevel::M,
+    #[test]
+    fn test_verify_qr_code_corrupted() {
+        let mut img = create_qr_code(
+            "https://example.com",
+            EcLevel::L,
             10,
             4,
             Rgba([0, 0, 0, 255]),
@@ -397,106 +4519,234 @@ mod tests {
         )
         .unwrap();
 
-        assert!(img.width() > img_small.width());
+        // Cover the middle of the code with an oversized "logo"
+        let (w, h) = img.dimensions();
+        for y in h / 4..h * 3 / 4 {
+            for x in w / 4..w * 3 / 4 {
+                img.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+        assert!(verify_qr_code(&img, b"https://example.com").is_err());
     }
 
     #[test]
-    fn test_create_qr_code_custom_border() {
-        let img = create_qr_code(
-            "test",
-            EcLevel::M,
-            10,
-            10,
-            Rgba([0, 0, 0, 255]),
-            Rgba([255, 255, 255, 255]),
-        )
-        .unwrap();
+    fn test_strip_control_chars() {
+        let (cleaned, removed) = strip_control_chars("\u{feff}a\u{200b}b\u{b}c\td\r\n");
+        assert_eq!(cleaned, "abc\td\r\n");
+        assert_eq!(removed, 3);
 
-        let img_small_border = create_qr_code(
-            "test",
-            EcLevel::M,
-            10,
-            4,
-            Rgba([0, 0, 0, 255]),
-            Rgba([255, 255, 255, 255]),
-        )
-        .unwrap();
+        // Zero-width joiners hold emoji sequences together
+        let family = "\u{1f468}\u{200d}\u{1f469}";
+        assert_eq!(strip_control_chars(family), (family.to_string(), 0));
+    }
 
-        assert!(img.width() > img_small_border.width());
+    #[test]
+    fn test_normalize_text() {
+        let decomposed = "Cafe\u{301}";
+        assert_eq!(normalize_text(decomposed, "nfc"), "Caf\u{e9}");
+        assert_eq!(normalize_text("Caf\u{e9}", "nfd"), decomposed);
+        assert_eq!(normalize_text(decomposed, "none"), decomposed);
     }
 
     #[test]
-    fn test_create_qr_code_all_error_levels() {
-        for level in [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H] {
-            let img = create_qr_code(
-                "test",
-                level,
-                10,
-                4,
-                Rgba([0, 0, 0, 255]),
-                Rgba([255, 255, 255, 255]),
-            );
-            assert!(img.is_ok());
-        }
+    fn test_wrap_data_suffix() {
+        let result = wrap_data("https://example.com", None, Some("?x=1"));
+        assert_eq!(result, "https://example.com?x=1");
     }
 
     #[test]
-    fn test_create_qr_code_custom_colors() {
-        let img = create_qr_code(
-            "test",
-            EcLevel::M,
-            10,
-            4,
-            Rgba([0, 0, 255, 255]),   // Blue
-            Rgba([255, 255, 0, 255]), // Yellow
-        );
-        assert!(img.is_ok());
+    fn test_wrap_data_prefix_and_suffix() {
+        let result = wrap_data("example.com", Some("https://"), Some("/?utm_source=qr"));
+        assert_eq!(result, "https://example.com/?utm_source=qr");
     }
 
     #[test]
-    fn test_create_qr_code_empty_string() {
-        let img = create_qr_code(
-            "",
-            EcLevel::M,
-            10,
-            4,
-            Rgba([0, 0, 0, 255]),
-            Rgba([255, 255, 255, 255]),
-        );
-        assert!(img.is_ok());
+    fn test_data_uri() {
+        assert_eq!(data_uri("hi", "text/plain"), "data:text/plain;base64,aGk=");
     }
 
     #[test]
-    fn test_create_qr_code_long_text() {
-        let long_text = "A".repeat(1000);
-        let img = create_qr_code(
-            &long_text,
-            EcLevel::M,
-            10,
-            4,
-            Rgba([0, 0, 0, 255]),
-            Rgba([255, 255, 255, 255]),
-        );
-        assert!(img.is_ok());
+    fn test_parse_mime_type() {
+        assert_eq!(parse_mime_type("text/vcard").unwrap(), "text/vcard");
+        assert!(parse_mime_type("text").is_err());
+        assert!(parse_mime_type("text/").is_err());
+        assert!(parse_mime_type("text/plain;charset=utf-8").is_err());
+        assert!(parse_mime_type("a/b/c").is_err());
     }
 
     #[test]
-    fn test_create_qr_code_special_characters() {
-        let img = create_qr_code(
-            "Hello! @#$%^&*() 你好 🎉",
-            EcLevel::M,
-            10,
-            4,
-            Rgba([0, 0, 0, 255]),
-            Rgba([255, 255, 255, 255]),
-        );
-        assert!(img.is_ok());
+    fn test_wrap_data_none() {
+        let result = wrap_data("plain", None, None);
+        assert_eq!(result, "plain");
+    }
+
+    #[test]
+    fn test_validate_url_rejects_space_in_host() {
+        assert!(validate_url("https://exa mple.com").is_err());
+    }
+
+    #[test]
+    fn test_validate_url_rejects_invalid_host() {
+        assert!(validate_url("https://exa<mple.com").is_err());
+        assert!(validate_url("https://").is_err());
+    }
+
+    #[test]
+    fn test_validate_url_accepts_valid_url() {
+        assert!(validate_url("https://example.com/path?q=1").is_ok());
+    }
+
+    #[test]
+    fn test_validate_url_ignores_non_urls() {
+        assert!(validate_url("Hello, World!").is_ok());
+        assert!(validate_url("WIFI:T:WPA;S:My Net;P:pass;;").is_ok());
+    }
+
+    #[test]
+    fn test_strict_applies_after_affixes() {
+        let cli = Cli::parse_from(["qrgen", "--strict", "--data-prefix", "https://", "bad host"]);
+        assert!(prepare_payload(&cli, "bad host").is_err());
+
+        let cli = Cli::parse_from(["qrgen", "--strict", "--data-prefix", "https://", "ok.com"]);
+        assert!(prepare_payload(&cli, "example.com").is_ok());
+    }
+
+    #[test]
+    fn test_shorten_falls_back_unless_strict() {
+        // Nothing listens on a port a dropped listener was given
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let endpoint = format!("http://{}/new?url={{url}}", addr);
+        let long = "https://example.com/a/very/long/path";
+
+        let cli = Cli::parse_from(["qrgen", "--shorten", "--shortener-url", &endpoint, long]);
+        assert_eq!(prepare_payload(&cli, long).unwrap(), long);
+        // Only http(s) URLs are sent to the shortener
+        assert_eq!(prepare_payload(&cli, "Hello").unwrap(), "Hello");
+
+        let cli = Cli::parse_from([
+            "qrgen",
+            "--shorten",
+            "--strict",
+            "--shortener-url",
+            &endpoint,
+            long,
+        ]);
+        assert!(prepare_payload(&cli, long).is_err());
+        assert!(Cli::try_parse_from(["qrgen", "--shorten", long]).is_err());
+    }
+
+    #[test]
+    fn test_clip_to_capacity_short_data_untouched() {
+        let (clipped, dropped) =
+            clip_to_capacity("hello", "utf8", EcLevel::M, EncodeOptions::default()).unwrap();
+        assert_eq!(clipped, "hello");
+        assert_eq!(dropped, 0);
+    }
+
+    #[test]
+    fn test_clip_to_capacity_truncates_to_max() {
+        let data = "x".repeat(4000);
+        let (clipped, dropped) =
+            clip_to_capacity(&data, "utf8", EcLevel::H, EncodeOptions::default()).unwrap();
+
+        // Version 40-H holds 1273 bytes in byte mode
+        assert_eq!(clipped.len(), 1273);
+        assert_eq!(dropped, 4000 - 1273);
+        assert!(encode_smallest(clipped.as_bytes(), EcLevel::H, EncodeOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_clip_to_capacity_char_boundary() {
+        let data = "é".repeat(2000);
+        let (clipped, dropped) =
+            clip_to_capacity(&data, "utf8", EcLevel::H, EncodeOptions::default()).unwrap();
+
+        assert!(clipped.chars().all(|c| c == 'é'));
+        assert_eq!(clipped.len(), 1272);
+        assert_eq!(dropped, 4000 - 1272);
+    }
+
+    #[test]
+    fn test_encode_text_utf8() {
+        let bytes = encode_text("café", "utf8").unwrap();
+        assert_eq!(bytes, "café".as_bytes());
+    }
+
+    #[test]
+    fn test_encode_text_latin1() {
+        let bytes = encode_text("café", "latin1").unwrap();
+        assert_eq!(bytes, vec![b'c', b'a', b'f', 0xE9]);
+    }
+
+    #[test]
+    fn test_encode_text_latin1_is_iso_8859_1() {
+        // Windows-1252 would store € as 0x80; ISO-8859-1 has no €
+        assert!(encode_text("5 €", "latin1").is_err());
+        // U+0080-U+009F are C1 controls, stored as is
+        assert_eq!(encode_text("\u{85}", "latin1").unwrap(), vec![0x85]);
+    }
+
+    #[test]
+    fn test_encode_text_shift_jis() {
+        let bytes = encode_text("日本", "shift-jis").unwrap();
+        assert_eq!(bytes, vec![0x93, 0xFA, 0x96, 0x7B]);
+    }
+
+    #[test]
+    fn test_encode_text_unrepresentable() {
+        assert!(encode_text("日本", "latin1").is_err());
+    }
+
+    #[test]
+    fn test_latin1_produces_smaller_code() {
+        let text = "é".repeat(20);
+        let utf8 =
+            QrCode::with_error_correction_level(encode_text(&text, "utf8").unwrap(), EcLevel::M)
+                .unwrap();
+        let latin1 =
+            QrCode::with_error_correction_level(encode_text(&text, "latin1").unwrap(), EcLevel::M)
+                .unwrap();
+        assert!(latin1.width() < utf8.width());
     }
 
     #[test]
     fn test_print_terminal() {
         // Just verify it doesn't panic or error
-        let result = print_terminal("test", EcLevel::M);
+        let result = print_terminal(b"test", EcLevel::M, EncodeOptions::default(), false);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_terminal_themes_are_inverted() {
+        let code = QrCode::with_error_correction_level(b"test", EcLevel::M).unwrap();
+        let light = terminal_string(&code, false);
+        let dark = terminal_string(&code, true);
+
+        // The quiet zone is blank on a light terminal and solid on a dark one
+        assert!(light.starts_with("  "));
+        assert!(dark.starts_with("██"));
+        let swapped: String = light
+            .chars()
+            .map(|c| match c {
+                '█' => ' ',
+                ' ' => '█',
+                other => other,
+            })
+            .collect();
+        assert_eq!(dark, swapped);
+    }
+
+    #[test]
+    fn test_colorfgbg_is_dark() {
+        assert_eq!(colorfgbg_is_dark("15;0"), Some(true));
+        assert_eq!(colorfgbg_is_dark("0;15"), Some(false));
+        assert_eq!(colorfgbg_is_dark("15;default;8"), Some(true));
+        assert_eq!(colorfgbg_is_dark("0;7"), Some(false));
+        assert_eq!(colorfgbg_is_dark("default"), None);
+        assert!(terminal_is_dark("dark"));
+        assert!(!terminal_is_dark("light"));
+    }
 }
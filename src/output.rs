@@ -0,0 +1,840 @@
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use qrcode::bits::Bits;
+use qrcode::ec::construct_codewords;
+use qrcode::{Color, QrCode, Version};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tiff::encoder::{colortype, Compression, DeflateLevel, TiffEncoder};
+
+use crate::run_log::{self, LogEntry};
+use crate::segments::{self, EncodeOptions};
+use crate::Cli;
+
+/// Hex digits of the payload hash that `--hash-suffix` puts in filenames
+const HASH_SUFFIX_LEN: usize = 8;
+
+/// Wait before the first `--write-retries` retry; doubled for each one after
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Columns of a CSV `--manifest`, in order
+const MANIFEST_COLUMNS: [&str; 8] = [
+    "path", "payload", "sha256", "format", "version", "width_px", "ec_level", "bytes",
+];
+
+/// tEXt keyword for `--embed-metadata`
+pub const METADATA_KEYWORD: &str = "qrgen";
+
+/// zlib level for `--reproducible` PNGs, pinned rather than left to the
+/// encoder's default
+const REPRODUCIBLE_DEFLATE_LEVEL: u8 = 9;
+
+/// Everything qrgen can write
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Svg,
+    Webp,
+    Bmp,
+    Tiff,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "svg" => Some(Self::Svg),
+            "webp" => Some(Self::Webp),
+            "bmp" => Some(Self::Bmp),
+            "tiff" | "tif" => Some(Self::Tiff),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// The name `--output-format` takes for this format
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpeg",
+            Self::Svg => "svg",
+            Self::Webp => "webp",
+            Self::Bmp => "bmp",
+            Self::Tiff => "tiff",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_name)
+    }
+}
+
+/// Work out the output format: an explicit `--output-format` wins over the
+/// file extension, with a warning if the two disagree
+pub fn resolve_format(explicit: Option<&str>, path: &Path) -> Result<OutputFormat> {
+    let from_path = OutputFormat::from_path(path);
+
+    let Some(name) = explicit else {
+        return from_path.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Can't tell the output format from {:?}; add an extension or pass --output-format",
+                path
+            )
+        });
+    };
+
+    let format = OutputFormat::from_name(name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown output format: {}", name))?;
+    if let Some(ext_format) = from_path {
+        if ext_format != format {
+            eprintln!(
+                "Warning: --output-format {} overrides the extension of {}",
+                name,
+                path.display()
+            );
+        }
+    }
+
+    Ok(format)
+}
+
+/// Describe the module grid as `{ version, width, modules }` where each row of
+/// `modules` lists booleans with dark modules as `true`
+pub fn matrix_json(code: &QrCode) -> serde_json::Value {
+    let width = code.width();
+    let version = match code.version() {
+        Version::Normal(v) | Version::Micro(v) => v,
+    };
+    let modules: Vec<Vec<bool>> = code
+        .to_colors()
+        .chunks(width)
+        .map(|row| row.iter().map(|&c| c == Color::Dark).collect())
+        .collect();
+
+    serde_json::json!({
+        "version": version,
+        "width": width,
+        "modules": modules,
+    })
+}
+
+/// Describe a written code for `--json-output`. `width_px` is null for JSON
+/// matrix output, which has no pixels, and `version` and `ec_level` are null
+/// for Data Matrix; `bytes` is the length of the encoded payload.
+pub fn output_summary(entry: &LogEntry) -> serde_json::Value {
+    let width_px = (entry.format != OutputFormat::Json).then_some(entry.dimensions.0);
+    serde_json::json!({
+        "path": entry.output.display().to_string(),
+        "format": entry.format.name(),
+        "version": entry.version,
+        "width_px": width_px,
+        "ec_level": entry.error_correction,
+        "bytes": entry.data.len(),
+    })
+}
+
+/// One `--manifest` entry: the `--json-output` summary plus the payload, as
+/// text, and its SHA-256
+pub fn manifest_entry(summary: &serde_json::Value, data: &[u8]) -> serde_json::Value {
+    let mut entry = summary.clone();
+    entry["payload"] = String::from_utf8_lossy(data).into();
+    entry["sha256"] = run_log::data_hash(data).into();
+    entry
+}
+
+/// The `--manifest` file contents: CSV with a header row for a `.csv` path,
+/// one JSON object per line for `.jsonl` or `.ndjson`, otherwise a JSON array
+fn manifest_contents(path: &Path, entries: &[serde_json::Value]) -> Result<Vec<u8>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "csv" => {}
+        "jsonl" | "ndjson" => {
+            let mut contents = Vec::new();
+            for entry in entries {
+                serde_json::to_writer(&mut contents, entry)?;
+                contents.push(b'\n');
+            }
+            return Ok(contents);
+        }
+        _ => {
+            let mut contents = serde_json::to_vec_pretty(entries)?;
+            contents.push(b'\n');
+            return Ok(contents);
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(MANIFEST_COLUMNS)?;
+    for entry in entries {
+        writer.write_record(MANIFEST_COLUMNS.iter().map(|&column| match &entry[column] {
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::String(text) => text.clone(),
+            value => value.to_string(),
+        }))?;
+    }
+    writer.into_inner().context("Failed to build CSV manifest")
+}
+
+/// Write every generated code to the `--manifest` file, replacing it
+pub fn write_manifest(path: &Path, entries: &[serde_json::Value], retries: u32) -> Result<()> {
+    let contents = manifest_contents(path, entries)?;
+    with_write_retries(retries, || {
+        std::fs::write(path, &contents)
+            .with_context(|| format!("Failed to write manifest {}", path.display()))
+    })
+}
+
+/// Provenance text for `--embed-metadata`; `symbol` describes the code itself
+pub fn metadata_text(data_len: usize, symbol: &str) -> String {
+    format!(
+        "qrgen {}; data length {} bytes; {}",
+        env!("CARGO_PKG_VERSION"),
+        data_len,
+        symbol
+    )
+}
+
+pub fn qr_metadata(code: &QrCode, data_len: usize) -> String {
+    let version = match code.version() {
+        Version::Normal(v) | Version::Micro(v) => v,
+    };
+    let symbol = format!(
+        "error correction {:?}; QR version {}",
+        code.error_correction_level(),
+        version
+    );
+    metadata_text(data_len, &symbol)
+}
+
+/// Describe how `data` fills `code`: the encoding modes the optimizer picked,
+/// and how many of the data bits for the code's version and EC level it uses
+pub fn encoding_report(
+    code: &QrCode,
+    data: &[u8],
+    encode_opts: EncodeOptions,
+) -> Result<serde_json::Value> {
+    let version = code.version();
+    let ec_level = code.error_correction_level();
+
+    let mut bits = Bits::new(version);
+    segments::push_data(&mut bits, data, encode_opts)?;
+    let used = bits.len();
+    let available = bits.max_len(ec_level)?;
+
+    let mut modes: Vec<String> = segments::choose_segments(data, version, encode_opts)
+        .iter()
+        .map(|segment| format!("{:?}", segment.mode))
+        .collect();
+    modes.dedup();
+    let mode = match modes.as_slice() {
+        [single] => single.clone(),
+        [] => "Byte".to_string(),
+        _ => "Mixed".to_string(),
+    };
+
+    let number = match version {
+        Version::Normal(v) | Version::Micro(v) => v,
+    };
+    let percent = used as f64 / available as f64 * 100.0;
+    Ok(serde_json::json!({
+        "mode": mode,
+        "segments": modes,
+        "version": number,
+        "error_correction": format!("{:?}", ec_level),
+        "capacity_used_bits": used,
+        "capacity_bits": available,
+        "capacity_used_percent": (percent * 10.0).round() / 10.0,
+    }))
+}
+
+/// Print the report to stderr, or write it to `path` when one is given
+pub fn write_encoding_report(report: &serde_json::Value, path: Option<&Path>) -> Result<()> {
+    let json = serde_json::to_string_pretty(report)?;
+    match path {
+        Some(path) => std::fs::write(path, json + "\n")
+            .with_context(|| format!("Failed to save encoding report to {:?}", path)),
+        None => {
+            eprintln!("{}", json);
+            Ok(())
+        }
+    }
+}
+
+/// Rebuild the bitstream behind `code` for `--dump-bits`: the data codewords
+/// (mode indicators, lengths, data, terminator and padding) followed by the
+/// error correction codewords, interleaved in the order they're placed
+pub fn bitstream_dump(code: &QrCode, data: &[u8], encode_opts: EncodeOptions) -> Result<String> {
+    let version = code.version();
+    let ec_level = code.error_correction_level();
+
+    let mut bits = Bits::new(version);
+    segments::push_data(&mut bits, data, encode_opts)?;
+    let data_bits = bits.len();
+    bits.push_terminator(ec_level)?;
+    let (data_codewords, ec_codewords) =
+        construct_codewords(&bits.into_bytes(), version, ec_level)?;
+
+    let number = match version {
+        Version::Normal(v) | Version::Micro(v) => v,
+    };
+    let mut dump = format!(
+        "Bitstream: QR version {}, error correction {:?}, {} data bits before padding\n",
+        number, ec_level, data_bits
+    );
+    for (label, codewords) in [("Data", &data_codewords), ("EC", &ec_codewords)] {
+        let hex: Vec<String> = codewords.iter().map(|b| format!("{:02x}", b)).collect();
+        let binary: Vec<String> = codewords.iter().map(|b| format!("{:08b}", b)).collect();
+        dump.push_str(&format!(
+            "{} codewords ({}):\n  hex: {}\n  bin: {}\n",
+            label,
+            codewords.len(),
+            hex.join(" "),
+            binary.join(" ")
+        ));
+    }
+    Ok(dump)
+}
+
+/// The module grid as text for `--show-matrix`: a header with the version
+/// and width, then one line per row with `1` for dark modules and `0` for light
+pub fn matrix_dump(code: &QrCode) -> String {
+    let width = code.width();
+    let version = match code.version() {
+        Version::Normal(v) | Version::Micro(v) => v,
+    };
+
+    let mut dump = format!(
+        "Matrix: QR version {}, {}x{} modules\n",
+        version, width, width
+    );
+    for row in code.to_colors().chunks(width) {
+        dump.extend(
+            row.iter()
+                .map(|&c| if c == Color::Dark { '1' } else { '0' }),
+        );
+        dump.push('\n');
+    }
+    dump
+}
+
+pub fn save_matrix_json(code: &QrCode, path: &Path) -> Result<()> {
+    let json = serde_json::to_string(&matrix_json(code))?;
+    std::fs::write(path, json).with_context(|| format!("Failed to save QR matrix to {:?}", path))
+}
+
+fn tiff_compression(name: &str) -> Result<Compression> {
+    match name {
+        "none" => Ok(Compression::Uncompressed),
+        "lzw" => Ok(Compression::Lzw),
+        "deflate" => Ok(Compression::Deflate(DeflateLevel::Balanced)),
+        _ => Err(anyhow::anyhow!("Unknown TIFF compression: {}", name)),
+    }
+}
+
+/// Write a TIFF directly through the `tiff` crate, since the `image` encoder
+/// doesn't expose compression settings
+fn save_tiff(img: &RgbaImage, path: &Path, compression: &str) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+    let mut encoder = TiffEncoder::new(BufWriter::new(file))
+        .context("Failed to start TIFF encoder")?
+        .with_compression(tiff_compression(compression)?);
+
+    encoder
+        .write_image::<colortype::RGBA8>(img.width(), img.height(), img.as_raw())
+        .context("Failed to encode TIFF")?;
+    Ok(())
+}
+
+/// Write a PNG through the `png` crate so the print resolution can be stored
+/// in the pHYs chunk, provenance metadata in a `qrgen` tEXt chunk and, with
+/// `srgb`, an sRGB chunk marking the colors for color-managed workflows.
+/// `reproducible` pins the filter and compression level so the same image
+/// always encodes to the same bytes; no time-dependent chunk is ever written.
+fn save_png(
+    img: &RgbaImage,
+    path: &Path,
+    dpi: Option<u32>,
+    metadata: Option<&str>,
+    srgb: bool,
+    reproducible: bool,
+) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), img.width(), img.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    if reproducible {
+        encoder.set_filter(png::Filter::Up);
+        encoder.set_deflate_compression(png::DeflateCompression::Level(REPRODUCIBLE_DEFLATE_LEVEL));
+    }
+
+    if let Some(dpi) = dpi {
+        // pHYs stores pixels per meter
+        let ppm = (dpi as f64 / 0.0254).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: ppm,
+            yppu: ppm,
+            unit: png::Unit::Meter,
+        }));
+    }
+    if srgb {
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    }
+    if let Some(text) = metadata {
+        encoder
+            .add_text_chunk(METADATA_KEYWORD.to_string(), text.to_string())
+            .context("Failed to add PNG metadata")?;
+    }
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(img.as_raw()))
+        .context("Failed to encode PNG")?;
+    Ok(())
+}
+
+/// Save the finished image with the encoder for `format`. `metadata` is
+/// only embedded in PNG output.
+pub fn save_image(
+    cli: &Cli,
+    img: &RgbaImage,
+    path: &Path,
+    format: OutputFormat,
+    metadata: Option<&str>,
+) -> Result<()> {
+    match format {
+        OutputFormat::Png => match (cli.print_dpi(), metadata, cli.embed_srgb, cli.reproducible) {
+            (None, None, false, false) => img
+                .save_with_format(path, ImageFormat::Png)
+                .map_err(Into::into),
+            (dpi, metadata, srgb, reproducible) => {
+                save_png(img, path, dpi, metadata, srgb, reproducible)
+            }
+        },
+        // JPEG has no alpha channel
+        OutputFormat::Jpeg => DynamicImage::ImageRgba8(img.clone())
+            .to_rgb8()
+            .save_with_format(path, ImageFormat::Jpeg)
+            .map_err(Into::into),
+        OutputFormat::Webp => img
+            .save_with_format(path, ImageFormat::WebP)
+            .map_err(Into::into),
+        OutputFormat::Bmp => img
+            .save_with_format(path, ImageFormat::Bmp)
+            .map_err(Into::into),
+        OutputFormat::Tiff => save_tiff(img, path, &cli.tiff_compression),
+        OutputFormat::Svg | OutputFormat::Json => Err(anyhow::anyhow!(
+            "{:?} output is not a raster image format",
+            format
+        )),
+    }
+    .with_context(|| format!("Failed to save QR code to {:?}", path))
+}
+
+/// Where to write the code for `data`: `path` itself, or with --hash-suffix
+/// the start of the payload's SHA-256 inserted before the extension, so
+/// `qr_code.png` becomes `qr_code.a1b2c3d4.png`
+pub fn output_path(cli: &Cli, path: &Path, data: &[u8]) -> PathBuf {
+    if !cli.hash_suffix {
+        return path.to_path_buf();
+    }
+
+    let hash = &run_log::data_hash(data)[..HASH_SUFFIX_LEN];
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, hash, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, hash),
+    };
+    path.with_file_name(name)
+}
+
+/// Whether the failure came from the filesystem rather than the encoder,
+/// so trying again might succeed
+fn is_io_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        // `image` and `png` wrap the I/O error without reporting it as a source
+        cause.is::<std::io::Error>()
+            || matches!(
+                cause.downcast_ref::<image::ImageError>(),
+                Some(image::ImageError::IoError(_))
+            )
+            || matches!(
+                cause.downcast_ref::<png::EncodingError>(),
+                Some(png::EncodingError::IoError(_))
+            )
+    })
+}
+
+/// Run `write`, trying again up to `retries` times with an exponential
+/// backoff when it fails with an I/O error, for network shares and other
+/// flaky filesystems. Other errors are returned straight away.
+pub fn with_write_retries<T>(retries: u32, mut write: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = FIRST_RETRY_DELAY;
+    for _ in 0..retries {
+        match write() {
+            Err(e) if is_io_error(&e) => {
+                eprintln!("Warning: {:#}; retrying in {} ms", e, delay.as_millis());
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    write()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_save_png_with_dpi_writes_phys() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("print.png");
+        let img = RgbaImage::new(4, 4);
+        let cli = Cli::parse_from(["qrgen", "x", "--dpi", "300"]);
+
+        save_image(&cli, &img, &path, OutputFormat::Png, None).unwrap();
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+        let reader = decoder.read_info().unwrap();
+        let dims = reader.info().pixel_dims.unwrap();
+        assert_eq!(dims.unit, png::Unit::Meter);
+        assert_eq!(dims.xppu, 11811);
+        assert_eq!(dims.yppu, 11811);
+    }
+
+    #[test]
+    fn test_save_png_embeds_metadata() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("meta.png");
+        let img = RgbaImage::new(4, 4);
+        let cli = Cli::parse_from(["qrgen", "x"]);
+
+        save_image(&cli, &img, &path, OutputFormat::Png, Some("qrgen 0.1.0")).unwrap();
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+        let reader = decoder.read_info().unwrap();
+        let chunk = &reader.info().uncompressed_latin1_text[0];
+        assert_eq!(chunk.keyword, METADATA_KEYWORD);
+        assert_eq!(chunk.text, "qrgen 0.1.0");
+    }
+
+    #[test]
+    fn test_save_png_embed_srgb() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let img = RgbaImage::new(4, 4);
+        let srgb_chunk = |args: &[&str], name: &str| {
+            let path = dir.path().join(name);
+            let cli = Cli::parse_from(args);
+            save_image(&cli, &img, &path, OutputFormat::Png, None).unwrap();
+            let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+            decoder.read_info().unwrap().info().srgb
+        };
+
+        assert_eq!(
+            srgb_chunk(&["qrgen", "x", "--embed-srgb"], "tagged.png"),
+            Some(png::SrgbRenderingIntent::Perceptual)
+        );
+        assert_eq!(srgb_chunk(&["qrgen", "x"], "plain.png"), None);
+    }
+
+    /// The type of every chunk in a PNG file, in order, and the filter type
+    /// byte that starts each row of the inflated IDAT data
+    fn png_layout(bytes: &[u8], stride: usize) -> (Vec<String>, Vec<u8>) {
+        let mut chunks = Vec::new();
+        let mut idat = Vec::new();
+        let mut pos = 8;
+        while pos < bytes.len() {
+            let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+            let kind = String::from_utf8_lossy(&bytes[pos + 4..pos + 8]).to_string();
+            if kind == "IDAT" {
+                idat.extend_from_slice(&bytes[pos + 8..pos + 8 + len]);
+            }
+            chunks.push(kind);
+            pos += 12 + len;
+        }
+        let mut raw = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(&idat[..]), &mut raw)
+            .unwrap();
+        let filters = raw.chunks(stride).map(|row| row[0]).collect();
+        (chunks, filters)
+    }
+
+    #[test]
+    fn test_save_png_reproducible() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let img = RgbaImage::from_fn(21, 21, |x, y| {
+            let v = if (x * 7 + y * 3) % 5 < 2 { 0 } else { 255 };
+            image::Rgba([v, v, v, 255])
+        });
+        let save = |args: &[&str], name: &str| {
+            let path = dir.path().join(name);
+            let cli = Cli::parse_from(args);
+            save_image(&cli, &img, &path, OutputFormat::Png, Some("qrgen 0.1.0")).unwrap();
+            std::fs::read(path).unwrap()
+        };
+        let args = ["qrgen", "x", "--reproducible", "--embed-srgb"];
+
+        let first = save(&args, "first.png");
+        assert_eq!(first, save(&args, "second.png"));
+        // Every row uses the pinned Up filter, and no timestamp is written
+        let stride = 1 + 21 * 4;
+        let (chunks, filters) = png_layout(&first, stride);
+        assert!(!chunks.iter().any(|kind| kind == "tIME"));
+        assert_eq!(filters, vec![png::Filter::Up as u8; 21]);
+        // The default encoder settings pick filters row by row
+        let default = save(&["qrgen", "x", "--embed-srgb"], "default.png");
+        assert_ne!(png_layout(&default, stride).1, filters);
+        // The pixels survive the pinned encoder settings
+        let decoded = image::load_from_memory(&first).unwrap().to_rgba8();
+        assert_eq!(decoded, img);
+    }
+
+    #[test]
+    fn test_with_write_retries_recovers_from_io_error() {
+        let flaky = |attempts: &mut u32| -> Result<()> {
+            *attempts += 1;
+            if *attempts == 1 {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "share went away",
+                ))
+                .context("Failed to save QR code")
+            } else {
+                Ok(())
+            }
+        };
+
+        let mut attempts = 0;
+        with_write_retries(1, || flaky(&mut attempts)).unwrap();
+        assert_eq!(attempts, 2);
+
+        let mut attempts = 0;
+        assert!(with_write_retries(0, || flaky(&mut attempts)).is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_save_into_missing_directory_is_io_error() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("missing").join("code.png");
+        let img = RgbaImage::new(4, 4);
+        let cli = Cli::parse_from(["qrgen", "x"]);
+
+        let err = save_image(&cli, &img, &path, OutputFormat::Png, None).unwrap_err();
+        assert!(is_io_error(&err));
+    }
+
+    #[test]
+    fn test_with_write_retries_skips_other_errors() {
+        let mut attempts = 0;
+        let result: Result<()> = with_write_retries(3, || {
+            attempts += 1;
+            Err(anyhow::anyhow!("Failed to encode PNG"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_output_path_hash_suffix() {
+        let plain = Cli::parse_from(["qrgen", "x"]);
+        assert_eq!(
+            output_path(&plain, Path::new("out/qr_code.png"), b"hello"),
+            PathBuf::from("out/qr_code.png")
+        );
+
+        let cli = Cli::parse_from(["qrgen", "x", "--hash-suffix"]);
+        assert_eq!(
+            output_path(&cli, Path::new("out/qr_code.png"), b"hello"),
+            PathBuf::from("out/qr_code.2cf24dba.png")
+        );
+        assert_eq!(
+            output_path(&cli, Path::new("qr_code"), b"hello"),
+            PathBuf::from("qr_code.2cf24dba")
+        );
+        assert_ne!(
+            output_path(&cli, Path::new("qr_code.png"), b"hello"),
+            output_path(&cli, Path::new("qr_code.png"), b"world")
+        );
+    }
+
+    #[test]
+    fn test_output_summary() {
+        let entry = LogEntry {
+            data: b"hello",
+            output: Path::new("out/code.svg"),
+            format: OutputFormat::Svg,
+            symbol: "QR version 1".to_string(),
+            version: Some(1),
+            error_correction: Some("M".to_string()),
+            dimensions: (290, 290),
+        };
+        assert_eq!(
+            output_summary(&entry),
+            serde_json::json!({
+                "path": "out/code.svg",
+                "format": "svg",
+                "version": 1,
+                "width_px": 290,
+                "ec_level": "M",
+                "bytes": 5,
+            })
+        );
+
+        let matrix = LogEntry {
+            format: OutputFormat::Json,
+            ..entry
+        };
+        assert!(output_summary(&matrix)["width_px"].is_null());
+    }
+
+    #[test]
+    fn test_manifest_contents() {
+        let summary = serde_json::json!({
+            "path": "out/a.png",
+            "format": "png",
+            "version": 1,
+            "width_px": 290,
+            "ec_level": "M",
+            "bytes": 9,
+        });
+        let entries = [manifest_entry(&summary, b"hello, qr")];
+        assert_eq!(entries[0]["payload"], "hello, qr");
+        assert_eq!(entries[0]["sha256"].as_str().unwrap().len(), 64);
+
+        let lines = manifest_contents(Path::new("manifest.jsonl"), &entries).unwrap();
+        let lines = String::from_utf8(lines).unwrap();
+        assert_eq!(lines.lines().count(), 1);
+        let parsed: serde_json::Value = serde_json::from_str(lines.trim_end()).unwrap();
+        assert_eq!(parsed, entries[0]);
+
+        // Any other extension, .json included, gets a single JSON array
+        let array = manifest_contents(Path::new("manifest.json"), &entries).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&array).unwrap();
+        assert_eq!(parsed, serde_json::Value::Array(entries.to_vec()));
+
+        let csv = manifest_contents(Path::new("manifest.CSV"), &entries).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut rows = csv.lines();
+        assert_eq!(
+            rows.next(),
+            Some("path,payload,sha256,format,version,width_px,ec_level,bytes")
+        );
+        let row = rows.next().unwrap();
+        assert!(row.starts_with("out/a.png,\"hello, qr\","));
+        assert!(row.ends_with(",png,1,290,M,9"));
+    }
+
+    #[test]
+    fn test_encoding_report() {
+        let code = QrCode::new(b"HELLO WORLD").unwrap();
+        let report = encoding_report(&code, b"HELLO WORLD", EncodeOptions::default()).unwrap();
+
+        // 4 mode bits + 9 count bits + 61 data bits for 11 alphanumeric chars;
+        // version 1-M holds 16 data codewords
+        assert_eq!(report["mode"], "Alphanumeric");
+        assert_eq!(report["version"], 1);
+        assert_eq!(report["error_correction"], "M");
+        assert_eq!(report["capacity_used_bits"], 74);
+        assert_eq!(report["capacity_bits"], 128);
+        assert_eq!(report["capacity_used_percent"], 57.8);
+    }
+
+    #[test]
+    fn test_bitstream_dump() {
+        let code = QrCode::new(b"01234567").unwrap();
+        let dump = bitstream_dump(&code, b"01234567", EncodeOptions::default()).unwrap();
+
+        // Numeric mode 0001, then a 10-bit count of 8; version 1-M has 16 data
+        // and 10 EC codewords
+        assert!(dump.contains("error correction M, 41 data bits"));
+        assert!(dump.contains("Data codewords (16):\n  hex: 10 20 0c"));
+        assert!(dump.contains("  bin: 00010000 00100000 00001100"));
+        assert!(dump.contains("EC codewords (10):"));
+    }
+
+    #[test]
+    fn test_matrix_dump() {
+        let code = QrCode::new(b"hello").unwrap();
+        let dump = matrix_dump(&code);
+        let mut lines = dump.lines();
+
+        assert_eq!(lines.next(), Some("Matrix: QR version 1, 21x21 modules"));
+        let rows: Vec<&str> = lines.collect();
+        assert_eq!(rows.len(), 21);
+        assert!(rows.iter().all(|row| row.len() == 21));
+        // Top edge of the top-left finder, its light separator, then timing
+        assert!(rows[0].starts_with("11111110"));
+        assert!(rows[6].starts_with("1111111010101"));
+    }
+
+    #[test]
+    fn test_format_from_path_case_insensitive() {
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.TIFF")),
+            Some(OutputFormat::Tiff)
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.tif")),
+            Some(OutputFormat::Tiff)
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.jpg")),
+            Some(OutputFormat::Jpeg)
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.png")),
+            Some(OutputFormat::Png)
+        );
+        assert_eq!(OutputFormat::from_path(Path::new("out")), None);
+    }
+
+    #[test]
+    fn test_resolve_format_flag_wins() {
+        let format = resolve_format(Some("svg"), Path::new("out.png")).unwrap();
+        assert_eq!(format, OutputFormat::Svg);
+    }
+
+    #[test]
+    fn test_resolve_format_without_extension() {
+        let format = resolve_format(Some("png"), Path::new("plainname")).unwrap();
+        assert_eq!(format, OutputFormat::Png);
+        assert!(resolve_format(None, Path::new("plainname")).is_err());
+    }
+
+    #[test]
+    fn test_matrix_json_shape() {
+        let code = QrCode::new("test").unwrap();
+        let json = matrix_json(&code);
+
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["width"], 21);
+        let rows = json["modules"].as_array().unwrap();
+        assert_eq!(rows.len(), 21);
+        assert!(rows.iter().all(|row| row.as_array().unwrap().len() == 21));
+        // Top-left finder pattern corner is always dark
+        assert_eq!(rows[0][0], true);
+    }
+
+    #[test]
+    fn test_tiff_compression_names() {
+        assert!(tiff_compression("none").unwrap() == Compression::Uncompressed);
+        assert!(tiff_compression("lzw").unwrap() == Compression::Lzw);
+        assert!(tiff_compression("zip").is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use clap::{Args, Parser};
+use std::path::PathBuf;
+
+use crate::{decode_qr_image, Cli};
+
+#[derive(Args, Debug, Clone)]
+pub struct ReencodeArgs {
+    /// Image holding the QR code to regenerate
+    input: PathBuf,
+
+    /// Generation options applied to the recovered data, e.g. --size 20 --fill-color red
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    options: Vec<String>,
+}
+
+/// Decode the code in the input image and build the command line that
+/// regenerates it: the recovered text as the data, styled by the given options
+pub fn cli_for(args: &ReencodeArgs) -> Result<Cli> {
+    let img = image::open(&args.input)
+        .with_context(|| format!("Failed to open {}", args.input.display()))?
+        .to_rgba8();
+    let decoded = decode_qr_image(&img)
+        .with_context(|| format!("Failed to read a QR code from {}", args.input.display()))?;
+    let data = String::from_utf8(decoded).map_err(|_| {
+        anyhow::anyhow!(
+            "The code in {} doesn't hold UTF-8 text, so it can't be re-encoded",
+            args.input.display()
+        )
+    })?;
+
+    // After `--` so payloads starting with a dash aren't taken for options
+    let argv = std::iter::once("qrgen")
+        .chain(args.options.iter().map(String::as_str))
+        .chain(["--", data.as_str()]);
+    Ok(Cli::parse_from(argv))
+}
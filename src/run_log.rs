@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::output::OutputFormat;
+
+/// What `--log-file` and `--json-output` record about one generated code
+pub struct LogEntry<'a> {
+    pub data: &'a [u8],
+    pub output: &'a Path,
+    pub format: OutputFormat,
+    /// e.g. "QR version 3" or "Data Matrix 10x10"
+    pub symbol: String,
+    /// QR version, for symbologies that have one
+    pub version: Option<i16>,
+    /// Error correction level, for symbologies that have a choice
+    pub error_correction: Option<String>,
+    /// Width and height in pixels, or in modules for JSON output
+    pub dimensions: (u32, u32),
+}
+
+/// Hex SHA-256 of the payload, so the log identifies codes without storing
+/// their contents
+pub fn data_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// One log line: a UTC timestamp followed by `key=value` fields
+fn format_entry(entry: &LogEntry, time: SystemTime) -> String {
+    format!(
+        "{} sha256={} output={:?} symbol={:?} ec={} size={}x{}\n",
+        humantime::format_rfc3339_seconds(time),
+        data_hash(entry.data),
+        entry.output.display().to_string(),
+        entry.symbol,
+        entry.error_correction.as_deref().unwrap_or("-"),
+        entry.dimensions.0,
+        entry.dimensions.1
+    )
+}
+
+/// Append a line for `entry` to the log, holding an exclusive lock while
+/// writing so concurrent runs sharing a log don't interleave
+pub fn append(log_path: &Path, entry: &LogEntry) -> Result<()> {
+    let line = format_entry(entry, SystemTime::now());
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .with_context(|| format!("Failed to open log file {}", log_path.display()))?;
+
+    file.lock()
+        .with_context(|| format!("Failed to lock log file {}", log_path.display()))?;
+    file.write_all(line.as_bytes())
+        .with_context(|| format!("Failed to write log file {}", log_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_entry() {
+        let entry = LogEntry {
+            data: b"hello",
+            output: Path::new("out/qr code.png"),
+            format: OutputFormat::Png,
+            symbol: "QR version 1".to_string(),
+            version: Some(1),
+            error_correction: Some("M".to_string()),
+            dimensions: (290, 290),
+        };
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        assert_eq!(
+            format_entry(&entry, time),
+            "2023-11-14T22:13:20Z \
+             sha256=2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824 \
+             output=\"out/qr code.png\" symbol=\"QR version 1\" ec=M size=290x290\n"
+        );
+    }
+
+    #[test]
+    fn test_format_entry_never_contains_data() {
+        let entry = LogEntry {
+            data: b"secret-password",
+            output: Path::new("wifi.png"),
+            format: OutputFormat::Png,
+            symbol: "Data Matrix 16x16".to_string(),
+            version: None,
+            error_correction: None,
+            dimensions: (240, 240),
+        };
+        let line = format_entry(&entry, SystemTime::now());
+
+        assert!(!line.contains("secret"));
+        assert!(line.contains(" ec=- "));
+    }
+}
@@ -0,0 +1,223 @@
+use image::Rgba;
+use qrcode::EcLevel;
+use std::sync::Mutex;
+
+use crate::{contrast_ratio, ec_recovery_fraction, MIN_CONTRAST_RATIO};
+
+/// --module-gap above which modules get too thin to threshold reliably
+const AGGRESSIVE_MODULE_GAP: f32 = 0.25;
+
+/// Everything about a rendered code that affects whether it scans
+pub struct ScanSettings {
+    pub fill_color: Rgba<u8>,
+    pub back_color: Rgba<u8>,
+    pub error_correction: EcLevel,
+    /// Fraction of the code hidden behind the logo, if there is one
+    pub logo_coverage: Option<f64>,
+    pub module_gap: f32,
+    pub background_image: bool,
+    /// Dark modules are drawn as a sprite from --module-image
+    pub module_image: bool,
+    /// Finder patterns are drawn with a --finder-shape other than square
+    pub styled_finders: bool,
+}
+
+/// Advisories for settings that are likely to produce a code scanners
+/// struggle with; empty when nothing looks risky
+pub fn scannability_warnings(settings: &ScanSettings) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    // A photo or a transparent background replaces the back color, so
+    // contrast can't be judged here
+    if !settings.background_image && settings.back_color[3] == 255 {
+        let ratio = contrast_ratio(settings.fill_color, settings.back_color);
+        if ratio < MIN_CONTRAST_RATIO {
+            let fill = settings.fill_color;
+            warnings.push(format!(
+                "fill color #{:02x}{:02x}{:02x} has low contrast against the background ({:.1}:1); the code may not scan",
+                fill[0], fill[1], fill[2], ratio
+            ));
+        }
+    }
+
+    if let Some(coverage) = settings.logo_coverage {
+        let recoverable = ec_recovery_fraction(settings.error_correction);
+        if coverage > recoverable {
+            warnings.push(format!(
+                "logo covers about {:.0}% of the code but error correction {:?} only recovers about {:.0}%; the code may not scan",
+                coverage * 100.0,
+                settings.error_correction,
+                recoverable * 100.0
+            ));
+        }
+    }
+
+    if settings.module_gap > AGGRESSIVE_MODULE_GAP {
+        warnings.push(
+            "large --module-gap values can make the code hard to scan; check the result with --verify"
+                .to_string(),
+        );
+    }
+
+    // Photos make light modules unpredictable, so be loud about the risk
+    if settings.background_image {
+        warnings.push(
+            "--background-image lets the photo show through light modules; many photos will make the code unscannable. Check the result with --verify"
+                .to_string(),
+        );
+        if settings.error_correction != EcLevel::H {
+            warnings.push(
+                "error correction H (-e H) is strongly recommended with --background-image"
+                    .to_string(),
+            );
+        }
+    }
+
+    if settings.module_image {
+        warnings.push(
+            "--module-image replaces dark modules with a picture, which scanners may not read as dark; check the result with --verify"
+                .to_string(),
+        );
+    }
+
+    if settings.styled_finders {
+        warnings.push(
+            "--finder-shape styles other than square may not scan on every reader; check the result with --verify"
+                .to_string(),
+        );
+    }
+
+    warnings
+}
+
+/// Print each warning to stderr, skipping any already printed so batch runs
+/// don't repeat the same advice for every code
+pub fn print_warnings(warnings: Vec<String>) {
+    static PRINTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    let mut printed = PRINTED.lock().unwrap_or_else(|e| e.into_inner());
+    for warning in warnings {
+        if !printed.contains(&warning) {
+            eprintln!("Warning: {}", warning);
+            printed.push(warning);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safe_settings() -> ScanSettings {
+        ScanSettings {
+            fill_color: Rgba([0, 0, 0, 255]),
+            back_color: Rgba([255, 255, 255, 255]),
+            error_correction: EcLevel::M,
+            logo_coverage: None,
+            module_gap: 0.0,
+            background_image: false,
+            module_image: false,
+            styled_finders: false,
+        }
+    }
+
+    #[test]
+    fn test_no_warnings_for_safe_settings() {
+        assert!(scannability_warnings(&safe_settings()).is_empty());
+    }
+
+    #[test]
+    fn test_transparent_background_skips_contrast() {
+        let settings = ScanSettings {
+            back_color: Rgba([0, 0, 0, 0]),
+            ..safe_settings()
+        };
+        assert!(scannability_warnings(&settings).is_empty());
+    }
+
+    #[test]
+    fn test_low_contrast_warning() {
+        let settings = ScanSettings {
+            fill_color: Rgba([255, 255, 0, 255]),
+            ..safe_settings()
+        };
+        let warnings = scannability_warnings(&settings);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("fill color #ffff00 has low contrast"));
+    }
+
+    #[test]
+    fn test_logo_coverage_warning() {
+        let settings = ScanSettings {
+            logo_coverage: Some(0.2),
+            ..safe_settings()
+        };
+        assert_eq!(
+            scannability_warnings(&settings),
+            ["logo covers about 20% of the code but error correction M only recovers about 15%; the code may not scan"]
+        );
+
+        let settings = ScanSettings {
+            error_correction: EcLevel::H,
+            ..settings
+        };
+        assert!(scannability_warnings(&settings).is_empty());
+    }
+
+    #[test]
+    fn test_module_gap_warning() {
+        let settings = ScanSettings {
+            module_gap: 0.4,
+            ..safe_settings()
+        };
+        let warnings = scannability_warnings(&settings);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("large --module-gap values"));
+    }
+
+    #[test]
+    fn test_background_without_h_warning() {
+        // Contrast is skipped with a photo, even for a light fill
+        let settings = ScanSettings {
+            fill_color: Rgba([250, 250, 250, 255]),
+            background_image: true,
+            ..safe_settings()
+        };
+        let warnings = scannability_warnings(&settings);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("--background-image lets the photo show through"));
+        assert_eq!(
+            warnings[1],
+            "error correction H (-e H) is strongly recommended with --background-image"
+        );
+
+        // At H only the photo itself is worth a warning
+        let settings = ScanSettings {
+            error_correction: EcLevel::H,
+            ..settings
+        };
+        assert_eq!(scannability_warnings(&settings).len(), 1);
+    }
+
+    #[test]
+    fn test_module_image_warning() {
+        let settings = ScanSettings {
+            module_image: true,
+            ..safe_settings()
+        };
+        let warnings = scannability_warnings(&settings);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("--module-image replaces dark modules"));
+    }
+
+    #[test]
+    fn test_styled_finders_warning() {
+        let settings = ScanSettings {
+            styled_finders: true,
+            ..safe_settings()
+        };
+        let warnings = scannability_warnings(&settings);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("--finder-shape styles"));
+    }
+}
@@ -0,0 +1,232 @@
+use qrcode::bits::Bits;
+use qrcode::optimize::{total_encoded_len, Parser, Segment};
+use qrcode::types::{Mode, QrResult, Version};
+
+/// How the payload is turned into bits, independent of the error correction
+/// level
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EncodeOptions {
+    /// ECI designator to prepend so readers know the byte encoding
+    pub eci: Option<u32>,
+    /// Search for the cheapest split into numeric, alphanumeric and byte
+    /// segments instead of relying on the qrcode crate's optimizer alone
+    pub optimize_segments: bool,
+    /// Smallest QR version to encode at, even when the data fits in fewer modules
+    pub min_version: Option<i16>,
+}
+
+const MODES: [Mode; 3] = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte];
+
+fn allows(mode: Mode, byte: u8) -> bool {
+    match mode {
+        Mode::Numeric => byte.is_ascii_digit(),
+        Mode::Alphanumeric => {
+            byte.is_ascii_digit() || byte.is_ascii_uppercase() || b" $%*+-./:".contains(&byte)
+        }
+        _ => true,
+    }
+}
+
+/// Characters per group and the bits each character in a group adds: numeric
+/// packs 3 digits into 10 bits (4 + 3 + 3), alphanumeric 2 characters into 11
+fn char_costs(mode: Mode) -> &'static [usize] {
+    match mode {
+        Mode::Numeric => &[4, 3, 3],
+        Mode::Alphanumeric => &[6, 5],
+        _ => &[8],
+    }
+}
+
+/// Cheapest split of `data` into numeric, alphanumeric and byte segments at
+/// `version`. Each state tracks a mode and where the next character falls
+/// in that mode's group, so the costs are exact rather than averaged.
+pub fn optimal_segments(data: &[u8], version: Version) -> Vec<Segment> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+
+    // States are (mode, position in group); only 3 + 2 + 1 of the 3 x 3 are used
+    let state = |m: usize, phase: usize| m * 3 + phase;
+    let header = |mode: Mode| version.mode_bits_count() + mode.length_bits_count(version);
+
+    let mut cost = vec![[usize::MAX; 9]; data.len()];
+    // Predecessor state, or None where a new segment started
+    let mut from = vec![[None; 9]; data.len()];
+
+    for (i, &byte) in data.iter().enumerate() {
+        let best_previous = (i > 0)
+            .then(|| {
+                (0..9)
+                    .filter(|&s| cost[i - 1][s] != usize::MAX)
+                    .min_by_key(|&s| cost[i - 1][s])
+            })
+            .flatten();
+
+        for (m, &mode) in MODES.iter().enumerate() {
+            if !allows(mode, byte) {
+                continue;
+            }
+            let costs = char_costs(mode);
+
+            // Start a new segment here
+            let start = best_previous.map_or(0, |s| cost[i - 1][s]) + header(mode) + costs[0];
+            let target = state(m, 1 % costs.len());
+            if start < cost[i][target] {
+                cost[i][target] = start;
+                from[i][target] = None;
+            }
+
+            // Or extend a segment of the same mode
+            if i == 0 {
+                continue;
+            }
+            for (phase, &char_cost) in costs.iter().enumerate() {
+                let previous = cost[i - 1][state(m, phase)];
+                if previous == usize::MAX {
+                    continue;
+                }
+                let target = state(m, (phase + 1) % costs.len());
+                if previous + char_cost < cost[i][target] {
+                    cost[i][target] = previous + char_cost;
+                    from[i][target] = Some(state(m, phase));
+                }
+            }
+        }
+    }
+
+    // Walk back from the cheapest final state, closing a segment at each start
+    let last = data.len() - 1;
+    let mut s = (0..9)
+        .filter(|&s| cost[last][s] != usize::MAX)
+        .min_by_key(|&s| cost[last][s])
+        .expect("byte mode accepts every character");
+    let mut segments = Vec::new();
+    let mut end = data.len();
+    for i in (0..data.len()).rev() {
+        match from[i][s] {
+            Some(previous) => s = previous,
+            None => {
+                segments.push(Segment {
+                    mode: MODES[s / 3],
+                    begin: i,
+                    end,
+                });
+                end = i;
+                if i > 0 {
+                    s = (0..9)
+                        .filter(|&p| cost[i - 1][p] != usize::MAX)
+                        .min_by_key(|&p| cost[i - 1][p])
+                        .expect("byte mode accepts every character");
+                }
+            }
+        }
+    }
+    segments.reverse();
+    segments
+}
+
+/// Segments the data is encoded as at `version`. With `optimize_segments`,
+/// the exact split is used when it beats the qrcode crate's optimizer, which
+/// also knows Kanji mode.
+pub fn choose_segments(data: &[u8], version: Version, encode_opts: EncodeOptions) -> Vec<Segment> {
+    let theirs: Vec<Segment> = Parser::new(data).optimize(version).collect();
+    if !encode_opts.optimize_segments {
+        return theirs;
+    }
+
+    let ours = optimal_segments(data, version);
+    if total_encoded_len(&ours, version) < total_encoded_len(&theirs, version) {
+        ours
+    } else {
+        theirs
+    }
+}
+
+/// Push the ECI designator (if any) and the data
+pub fn push_data(bits: &mut Bits, data: &[u8], encode_opts: EncodeOptions) -> QrResult<()> {
+    if let Some(designator) = encode_opts.eci {
+        bits.push_eci_designator(designator)?;
+    }
+    let segments = choose_segments(data, bits.version(), encode_opts);
+    bits.push_segments(data, segments.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimal_segments_splits_long_digit_run() {
+        let data = b"https://example.com/0123456789012345678901234567890123456789";
+        let segments = optimal_segments(data, Version::Normal(5));
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].mode, Mode::Byte);
+        assert_eq!(segments[1].mode, Mode::Numeric);
+        assert_eq!(segments[1].begin, 20);
+        assert_eq!(segments[1].end, data.len());
+    }
+
+    #[test]
+    fn test_optimal_segments_keeps_short_runs_together() {
+        // Five digits don't pay for a second segment header
+        let segments = optimal_segments(b"HTTP://EXAMPLE.COM/12345", Version::Normal(2));
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].mode, Mode::Alphanumeric);
+    }
+
+    #[test]
+    fn test_optimal_segments_never_worse_than_crate() {
+        let payloads: [&[u8]; 6] = [
+            b"HTTP://EXAMPLE.COM/12345",
+            b"WIFI:S:Cafe 42;T:WPA;P:hunter2;;",
+            b"Order 1234567890 SHIPPED",
+            b"0123456789ABCDEFabcdef012345",
+            "héllo 2024 WORLD".as_bytes(),
+            b"https://x.io/AB12CD34EF56GH78IJ90KL12MN34",
+        ];
+        for data in payloads {
+            for v in [1, 10, 27] {
+                let version = Version::Normal(v);
+                let ours = total_encoded_len(&optimal_segments(data, version), version);
+                let theirs: Vec<Segment> = Parser::new(data).optimize(version).collect();
+                assert!(ours <= total_encoded_len(&theirs, version));
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimal_segments_beats_crate_on_alternating_runs() {
+        // The crate keeps the whole path in byte mode; splitting out the
+        // alphanumeric tail saves 59 bits
+        let data = b"https://x.io/AB12CD34EF56GH78IJ90KL12MN34";
+        let version = Version::Normal(1);
+        let theirs: Vec<Segment> = Parser::new(data).optimize(version).collect();
+        assert_eq!(total_encoded_len(&theirs, version), 340);
+        assert_eq!(
+            total_encoded_len(&optimal_segments(data, version), version),
+            281
+        );
+    }
+
+    #[test]
+    fn test_segmented_beats_byte_mode() {
+        let data = b"HTTP://EXAMPLE.COM/ORDER/12345678901234567890";
+        let smallest = |push: &dyn Fn(&mut Bits) -> QrResult<()>| {
+            (1..=40)
+                .find(|&v| {
+                    let mut bits = Bits::new(Version::Normal(v));
+                    push(&mut bits).is_ok() && bits.push_terminator(qrcode::EcLevel::M).is_ok()
+                })
+                .unwrap()
+        };
+
+        let encode_opts = EncodeOptions {
+            optimize_segments: true,
+            ..EncodeOptions::default()
+        };
+        let segmented = smallest(&|bits| push_data(bits, data, encode_opts));
+        let byte_only = smallest(&|bits| bits.push_byte_data(data));
+        assert!(segmented < byte_only, "{} vs {}", segmented, byte_only);
+    }
+}
@@ -0,0 +1,181 @@
+#[cfg(feature = "net")]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(feature = "net")]
+use std::time::Duration;
+
+/// Stands in for the long URL in `--shortener-url`
+const URL_PLACEHOLDER: &str = "{url}";
+
+/// How long to wait on the shortener before giving up on it
+#[cfg(feature = "net")]
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Check a `--shortener-url` value: an http(s) endpoint with a `{url}`
+/// placeholder for the long URL
+pub fn parse_endpoint(s: &str) -> Result<String, String> {
+    if !s.contains(URL_PLACEHOLDER) {
+        return Err(format!(
+            "expected a {} placeholder for the long URL, e.g. https://is.gd/create.php?format=simple&url={}",
+            URL_PLACEHOLDER, URL_PLACEHOLDER
+        ));
+    }
+    match url::Url::parse(&s.replace(URL_PLACEHOLDER, "x")) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(s.to_string()),
+        _ => Err("expected an http:// or https:// endpoint".to_string()),
+    }
+}
+
+/// Whether `data` is an http(s) URL worth shortening
+pub fn is_web_url(data: &str) -> bool {
+    url::Url::parse(data).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// The request URL: `endpoint` with the long URL percent-encoded into its
+/// placeholder
+#[cfg(feature = "net")]
+fn request_url(endpoint: &str, long_url: &str) -> String {
+    let encoded: String = url::form_urlencoded::byte_serialize(long_url.as_bytes()).collect();
+    endpoint.replace(URL_PLACEHOLDER, &encoded)
+}
+
+/// The short URL in a shortener's response body, which must be nothing but
+/// an http(s) URL
+#[cfg(feature = "net")]
+fn short_url_from(body: &str) -> Result<String> {
+    let short = body.trim();
+    if !is_web_url(short) {
+        return Err(anyhow::anyhow!(
+            "Shortener replied {:?}, which isn't a URL",
+            short
+        ));
+    }
+    Ok(short.to_string())
+}
+
+/// POST `long_url` to the shortener at `endpoint` and return the short URL
+/// it replies with
+#[cfg(feature = "net")]
+pub fn shorten(endpoint: &str, long_url: &str) -> Result<String> {
+    let response = ureq::post(&request_url(endpoint, long_url))
+        .timeout(TIMEOUT)
+        .call()
+        .context("URL shortener request failed")?;
+    let body = response
+        .into_string()
+        .context("Failed to read the URL shortener's reply")?;
+    short_url_from(&body)
+}
+
+/// Without the `net` feature there is no HTTP client to shorten with
+#[cfg(not(feature = "net"))]
+pub fn shorten(_endpoint: &str, _long_url: &str) -> Result<String> {
+    Err(anyhow::anyhow!(
+        "qrgen was built without the `net` feature, so --shorten can't reach a shortener"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoint() {
+        let endpoint = "https://is.gd/create.php?format=simple&url={url}";
+        assert_eq!(parse_endpoint(endpoint).unwrap(), endpoint);
+        assert!(parse_endpoint("https://is.gd/create.php").is_err());
+        assert!(parse_endpoint("ftp://example.com/{url}").is_err());
+        assert!(parse_endpoint("{url}").is_err());
+    }
+}
+
+// The client side only exists with the `net` feature; the shortener is a
+// local mock server
+#[cfg(all(test, feature = "net"))]
+mod net_tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Answer one request on a local port with `status` and `body`. The
+    /// handle yields the request line the server saw.
+    fn serve_once(status: &str, body: &str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!(
+            "http://{}/create?url={}",
+            listener.local_addr().unwrap(),
+            URL_PLACEHOLDER
+        );
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            let request_line = lines.next().unwrap().unwrap();
+            // Skip the headers; the request has no body
+            for line in lines {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            request_line
+        });
+        (endpoint, handle)
+    }
+
+    #[test]
+    fn test_request_url_encodes_long_url() {
+        assert_eq!(
+            request_url(
+                "https://sho.rt/new?u={url}",
+                "https://example.com/a?b=c&d=e f"
+            ),
+            "https://sho.rt/new?u=https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc%26d%3De+f"
+        );
+    }
+
+    #[test]
+    fn test_short_url_from() {
+        assert_eq!(
+            short_url_from("https://sho.rt/abc\n").unwrap(),
+            "https://sho.rt/abc"
+        );
+        assert!(short_url_from("<html>rate limited</html>").is_err());
+        assert!(short_url_from("").is_err());
+    }
+
+    #[test]
+    fn test_shorten_with_mock_server() {
+        let (endpoint, server) = serve_once("200 OK", "https://sho.rt/x1\n");
+        let short = shorten(&endpoint, "https://example.com/long?page=1").unwrap();
+        assert_eq!(short, "https://sho.rt/x1");
+        assert_eq!(
+            server.join().unwrap(),
+            "POST /create?url=https%3A%2F%2Fexample.com%2Flong%3Fpage%3D1 HTTP/1.1"
+        );
+    }
+
+    #[test]
+    fn test_shorten_rejects_error_status() {
+        let (endpoint, server) = serve_once("500 Internal Server Error", "oops");
+        assert!(shorten(&endpoint, "https://example.com").is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_shorten_unreachable() {
+        // Bind then drop a listener to find a port nothing is listening on
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let endpoint = format!("http://{}/create?url={{url}}", addr);
+        assert!(shorten(&endpoint, "https://example.com").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use anyhow::{Context, Result};
+use image::Rgba;
+use qrcode::{Color, QrCode};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::{in_finder_pattern, palette_color};
+
+pub fn hex_color(color: Rgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// How modules are written out, beyond size and colors
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SvgOptions {
+    /// Fraction of each module left empty between dark modules
+    pub module_gap: f32,
+    /// Merge horizontal runs of full-size, same-colored dark modules into one `<rect>`
+    pub optimize: bool,
+    /// Style through CSS classes in a `<style>` block instead of inline fills
+    pub css: bool,
+    /// Leave out the background `<rect>` so light modules are transparent
+    pub transparent: bool,
+}
+
+/// `<style>` block giving the classed background and modules their default
+/// colors, with one extra class per palette color
+fn style_block(fill: &[Rgba<u8>], back_color: Rgba<u8>) -> String {
+    let mut style = String::from("<style>\n");
+    let _ = writeln!(
+        style,
+        ".qr-background {{ fill: {}; }}",
+        hex_color(back_color)
+    );
+    let _ = writeln!(style, ".qr-module {{ fill: {}; }}", hex_color(fill[0]));
+    if fill.len() > 1 {
+        for (i, &color) in fill.iter().enumerate() {
+            let _ = writeln!(style, ".qr-color-{} {{ fill: {}; }}", i, hex_color(color));
+        }
+    }
+    style.push_str("</style>\n");
+    style
+}
+
+/// Render the code as SVG in module units, scaled so each module is `size`
+/// pixels. Every dark module is its own `<rect>`, shrunk by the module gap
+/// outside the finder patterns and colored by `palette_color`.
+pub fn render_svg(
+    code: &QrCode,
+    size: u32,
+    border: u32,
+    fill: &[Rgba<u8>],
+    back_color: Rgba<u8>,
+    options: &SvgOptions,
+) -> String {
+    let qr_width = code.width() as u32;
+    let total = qr_width + 2 * border;
+    let px = total * size;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{px}" height="{px}" viewBox="0 0 {total} {total}" shape-rendering="crispEdges">"#
+    );
+    if options.css {
+        svg.push_str(&style_block(fill, back_color));
+    }
+    match (options.transparent, options.css) {
+        // Only the dark modules are drawn
+        (true, _) => {}
+        (false, true) => {
+            let _ = writeln!(
+                svg,
+                r#"<rect class="qr-background" width="{total}" height="{total}"/>"#
+            );
+        }
+        (false, false) => {
+            let _ = writeln!(
+                svg,
+                r#"<rect width="{total}" height="{total}" fill="{}"/>"#,
+                hex_color(back_color)
+            );
+        }
+    }
+
+    let color_at = |x: u32, y: u32| palette_color(fill, x, y, qr_width);
+    let paint = |color: Rgba<u8>| {
+        if !options.css {
+            format!(r#"fill="{}""#, hex_color(color))
+        } else if fill.len() > 1 {
+            let index = fill.iter().position(|&c| c == color).unwrap_or(0);
+            format!(r#"class="qr-module qr-color-{}""#, index)
+        } else {
+            r#"class="qr-module""#.to_string()
+        }
+    };
+    let is_dark = |x: u32, y: u32| code[(x as usize, y as usize)] == Color::Dark;
+    let gap_at = |x: u32, y: u32| {
+        if in_finder_pattern(x, y, qr_width) {
+            0.0
+        } else {
+            options.module_gap
+        }
+    };
+
+    for y in 0..qr_width {
+        let mut x = 0;
+        while x < qr_width {
+            if !is_dark(x, y) {
+                x += 1;
+                continue;
+            }
+
+            let gap = gap_at(x, y);
+            let color = color_at(x, y);
+            // Only full-size modules can merge without closing a gap
+            let mut run = 1;
+            if options.optimize && gap == 0.0 {
+                while x + run < qr_width
+                    && is_dark(x + run, y)
+                    && gap_at(x + run, y) == 0.0
+                    && color_at(x + run, y) == color
+                {
+                    run += 1;
+                }
+            }
+
+            let inset = gap / 2.0;
+            let side = 1.0 - gap;
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{side}" {}/>"#,
+                (x + border) as f32 + inset,
+                (y + border) as f32 + inset,
+                run as f32 - gap,
+                paint(color)
+            );
+            x += run;
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn save_svg(svg: &str, path: &Path) -> Result<()> {
+    std::fs::write(path, svg).with_context(|| format!("Failed to save QR code to {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_color() {
+        assert_eq!(hex_color(Rgba([255, 87, 51, 255])), "#ff5733");
+    }
+
+    #[test]
+    fn test_render_svg_structure() {
+        let code = QrCode::new("test").unwrap();
+        let svg = render_svg(
+            &code,
+            10,
+            4,
+            &[Rgba([0, 0, 0, 255])],
+            Rgba([255, 255, 255, 255]),
+            &SvgOptions::default(),
+        );
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        // 21 modules + 2 * 4 border, at 10px per module
+        assert!(svg.contains(r#"width="290" height="290" viewBox="0 0 29 29""#));
+        assert!(svg.contains(r##"fill="#ffffff""##));
+
+        let dark = code
+            .to_colors()
+            .iter()
+            .filter(|&&c| c == Color::Dark)
+            .count();
+        assert_eq!(svg.matches(r##"fill="#000000""##).count(), dark);
+        assert!(svg.contains(r#"width="1" height="1""#));
+    }
+
+    #[test]
+    fn test_render_svg_module_gap() {
+        let code = QrCode::new("test").unwrap();
+        let svg = render_svg(
+            &code,
+            10,
+            4,
+            &[Rgba([0, 0, 0, 255])],
+            Rgba([255, 255, 255, 255]),
+            &SvgOptions {
+                module_gap: 0.2,
+                ..SvgOptions::default()
+            },
+        );
+
+        // Finder patterns stay solid; the timing pattern module at (8, 6) is
+        // the first dark module outside them
+        assert!(svg.contains(r#"<rect x="4" y="4" width="1" height="1""#));
+        assert!(svg.contains(r#"<rect x="12.1" y="10.1" width="0.8" height="0.8""#));
+    }
+
+    /// Paint each `<rect>` of the SVG onto a module grid
+    fn rasterize(svg: &str, total: usize) -> Vec<bool> {
+        let mut grid = vec![false; total * total];
+        let attr = |rect: &str, name: &str| -> f32 {
+            let start = rect.find(&format!(" {}=\"", name)).unwrap() + name.len() + 3;
+            rect[start..].split('"').next().unwrap().parse().unwrap()
+        };
+        for rect in svg.lines().filter(|l| l.starts_with("<rect x=")) {
+            let (x, y, w) = (attr(rect, "x"), attr(rect, "y"), attr(rect, "width"));
+            for dx in 0..w as usize {
+                grid[y as usize * total + x as usize + dx] = true;
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn test_render_svg_optimize_merges_runs() {
+        let code = QrCode::new("https://example.com/a/long/path").unwrap();
+        let render = |optimize| {
+            render_svg(
+                &code,
+                10,
+                4,
+                &[Rgba([0, 0, 0, 255])],
+                Rgba([255, 255, 255, 255]),
+                &SvgOptions {
+                    optimize,
+                    ..SvgOptions::default()
+                },
+            )
+        };
+        let plain = render(false);
+        let optimized = render(true);
+
+        // The top row of the top-left finder is a single 7-module run
+        assert!(optimized.contains(r#"<rect x="4" y="4" width="7" height="1""#));
+        assert!(optimized.matches("<rect").count() < plain.matches("<rect").count() / 2);
+
+        let total = code.width() + 8;
+        assert_eq!(rasterize(&plain, total), rasterize(&optimized, total));
+    }
+
+    #[test]
+    fn test_render_svg_optimize_keeps_gaps() {
+        let code = QrCode::new("test").unwrap();
+        let svg = render_svg(
+            &code,
+            10,
+            4,
+            &[Rgba([0, 0, 0, 255])],
+            Rgba([255, 255, 255, 255]),
+            &SvgOptions {
+                module_gap: 0.2,
+                optimize: true,
+                ..SvgOptions::default()
+            },
+        );
+
+        // Finder rows still merge, but gapped modules stay separate
+        assert!(svg.contains(r#"<rect x="4" y="4" width="7" height="1""#));
+        assert!(svg.contains(r#"<rect x="12.1" y="10.1" width="0.8" height="0.8""#));
+    }
+
+    #[test]
+    fn test_render_svg_transparent_omits_background() {
+        let code = QrCode::new("test").unwrap();
+        let svg = render_svg(
+            &code,
+            10,
+            4,
+            &[Rgba([0, 0, 0, 255])],
+            Rgba([255, 255, 255, 255]),
+            &SvgOptions {
+                transparent: true,
+                ..SvgOptions::default()
+            },
+        );
+
+        assert!(!svg.contains(r#"<rect width="29" height="29""#));
+        assert!(!svg.contains(r##"fill="#ffffff""##));
+        assert!(svg.contains(r#"<rect x="4" y="4" width="1" height="1""#));
+    }
+
+    #[test]
+    fn test_render_svg_css_classes() {
+        let code = QrCode::new("test").unwrap();
+        let svg = render_svg(
+            &code,
+            10,
+            4,
+            &[Rgba([0, 0, 128, 255])],
+            Rgba([255, 255, 255, 255]),
+            &SvgOptions {
+                css: true,
+                ..SvgOptions::default()
+            },
+        );
+
+        assert!(svg.contains("<style>"));
+        assert!(svg.contains(".qr-module { fill: #000080; }"));
+        assert!(svg.contains(".qr-background { fill: #ffffff; }"));
+        assert!(svg.contains(r#"<rect class="qr-background" width="29" height="29"/>"#));
+        assert!(svg.contains(r#"class="qr-module""#));
+        assert!(!svg.contains("fill=\""));
+    }
+
+    #[test]
+    fn test_render_svg_css_palette_classes() {
+        let code = QrCode::new("https://example.com").unwrap();
+        let palette = [Rgba([0, 0, 0, 255]), Rgba([0, 0, 128, 255])];
+        let svg = render_svg(
+            &code,
+            10,
+            4,
+            &palette,
+            Rgba([255, 255, 255, 255]),
+            &SvgOptions {
+                css: true,
+                ..SvgOptions::default()
+            },
+        );
+
+        assert!(svg.contains(".qr-color-1 { fill: #000080; }"));
+        assert!(svg.contains(r#"class="qr-module qr-color-0""#));
+        assert!(svg.contains(r#"class="qr-module qr-color-1""#));
+    }
+}
@@ -1,109 +1,426 @@
 use anyhow::Result;
+use base64::Engine;
+use qrcode::EcLevel;
 use std::io::{self, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-pub fn apply_template(template_type: &str, data: &str) -> Result<String> {
+use crate::run_log;
+
+/// How template input is split into fields
+#[derive(Clone, Copy, Debug)]
+pub struct FieldOptions {
+    pub separator: char,
+    /// Strip whitespace around each field, so "John , 123" reads as "John" and "123"
+    pub trim: bool,
+}
+
+impl FieldOptions {
+    /// Split into at most `limit` fields; the last one keeps any further separators
+    fn split<'a>(&self, data: &'a str, limit: usize) -> Vec<&'a str> {
+        data.splitn(limit, self.separator)
+            .map(|field| if self.trim { field.trim() } else { field })
+            .collect()
+    }
+
+    /// Split at every separator, for templates that take an exact number of
+    /// fields and reject extras
+    fn split_all<'a>(&self, data: &'a str) -> Vec<&'a str> {
+        data.split(self.separator)
+            .map(|field| if self.trim { field.trim() } else { field })
+            .collect()
+    }
+}
+
+/// Switches for the WiFi template
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WifiOptions {
+    /// Reject encryption types that aren't WPA, WEP or nopass (or an alias)
+    /// instead of falling back to WPA
+    pub strict_encryption: bool,
+    /// Mark the network as hidden, so phones look for it by name
+    pub hidden: bool,
+}
+
+/// Whether templates may ask on the terminal for fields missing from the input
+#[derive(Clone, Copy, Debug)]
+pub struct PromptOptions {
+    /// When false, input with too few fields is an error instead
+    pub enabled: bool,
+    /// How long each prompt waits for an answer; `None` waits forever
+    pub timeout: Option<Duration>,
+}
+
+/// Field splitting plus the per-template format switches
+#[derive(Clone, Copy, Debug)]
+pub struct TemplateOptions<'a> {
+    pub fields: FieldOptions,
+    /// vCard format to emit, "3.0" or "4.0"
+    pub vcard_version: &'a str,
+    /// SMS payload form, "smsto" (`SMSTO:number:body`) or "uri" (`sms:number?body=...`)
+    pub sms_format: &'a str,
+    pub prompts: PromptOptions,
+    pub wifi: WifiOptions,
+}
+
+/// Usage details for one template, shown by --template-help
+pub struct TemplateInfo {
+    pub name: &'static str,
+    pub summary: &'static str,
+    /// Fields in input order as (name, description); optional ones follow
+    /// the required ones
+    pub required: &'static [(&'static str, &'static str)],
+    pub optional: &'static [(&'static str, &'static str)],
+    /// Escaping and validation rules worth knowing before writing input
+    pub notes: &'static str,
+    /// Input for the runnable example
+    pub example: &'static str,
+}
+
+/// Every template `--template` accepts, in the order they're listed
+pub const TEMPLATES: &[TemplateInfo] = &[
+    TemplateInfo {
+        name: "wifi",
+        summary: "Join a WiFi network",
+        required: &[
+            ("ssid", "Network name"),
+            ("password", "Network password; leave empty for open networks"),
+            ("encryption", "WPA, WEP or nopass (WPA2 and WPA3 count as WPA)"),
+        ],
+        optional: &[],
+        notes: "\\ ; , : and \" in the SSID and password are escaped for you. \
+                --wifi-hidden marks a hidden network and --strict-wifi-encryption rejects \
+                unknown encryption types instead of using WPA. With too few fields, \
+                qrgen asks for each one.",
+        example: "MyNetwork,mypassword,WPA",
+    },
+    TemplateInfo {
+        name: "vcard",
+        summary: "Contact card",
+        required: &[("name", "Full name"), ("phone", "Phone number")],
+        optional: &[
+            ("email", "Email address"),
+            ("organization", "Company or organization"),
+            ("title", "Job title"),
+            ("website", "Website URL"),
+            ("address", "Postal address; may contain the separator"),
+        ],
+        notes: "Pick the format with --vcard-version 3.0 or 4.0. Commas in the \
+                address are escaped for you. With too few fields, qrgen asks for each one.",
+        example: "John Doe,+1234567890,john@example.com,Acme Corp",
+    },
+    TemplateInfo {
+        name: "sms",
+        summary: "Text message with an optional prefilled body",
+        required: &[("phone", "Recipient's number")],
+        optional: &[("message", "Message text; may contain the separator")],
+        notes: "Pick the format with --sms-format smsto or uri; the uri form \
+                percent-encodes the message for you.",
+        example: "+1234567890,Hello from qrgen",
+    },
+    TemplateInfo {
+        name: "email",
+        summary: "mailto: link with subject and body",
+        required: &[("address", "Recipient's email address")],
+        optional: &[
+            ("subject", "Subject line"),
+            ("body", "Message body; may contain the separator"),
+        ],
+        notes: "",
+        example: "hello@example.com,Question,Hi there",
+    },
+    TemplateInfo {
+        name: "phone",
+        summary: "tel: link to call a number",
+        required: &[("number", "Phone number; the whole input is used")],
+        optional: &[],
+        notes: "",
+        example: "+1234567890",
+    },
+    TemplateInfo {
+        name: "whatsapp",
+        summary: "WhatsApp click-to-chat link",
+        required: &[("phone", "Full international number; only digits are kept")],
+        optional: &[("message", "Prefilled message; may contain the separator")],
+        notes: "The message is percent-encoded for you.",
+        example: "+44 7700 900123,Hi there",
+    },
+    TemplateInfo {
+        name: "app",
+        summary: "App download link with store URLs checked",
+        required: &[
+            ("ios_url", "App Store URL"),
+            ("android_url", "Google Play URL"),
+            ("fallback_url", "Landing page that redirects by platform; this is encoded"),
+        ],
+        optional: &[],
+        notes: "All three must be valid URLs. A code holds one URL, so only the \
+                fallback is encoded.",
+        example: "https://apps.apple.com/app/id123,https://play.google.com/store/apps/details?id=com.example,https://example.com/app",
+    },
+    TemplateInfo {
+        name: "zatca",
+        summary: "Saudi e-invoice (ZATCA) TLV record",
+        required: &[
+            ("seller", "Seller name"),
+            ("vat_number", "15 digits starting and ending with 3"),
+            ("timestamp", "Invoice time, e.g. 2022-04-25T15:30:00Z"),
+            ("total", "Invoice total including VAT"),
+            ("vat_total", "VAT amount"),
+        ],
+        optional: &[],
+        notes: "Totals must be plain non-negative numbers and each field at most \
+                255 bytes. The record is base64-encoded.",
+        example: "Bobs Records,310122393500003,2022-04-25T15:30:00Z,1000.00,150.00",
+    },
+    TemplateInfo {
+        name: "spotify",
+        summary: "open.spotify.com link",
+        required: &[
+            ("type", "track, album, playlist or artist"),
+            ("id", "Letters and digits at the end of a share link"),
+        ],
+        optional: &[],
+        notes: "",
+        example: "track,4uLU6hMCjMI75M1A2tKUQC",
+    },
+    TemplateInfo {
+        name: "calendar",
+        summary: "Calendar event (VEVENT)",
+        required: &[
+            ("summary", "Event title"),
+            ("start", "YYYY-MM-DD, or a date and time like 2026-10-17T09:00[Z]"),
+            ("end", "Same format as start; not before it"),
+        ],
+        optional: &[("location", "Where the event happens; may contain the separator")],
+        notes: "A trailing Z means UTC; without it the time is local to whoever \
+                scans. Commas, semicolons and backslashes in text are escaped for you. \
+                --also-ics writes the same event as an invite file.",
+        example: "Team sync,2026-10-17T09:00Z,2026-10-17T10:00Z,Room 4",
+    },
+];
+
+/// Detailed usage for `--template-help`: fields, rules, the default error
+/// correction and a runnable example, or `None` for an unknown template
+pub fn template_help(name: &str) -> Option<String> {
+    let info = TEMPLATES.iter().find(|info| info.name == name)?;
+
+    let mut help = format!(
+        "{} - {}\n\nFields, separated by --separator (default \",\"):\n",
+        info.name, info.summary
+    );
+    let width = info
+        .required
+        .iter()
+        .chain(info.optional)
+        .map(|(field, _)| field.len())
+        .max()
+        .unwrap_or(0);
+    for (fields, label) in [(info.required, "required"), (info.optional, "optional")] {
+        for (field, description) in fields {
+            help.push_str(&format!(
+                "  {:width$}  ({}) {}\n",
+                field,
+                label,
+                description,
+                width = width
+            ));
+        }
+    }
+    if !info.notes.is_empty() {
+        help.push_str(&format!("\n{}\n", info.notes));
+    }
+    if let Some(level) = default_error_correction(info.name) {
+        help.push_str(&format!("\nDefault error correction: {:?}\n", level));
+    }
+    help.push_str(&format!(
+        "\nExample:\n  qrgen \"{}\" --template {} -o {}.png\n",
+        info.example, info.name, info.name
+    ));
+    Some(help)
+}
+
+/// Print `label` and read one trimmed line from stdin. With a timeout the read
+/// happens on a helper thread so an unanswered prompt can be abandoned.
+fn prompt(label: &str, timeout: Option<Duration>) -> Result<String> {
+    print!("{}: ", label);
+    io::stdout().flush()?;
+
+    let Some(timeout) = timeout else {
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        return Ok(answer.trim().to_string());
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut answer = String::new();
+        let _ = sender.send(io::stdin().read_line(&mut answer).map(|_| answer));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(answer) => Ok(answer?.trim().to_string()),
+        Err(_) => Err(anyhow::anyhow!(
+            "No answer to \"{}\" within {:?}",
+            label,
+            timeout
+        )),
+    }
+}
+
+/// Error correction a template's payload gets unless -e is given. Codes
+/// scanned once at arm's length (WiFi, contacts) stay compact at L, while
+/// links that end up on posters and packaging get H.
+pub fn default_error_correction(template_type: &str) -> Option<EcLevel> {
     match template_type {
-        "wifi" => Ok(wifi_template(data)?),
-        "vcard" => Ok(vcard_template(data)?),
-        "sms" => Ok(sms_template(data)),
-        "email" => Ok(email_template(data)),
+        "wifi" | "vcard" => Some(EcLevel::L),
+        "sms" | "email" | "phone" | "zatca" | "calendar" => Some(EcLevel::M),
+        "whatsapp" | "app" | "spotify" => Some(EcLevel::H),
+        _ => None,
+    }
+}
+
+/// Expand `data` into the payload for `template_type`
+pub fn apply_template(
+    template_type: &str,
+    data: &str,
+    options: &TemplateOptions,
+) -> Result<String> {
+    let fields = options.fields;
+    match template_type {
+        "wifi" => wifi_template(data, fields, options.wifi, options.prompts),
+        "vcard" => vcard_template(data, fields, options.vcard_version, options.prompts),
+        "sms" => Ok(sms_template(data, fields, options.sms_format)),
+        "email" => Ok(email_template(data, fields)),
         "phone" => Ok(phone_template(data)),
+        "whatsapp" => Ok(whatsapp_template(data, fields)),
+        "app" => app_template(data, fields),
+        "zatca" => zatca_template(data, fields),
+        "spotify" => spotify_template(data, fields),
+        "calendar" => calendar_template(data, fields),
         _ => Err(anyhow::anyhow!("Unknown template type: {}", template_type)),
     }
 }
 
-fn wifi_template(data: &str) -> Result<String> {
-    let parts: Vec<&str> = data.split(',').collect();
+/// Map an encryption type to one phones accept in a WiFi code: WPA, WEP or
+/// NOPASS, with WPA2 and WPA3 read as WPA. Anything else falls back to WPA
+/// with a warning, or is an error when `strict`.
+fn wifi_encryption(value: &str, strict: bool) -> Result<&'static str> {
+    match value.to_uppercase().as_str() {
+        "WPA" | "WPA2" | "WPA3" => Ok("WPA"),
+        "WEP" => Ok("WEP"),
+        "NOPASS" => Ok("NOPASS"),
+        _ if strict => Err(anyhow::anyhow!(
+            "Unknown WiFi encryption {:?}: expected WPA, WEP or nopass",
+            value
+        )),
+        _ => {
+            eprintln!("Warning: unknown WiFi encryption {:?}; using WPA", value);
+            Ok("WPA")
+        }
+    }
+}
+
+/// Backslash-escape the characters that delimit fields in a WiFi code
+fn wifi_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn wifi_template(
+    data: &str,
+    fields: FieldOptions,
+    wifi: WifiOptions,
+    prompts: PromptOptions,
+) -> Result<String> {
+    let parts = fields.split(data, usize::MAX);
 
     let (ssid, password, encryption) = if parts.len() == 3 {
         (
             parts[0].to_string(),
             parts[1].to_string(),
-            parts[2].to_uppercase(),
+            parts[2].to_string(),
         )
+    } else if !prompts.enabled {
+        return Err(anyhow::anyhow!(
+            "WiFi template expects SSID,password,encryption"
+        ));
     } else {
         println!("WiFi QR Code Generator");
-        print!("Network SSID: ");
-        io::stdout().flush()?;
-        let mut ssid = String::new();
-        io::stdin().read_line(&mut ssid)?;
-
-        print!("Password: ");
-        io::stdout().flush()?;
-        let mut password = String::new();
-        io::stdin().read_line(&mut password)?;
-
-        print!("Encryption (WPA/WEP/nopass): ");
-        io::stdout().flush()?;
-        let mut encryption = String::new();
-        io::stdin().read_line(&mut encryption)?;
-        let encryption = encryption.trim().to_uppercase();
-        let encryption = if ["WPA", "WEP", "NOPASS"].contains(&encryption.as_str()) {
-            encryption
-        } else {
-            "WPA".to_string()
-        };
+        let ssid = prompt("Network SSID", prompts.timeout)?;
+        let password = prompt("Password", prompts.timeout)?;
+        let encryption = prompt("Encryption (WPA/WEP/nopass)", prompts.timeout)?;
 
-        (
-            ssid.trim().to_string(),
-            password.trim().to_string(),
-            encryption,
-        )
+        (ssid, password, encryption)
     };
+    let encryption = wifi_encryption(&encryption, wifi.strict_encryption)?;
+    let hidden = if wifi.hidden { "H:true;" } else { "" };
 
-    Ok(format!("WIFI:T:{};S:{};P:{};;", encryption, ssid, password))
+    Ok(format!(
+        "WIFI:T:{};S:{};P:{};{};",
+        encryption,
+        wifi_escape(&ssid),
+        wifi_escape(&password),
+        hidden
+    ))
 }
 
-fn vcard_template(data: &str) -> Result<String> {
-    let parts: Vec<&str> = data.split(',').collect();
+fn vcard_template(
+    data: &str,
+    fields: FieldOptions,
+    version: &str,
+    prompts: PromptOptions,
+) -> Result<String> {
+    // The address comes last so it can keep its own separators
+    let parts = fields.split(data, 7);
 
-    let (name, phone, email, org) = if parts.len() >= 2 {
-        let name = parts[0].to_string();
-        let phone = parts[1].to_string();
-        let email = if parts.len() > 2 {
-            parts[2].to_string()
-        } else {
-            String::new()
-        };
-        let org = if parts.len() > 3 {
-            parts[3].to_string()
-        } else {
-            String::new()
-        };
-        (name, phone, email, org)
+    let [name, phone, email, org, title, url, address] = if parts.len() >= 2 {
+        std::array::from_fn(|i| {
+            parts
+                .get(i)
+                .map(|part| part.to_string())
+                .unwrap_or_default()
+        })
+    } else if !prompts.enabled {
+        return Err(anyhow::anyhow!(
+            "vCard template expects at least name,phone"
+        ));
     } else {
         println!("vCard QR Code Generator");
-        print!("Full Name: ");
-        io::stdout().flush()?;
-        let mut name = String::new();
-        io::stdin().read_line(&mut name)?;
-
-        print!("Phone: ");
-        io::stdout().flush()?;
-        let mut phone = String::new();
-        io::stdin().read_line(&mut phone)?;
-
-        print!("Email: ");
-        io::stdout().flush()?;
-        let mut email = String::new();
-        io::stdin().read_line(&mut email)?;
-
-        print!("Organization (optional): ");
-        io::stdout().flush()?;
-        let mut org = String::new();
-        io::stdin().read_line(&mut org)?;
-
-        (
-            name.trim().to_string(),
-            phone.trim().to_string(),
-            email.trim().to_string(),
-            org.trim().to_string(),
-        )
+        let mut answers: [String; 7] = Default::default();
+        let labels = [
+            "Full Name",
+            "Phone",
+            "Email",
+            "Organization (optional)",
+            "Job Title (optional)",
+            "Website (optional)",
+            "Address (optional)",
+        ];
+        for (label, answer) in labels.iter().zip(answers.iter_mut()) {
+            *answer = prompt(label, prompts.timeout)?;
+        }
+        answers
     };
 
-    let mut vcard = format!("BEGIN:VCARD\nVERSION:3.0\nFN:{}\n", name);
+    let mut vcard = format!("BEGIN:VCARD\nVERSION:{}\nFN:{}\n", version, name);
     if !phone.is_empty() {
-        vcard.push_str(&format!("TEL:{}\n", phone));
+        let tel = match version {
+            "3.0" => format!("TEL:{}\n", phone),
+            // 4.0 carries the number as a tel: URI, which can't contain spaces
+            "4.0" => format!(
+                "TEL;VALUE=uri;TYPE=voice:tel:{}\n",
+                phone.split_whitespace().collect::<String>()
+            ),
+            _ => return Err(anyhow::anyhow!("Unsupported vCard version: {}", version)),
+        };
+        vcard.push_str(&tel);
     }
     if !email.is_empty() {
         vcard.push_str(&format!("EMAIL:{}\n", email));
@@ -111,22 +428,36 @@ fn vcard_template(data: &str) -> Result<String> {
     if !org.is_empty() {
         vcard.push_str(&format!("ORG:{}\n", org));
     }
+    if !title.is_empty() {
+        vcard.push_str(&format!("TITLE:{}\n", title));
+    }
+    if !url.is_empty() {
+        vcard.push_str(&format!("URL:{}\n", url));
+    }
+    if !address.is_empty() {
+        // Free-form address goes in the street component; commas inside a
+        // component must be escaped
+        vcard.push_str(&format!("ADR:;;{};;;;\n", address.replace(',', "\\,")));
+    }
     vcard.push_str("END:VCARD");
 
     Ok(vcard)
 }
 
-fn sms_template(data: &str) -> String {
-    let parts: Vec<&str> = data.splitn(2, ',').collect();
-    if parts.len() == 2 {
-        format!("SMSTO:{}:{}", parts[0], parts[1])
-    } else {
-        format!("SMSTO:{}:", data)
+/// `SMSTO:number:body`, or with `format` "uri" the `sms:number?body=...`
+/// form some iOS versions handle better
+fn sms_template(data: &str, fields: FieldOptions, format: &str) -> String {
+    let parts = fields.split(data, 2);
+    let message = parts.get(1).unwrap_or(&"");
+    match format {
+        "uri" if message.is_empty() => format!("sms:{}", parts[0]),
+        "uri" => format!("sms:{}?body={}", parts[0], url_encode(message)),
+        _ => format!("SMSTO:{}:{}", parts[0], message),
     }
 }
 
-fn email_template(data: &str) -> String {
-    let parts: Vec<&str> = data.splitn(3, ',').collect();
+fn email_template(data: &str, fields: FieldOptions) -> String {
+    let parts = fields.split(data, 3);
     let email = parts.first().unwrap_or(&"");
     let subject = parts.get(1).unwrap_or(&"");
     let body = parts.get(2).unwrap_or(&"");
@@ -138,13 +469,352 @@ fn phone_template(data: &str) -> String {
     format!("tel:{}", data)
 }
 
+/// Percent-encode everything except RFC 3986 unreserved characters
+fn url_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn whatsapp_template(data: &str, fields: FieldOptions) -> String {
+    let parts = fields.split(data, 2);
+    // wa.me wants the full international number as bare digits
+    let phone: String = parts[0].chars().filter(|c| c.is_ascii_digit()).collect();
+
+    match parts.get(1).filter(|message| !message.is_empty()) {
+        Some(message) => format!("https://wa.me/{}?text={}", phone, url_encode(message)),
+        None => format!("https://wa.me/{}", phone),
+    }
+}
+
+/// Takes `ios_url,android_url,fallback_url`. A code holds a single URL, so the
+/// fallback (usually a landing page that redirects by platform) is encoded;
+/// the store links are only checked so typos are caught.
+fn app_template(data: &str, fields: FieldOptions) -> Result<String> {
+    let parts = fields.split_all(data);
+    let [ios, android, fallback] = parts[..] else {
+        return Err(anyhow::anyhow!(
+            "App template expects ios_url,android_url,fallback_url"
+        ));
+    };
+
+    for (label, link) in [("iOS", ios), ("Android", android), ("fallback", fallback)] {
+        url::Url::parse(link)
+            .map_err(|e| anyhow::anyhow!("Invalid {} URL {:?}: {}", label, link, e))?;
+    }
+
+    Ok(fallback.to_string())
+}
+
+/// Kinds of Spotify item that have an open.spotify.com page
+const SPOTIFY_TYPES: [&str; 4] = ["track", "album", "playlist", "artist"];
+
+/// Takes `type,id`, e.g. `track,4uLU6hMCjMI75M1A2tKUQC`, and links to the
+/// item's open.spotify.com page
+fn spotify_template(data: &str, fields: FieldOptions) -> Result<String> {
+    let parts = fields.split_all(data);
+    let [kind, id] = parts[..] else {
+        return Err(anyhow::anyhow!("Spotify template expects type,id"));
+    };
+
+    if !SPOTIFY_TYPES.contains(&kind) {
+        return Err(anyhow::anyhow!(
+            "Invalid Spotify type {:?}: expected one of {}",
+            kind,
+            SPOTIFY_TYPES.join(", ")
+        ));
+    }
+    // Spotify IDs are base62
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return Err(anyhow::anyhow!(
+            "Invalid Spotify ID {:?}: expected letters and digits only",
+            id
+        ));
+    }
+
+    Ok(format!("https://open.spotify.com/{}/{}", kind, id))
+}
+
+/// An event read from `summary,start,end[,location]` calendar input
+struct CalendarEvent<'a> {
+    summary: &'a str,
+    /// DTSTART/DTEND values with their parameters, e.g. `:20261017T090000Z`
+    /// or `;VALUE=DATE:20261017`
+    start: String,
+    end: String,
+    location: Option<&'a str>,
+}
+
+/// Turn `2026-10-17T09:00`, `2026-10-17 09:00:00Z`, `20261017T090000` or a
+/// bare date into an iCalendar DTSTART/DTEND value, including the `:`
+fn ical_datetime(value: &str) -> Result<String> {
+    let compact: String = value
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .map(|c| if c == ' ' { 'T' } else { c })
+        .collect();
+    let (date, time) = match compact.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (compact.as_str(), None),
+    };
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if date.len() != 8 || !digits(date) {
+        return Err(anyhow::anyhow!(
+            "Invalid date {:?}: expected YYYY-MM-DD, optionally followed by THH:MM[:SS][Z]",
+            value
+        ));
+    }
+
+    let field = |s: &str, range: std::ops::Range<usize>| s[range].parse::<u32>().unwrap_or(0);
+    let (year, month, day) = (field(date, 0..4), field(date, 4..6), field(date, 6..8));
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(anyhow::anyhow!("Invalid date {:?}: no such day", value));
+    }
+
+    let Some(time) = time else {
+        return Ok(format!(";VALUE=DATE:{}", date));
+    };
+    let (clock, utc) = match time.strip_suffix('Z') {
+        Some(clock) => (clock, "Z"),
+        None => (time, ""),
+    };
+    let clock = match clock.len() {
+        4 => format!("{}00", clock),
+        6 => clock.to_string(),
+        _ => String::new(),
+    };
+    if clock.is_empty() || !digits(&clock) {
+        return Err(anyhow::anyhow!(
+            "Invalid time in {:?}: expected HH:MM or HH:MM:SS",
+            value
+        ));
+    }
+    if field(&clock, 0..2) > 23 || field(&clock, 2..4) > 59 || field(&clock, 4..6) > 59 {
+        return Err(anyhow::anyhow!("Invalid time in {:?}: no such time", value));
+    }
+    Ok(format!(":{}T{}{}", date, clock, utc))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Fold a content line so no line is longer than 75 octets, as RFC 5545
+/// requires: continuation lines start with a space, and a UTF-8 character
+/// is never split across lines
+fn ical_fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
+
+/// Backslash-escape an iCalendar TEXT value
+fn ical_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Takes `summary,start,end[,location]`; the location comes last so it can
+/// keep its own separators
+fn parse_event(data: &str, fields: FieldOptions) -> Result<CalendarEvent<'_>> {
+    let parts = fields.split(data, 4);
+    if parts.len() < 3 || parts[0].is_empty() {
+        return Err(anyhow::anyhow!(
+            "Calendar template expects summary,start,end[,location]"
+        ));
+    }
+
+    let start = ical_datetime(parts[1])?;
+    let end = ical_datetime(parts[2])?;
+    // Same-shaped values sort chronologically as strings
+    if start.len() == end.len() && end < start {
+        return Err(anyhow::anyhow!(
+            "Calendar event ends ({}) before it starts ({})",
+            parts[2],
+            parts[1]
+        ));
+    }
+
+    Ok(CalendarEvent {
+        summary: parts[0],
+        start,
+        end,
+        location: parts
+            .get(3)
+            .copied()
+            .filter(|location| !location.is_empty()),
+    })
+}
+
+/// Lines of the VEVENT block, with `extra` properties (like UID and DTSTAMP
+/// for a standalone file) right after BEGIN
+fn vevent(event: &CalendarEvent, extra: &[String]) -> Vec<String> {
+    let mut lines = vec!["BEGIN:VEVENT".to_string()];
+    lines.extend_from_slice(extra);
+    lines.push(format!("SUMMARY:{}", ical_escape(event.summary)));
+    lines.push(format!("DTSTART{}", event.start));
+    lines.push(format!("DTEND{}", event.end));
+    if let Some(location) = event.location {
+        lines.push(format!("LOCATION:{}", ical_escape(location)));
+    }
+    lines.push("END:VEVENT".to_string());
+    lines
+}
+
+fn calendar_template(data: &str, fields: FieldOptions) -> Result<String> {
+    let event = parse_event(data, fields)?;
+    Ok(vevent(&event, &[]).join("\n"))
+}
+
+/// The same event as the calendar template, as a complete `.ics` file for
+/// `--also-ics`: wrapped in VCALENDAR with the UID and DTSTAMP that calendar
+/// apps require, and CRLF line endings. The UID comes from the input, so
+/// importing a regenerated file doesn't add a second copy of the event.
+pub fn calendar_ics(data: &str, fields: FieldOptions, now: SystemTime) -> Result<String> {
+    let event = parse_event(data, fields)?;
+    let stamp: String = humantime::format_rfc3339_seconds(now)
+        .to_string()
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    let extra = [
+        format!("UID:{}@qrgen", &run_log::data_hash(data.as_bytes())[..16]),
+        format!("DTSTAMP:{}", stamp),
+    ];
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//qrgen//qrgen {}//EN", env!("CARGO_PKG_VERSION")),
+    ];
+    lines.extend(vevent(&event, &extra));
+    lines.push("END:VCALENDAR".to_string());
+    let folded: Vec<String> = lines.iter().map(|line| ical_fold(line)).collect();
+    Ok(folded.join("\r\n") + "\r\n")
+}
+
+/// ZATCA VAT registration numbers are 15 digits starting and ending with 3
+fn is_valid_vat_number(vat: &str) -> bool {
+    vat.len() == 15
+        && vat.bytes().all(|b| b.is_ascii_digit())
+        && vat.starts_with('3')
+        && vat.ends_with('3')
+}
+
+fn is_valid_amount(amount: &str) -> bool {
+    amount
+        .parse::<f64>()
+        .is_ok_and(|v| v.is_finite() && v >= 0.0)
+        && amount.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+}
+
+/// Takes `seller,vat_number,timestamp,total,vat_total` and builds the Saudi
+/// e-invoice TLV record (tags 1-5, one-byte lengths), base64-encoded
+fn zatca_template(data: &str, fields: FieldOptions) -> Result<String> {
+    let parts = fields.split_all(data);
+    let [seller, vat, timestamp, total, vat_total] = parts[..] else {
+        return Err(anyhow::anyhow!(
+            "ZATCA template expects seller,vat_number,timestamp,total,vat_total"
+        ));
+    };
+
+    if !is_valid_vat_number(vat) {
+        return Err(anyhow::anyhow!(
+            "Invalid VAT number {:?}: expected 15 digits starting and ending with 3",
+            vat
+        ));
+    }
+    for (label, amount) in [("total", total), ("VAT total", vat_total)] {
+        if !is_valid_amount(amount) {
+            return Err(anyhow::anyhow!(
+                "Invalid {} {:?}: expected a number",
+                label,
+                amount
+            ));
+        }
+    }
+
+    let mut tlv = Vec::new();
+    for (tag, value) in (1u8..).zip([seller, vat, timestamp, total, vat_total]) {
+        let len = u8::try_from(value.len())
+            .map_err(|_| anyhow::anyhow!("ZATCA field {} is longer than 255 bytes", tag))?;
+        tlv.push(tag);
+        tlv.push(len);
+        tlv.extend_from_slice(value.as_bytes());
+    }
+
+    Ok(base64::engine::general_purpose::STANDARD.encode(tlv))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const FIELDS: FieldOptions = FieldOptions {
+        separator: ',',
+        trim: true,
+    };
+
+    const PIPES: FieldOptions = FieldOptions {
+        separator: '|',
+        trim: true,
+    };
+
+    const OPTIONS: TemplateOptions = TemplateOptions {
+        fields: FIELDS,
+        vcard_version: "3.0",
+        sms_format: "smsto",
+        prompts: PROMPTS,
+        wifi: WifiOptions {
+            strict_encryption: false,
+            hidden: false,
+        },
+    };
+
+    const PROMPTS: PromptOptions = PromptOptions {
+        enabled: true,
+        timeout: None,
+    };
+
+    const STRICT: WifiOptions = WifiOptions {
+        strict_encryption: true,
+        hidden: false,
+    };
+
     #[test]
     fn test_wifi_template_with_data() {
-        let result = wifi_template("MyNetwork,password123,WPA").unwrap();
+        let result = wifi_template(
+            "MyNetwork,password123,WPA",
+            FIELDS,
+            WifiOptions::default(),
+            PROMPTS,
+        )
+        .unwrap();
         assert!(result.contains("WIFI:T:WPA"));
         assert!(result.contains("S:MyNetwork"));
         assert!(result.contains("P:password123"));
@@ -153,7 +823,13 @@ mod tests {
 
     #[test]
     fn test_wifi_template_wep_encryption() {
-        let result = wifi_template("TestNet,pass456,WEP").unwrap();
+        let result = wifi_template(
+            "TestNet,pass456,WEP",
+            FIELDS,
+            WifiOptions::default(),
+            PROMPTS,
+        )
+        .unwrap();
         assert!(result.contains("WIFI:T:WEP"));
         assert!(result.contains("S:TestNet"));
         assert!(result.contains("P:pass456"));
@@ -161,14 +837,91 @@ mod tests {
 
     #[test]
     fn test_wifi_template_nopass() {
-        let result = wifi_template("OpenNet,,NOPASS").unwrap();
+        let result =
+            wifi_template("OpenNet,,NOPASS", FIELDS, WifiOptions::default(), PROMPTS).unwrap();
         assert!(result.contains("WIFI:T:NOPASS"));
         assert!(result.contains("S:OpenNet"));
     }
 
+    #[test]
+    fn test_wifi_template_hidden() {
+        let hidden = WifiOptions {
+            hidden: true,
+            ..WifiOptions::default()
+        };
+        let result = wifi_template("MyNet,secret,WPA", FIELDS, hidden, PROMPTS).unwrap();
+        assert_eq!(result, "WIFI:T:WPA;S:MyNet;P:secret;H:true;;");
+
+        let result =
+            wifi_template("MyNet,secret,WPA", FIELDS, WifiOptions::default(), PROMPTS).unwrap();
+        assert_eq!(result, "WIFI:T:WPA;S:MyNet;P:secret;;");
+    }
+
+    #[test]
+    fn test_wifi_template_escapes_special_characters() {
+        let hidden = WifiOptions {
+            hidden: true,
+            ..WifiOptions::default()
+        };
+        let pipes = FieldOptions {
+            separator: '|',
+            ..FIELDS
+        };
+        let result = wifi_template(r#"Cafe;"Guest"|p\a:ss,1|WPA"#, pipes, hidden, PROMPTS).unwrap();
+        assert_eq!(
+            result,
+            r#"WIFI:T:WPA;S:Cafe\;\"Guest\";P:p\\a\:ss\,1;H:true;;"#
+        );
+    }
+
+    #[test]
+    fn test_wifi_template_encryption_aliases() {
+        for alias in ["WPA2", "wpa3", "wpa"] {
+            let data = format!("MyNet,secret,{}", alias);
+            let result = wifi_template(&data, FIELDS, STRICT, PROMPTS).unwrap();
+            assert!(result.starts_with("WIFI:T:WPA;"), "{}", alias);
+        }
+        let result = wifi_template("OpenNet,,nopass", FIELDS, STRICT, PROMPTS).unwrap();
+        assert!(result.starts_with("WIFI:T:NOPASS;"));
+    }
+
+    #[test]
+    fn test_wifi_template_unknown_encryption() {
+        let err = wifi_template("MyNet,secret,GIBBERISH", FIELDS, STRICT, PROMPTS).unwrap_err();
+        assert!(err.to_string().contains("Unknown WiFi encryption"));
+
+        // Without strict mode it falls back to WPA rather than passing it through
+        let result = wifi_template(
+            "MyNet,secret,GIBBERISH",
+            FIELDS,
+            WifiOptions::default(),
+            PROMPTS,
+        )
+        .unwrap();
+        assert!(result.starts_with("WIFI:T:WPA;"));
+    }
+
+    #[test]
+    fn test_templates_without_prompts_reject_missing_fields() {
+        let no_prompts = PromptOptions {
+            enabled: false,
+            timeout: None,
+        };
+        let err = wifi_template("broken", FIELDS, WifiOptions::default(), no_prompts).unwrap_err();
+        assert!(err.to_string().contains("SSID,password,encryption"));
+        let err = vcard_template("Jane Smith", FIELDS, "3.0", no_prompts).unwrap_err();
+        assert!(err.to_string().contains("name,phone"));
+    }
+
     #[test]
     fn test_vcard_template_full_data() {
-        let result = vcard_template("John Doe,+1234567890,john@example.com,Acme Corp").unwrap();
+        let result = vcard_template(
+            "John Doe,+1234567890,john@example.com,Acme Corp",
+            FIELDS,
+            "3.0",
+            PROMPTS,
+        )
+        .unwrap();
         assert!(result.contains("BEGIN:VCARD"));
         assert!(result.contains("VERSION:3.0"));
         assert!(result.contains("FN:John Doe"));
@@ -180,16 +933,68 @@ mod tests {
 
     #[test]
     fn test_vcard_template_minimal_data() {
-        let result = vcard_template("Jane Smith,+9876543210").unwrap();
+        let result = vcard_template("Jane Smith,+9876543210", FIELDS, "3.0", PROMPTS).unwrap();
         assert!(result.contains("BEGIN:VCARD"));
         assert!(result.contains("FN:Jane Smith"));
         assert!(result.contains("TEL:+9876543210"));
         assert!(result.contains("END:VCARD"));
     }
 
+    #[test]
+    fn test_vcard_template_version_4() {
+        let v3 = vcard_template("Jane Smith,+44 7700 900123", FIELDS, "3.0", PROMPTS).unwrap();
+        assert_eq!(
+            v3,
+            "BEGIN:VCARD\nVERSION:3.0\nFN:Jane Smith\nTEL:+44 7700 900123\nEND:VCARD"
+        );
+
+        let v4 = vcard_template("Jane Smith,+44 7700 900123", FIELDS, "4.0", PROMPTS).unwrap();
+        assert_eq!(
+            v4,
+            "BEGIN:VCARD\nVERSION:4.0\nFN:Jane Smith\nTEL;VALUE=uri;TYPE=voice:tel:+447700900123\nEND:VCARD"
+        );
+
+        assert!(vcard_template("Jane,123", FIELDS, "2.1", PROMPTS).is_err());
+    }
+
+    #[test]
+    fn test_vcard_template_extended_fields() {
+        let result = vcard_template(
+            "John Doe,+1234567890,john@example.com,Acme Corp,CTO,https://acme.example,1 Main St, Springfield",
+            FIELDS,
+            "3.0", PROMPTS
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "BEGIN:VCARD\nVERSION:3.0\nFN:John Doe\nTEL:+1234567890\nEMAIL:john@example.com\nORG:Acme Corp\nTITLE:CTO\nURL:https://acme.example\nADR:;;1 Main St\\, Springfield;;;;\nEND:VCARD"
+        );
+    }
+
+    #[test]
+    fn test_vcard_template_four_fields_unchanged() {
+        let result = vcard_template(
+            "John Doe,+1234567890,john@example.com,Acme Corp",
+            FIELDS,
+            "3.0",
+            PROMPTS,
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "BEGIN:VCARD\nVERSION:3.0\nFN:John Doe\nTEL:+1234567890\nEMAIL:john@example.com\nORG:Acme Corp\nEND:VCARD"
+        );
+    }
+
     #[test]
     fn test_vcard_template_no_organization() {
-        let result = vcard_template("Bob Jones,+1111111111,bob@example.com").unwrap();
+        let result = vcard_template(
+            "Bob Jones,+1111111111,bob@example.com",
+            FIELDS,
+            "3.0",
+            PROMPTS,
+        )
+        .unwrap();
         assert!(result.contains("FN:Bob Jones"));
         assert!(result.contains("TEL:+1111111111"));
         assert!(result.contains("EMAIL:bob@example.com"));
@@ -199,25 +1004,32 @@ mod tests {
 
     #[test]
     fn test_sms_template_with_message() {
-        let result = sms_template("1234567890,Hello there!");
+        let result = sms_template("1234567890,Hello there!", FIELDS, "smsto");
         assert_eq!(result, "SMSTO:1234567890:Hello there!");
     }
 
     #[test]
     fn test_sms_template_phone_only() {
-        let result = sms_template("9876543210");
+        let result = sms_template("9876543210", FIELDS, "smsto");
         assert_eq!(result, "SMSTO:9876543210:");
     }
 
+    #[test]
+    fn test_sms_template_uri_format() {
+        let result = sms_template("+1234567890,Hello there & bye", FIELDS, "uri");
+        assert_eq!(result, "sms:+1234567890?body=Hello%20there%20%26%20bye");
+        assert_eq!(sms_template("1234567890", FIELDS, "uri"), "sms:1234567890");
+    }
+
     #[test]
     fn test_sms_template_with_commas_in_message() {
-        let result = sms_template("1234567890,Hello, how are you?");
+        let result = sms_template("1234567890,Hello, how are you?", FIELDS, "smsto");
         assert_eq!(result, "SMSTO:1234567890:Hello, how are you?");
     }
 
     #[test]
     fn test_email_template_full() {
-        let result = email_template("contact@example.com,Subject Line,Email body text");
+        let result = email_template("contact@example.com,Subject Line,Email body text", FIELDS);
         assert_eq!(
             result,
             "mailto:contact@example.com?subject=Subject Line&body=Email body text"
@@ -226,13 +1038,13 @@ mod tests {
 
     #[test]
     fn test_email_template_address_only() {
-        let result = email_template("test@example.com");
+        let result = email_template("test@example.com", FIELDS);
         assert_eq!(result, "mailto:test@example.com?subject=&body=");
     }
 
     #[test]
     fn test_email_template_with_subject_no_body() {
-        let result = email_template("info@example.com,Important");
+        let result = email_template("info@example.com,Important", FIELDS);
         assert_eq!(result, "mailto:info@example.com?subject=Important&body=");
     }
 
@@ -248,39 +1060,440 @@ mod tests {
         assert_eq!(result, "tel:9876543210");
     }
 
+    #[test]
+    fn test_url_encode() {
+        assert_eq!(url_encode("Hello there!"), "Hello%20there%21");
+        assert_eq!(url_encode("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(url_encode("café"), "caf%C3%A9");
+    }
+
+    #[test]
+    fn test_whatsapp_template_phone_only() {
+        let result = whatsapp_template("+44 7700 900123", FIELDS);
+        assert_eq!(result, "https://wa.me/447700900123");
+    }
+
+    #[test]
+    fn test_whatsapp_template_with_message() {
+        let result = whatsapp_template("+1 (555) 010-9999,Hi there, I saw your poster", FIELDS);
+        assert_eq!(
+            result,
+            "https://wa.me/15550109999?text=Hi%20there%2C%20I%20saw%20your%20poster"
+        );
+    }
+
+    #[test]
+    fn test_whatsapp_template_empty_message() {
+        let result = whatsapp_template("15550109999,", FIELDS);
+        assert_eq!(result, "https://wa.me/15550109999");
+    }
+
+    #[test]
+    fn test_apply_template_whatsapp() {
+        let result = apply_template("whatsapp", "123,hello world", &OPTIONS).unwrap();
+        assert_eq!(result, "https://wa.me/123?text=hello%20world");
+    }
+
+    #[test]
+    fn test_app_template_encodes_fallback() {
+        let result = app_template(
+            "https://apps.apple.com/app/id1,https://play.google.com/store/apps/details?id=a.b,https://example.com/app",
+            FIELDS,
+        )
+        .unwrap();
+        assert_eq!(result, "https://example.com/app");
+    }
+
+    #[test]
+    fn test_app_template_trims_fields() {
+        let result = app_template(
+            "https://a.example, https://b.example, https://c.example",
+            FIELDS,
+        )
+        .unwrap();
+        assert_eq!(result, "https://c.example");
+    }
+
+    #[test]
+    fn test_fixed_field_templates_keep_whitespace_without_trim() {
+        let untrimmed = FieldOptions {
+            trim: false,
+            ..FIELDS
+        };
+
+        let result = app_template(
+            "https://a.example,https://b.example, https://c.example",
+            untrimmed,
+        )
+        .unwrap();
+        assert_eq!(result, " https://c.example");
+
+        // The ID's leading space is kept, so it is no longer base62
+        assert!(spotify_template("track, 4uLU6hMCjMI75M1A2tKUQC", untrimmed).is_err());
+
+        let result = zatca_template(
+            " Bobs Records ,310122393500003,2022-04-25T15:30:00Z,1000.00,150.00",
+            untrimmed,
+        )
+        .unwrap();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&result)
+            .unwrap();
+        assert_eq!(parse_tlv(&bytes)[0], (1, " Bobs Records ".to_string()));
+    }
+
+    #[test]
+    fn test_app_template_wrong_field_count() {
+        assert!(app_template("https://a.example,https://b.example", FIELDS).is_err());
+        assert!(app_template(
+            "https://a.example,https://b.example,https://c.example,x",
+            FIELDS
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_app_template_invalid_url() {
+        let err =
+            app_template("https://a.example,not a url,https://c.example", FIELDS).unwrap_err();
+        assert!(err.to_string().contains("Invalid Android URL"));
+
+        let err = app_template(
+            "https://a.example,https://b.example,https://exa mple.com",
+            FIELDS,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid fallback URL"));
+    }
+
+    /// Split a TLV byte string back into (tag, value) pairs
+    fn parse_tlv(bytes: &[u8]) -> Vec<(u8, String)> {
+        let mut fields = Vec::new();
+        let mut rest = bytes;
+        while let [tag, len, tail @ ..] = rest {
+            let (value, tail) = tail.split_at(*len as usize);
+            fields.push((*tag, String::from_utf8(value.to_vec()).unwrap()));
+            rest = tail;
+        }
+        fields
+    }
+
+    #[test]
+    fn test_zatca_template_round_trip() {
+        let result = zatca_template(
+            "Bobs Records,310122393500003,2022-04-25T15:30:00Z,1000.00,150.00",
+            FIELDS,
+        )
+        .unwrap();
+
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&result)
+            .unwrap();
+        assert_eq!(
+            parse_tlv(&bytes),
+            vec![
+                (1, "Bobs Records".to_string()),
+                (2, "310122393500003".to_string()),
+                (3, "2022-04-25T15:30:00Z".to_string()),
+                (4, "1000.00".to_string()),
+                (5, "150.00".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zatca_template_utf8_length_in_bytes() {
+        let result =
+            zatca_template("شركة|310122393500003|2022-04-25T15:30:00Z|1000|150", PIPES).unwrap();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&result)
+            .unwrap();
+
+        assert_eq!(bytes[0], 1);
+        assert_eq!(bytes[1] as usize, "شركة".len());
+        assert_eq!(parse_tlv(&bytes)[0].1, "شركة");
+    }
+
+    #[test]
+    fn test_zatca_template_invalid_vat() {
+        for vat in ["31012239350000", "210122393500003", "31012239350000A"] {
+            let data = format!("Shop,{},2022-04-25T15:30:00Z,100,15", vat);
+            assert!(zatca_template(&data, FIELDS).is_err(), "{}", vat);
+        }
+    }
+
+    #[test]
+    fn test_zatca_template_invalid_amounts() {
+        let err =
+            zatca_template("Shop,310122393500003,2022-04-25T15:30:00Z,abc,15", FIELDS).unwrap_err();
+        assert!(err.to_string().contains("Invalid total"));
+
+        let err =
+            zatca_template("Shop,310122393500003,2022-04-25T15:30:00Z,100,-5", FIELDS).unwrap_err();
+        assert!(err.to_string().contains("Invalid VAT total"));
+    }
+
+    #[test]
+    fn test_zatca_template_wrong_field_count() {
+        assert!(zatca_template("Shop,310122393500003,100", FIELDS).is_err());
+    }
+
+    #[test]
+    fn test_spotify_template_track() {
+        let result = spotify_template("track,4uLU6hMCjMI75M1A2tKUQC", FIELDS).unwrap();
+        assert_eq!(
+            result,
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC"
+        );
+
+        let result = spotify_template("playlist | 37i9dQZF1DXcBWIGoYBM5M", PIPES).unwrap();
+        assert_eq!(
+            result,
+            "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M"
+        );
+    }
+
+    #[test]
+    fn test_spotify_template_invalid_type() {
+        let err = spotify_template("podcast,4uLU6hMCjMI75M1A2tKUQC", FIELDS).unwrap_err();
+        assert!(err.to_string().contains("Invalid Spotify type"));
+    }
+
+    #[test]
+    fn test_spotify_template_invalid_id() {
+        assert!(spotify_template("track,", FIELDS).is_err());
+        assert!(spotify_template("track,abc/../def", FIELDS).is_err());
+        assert!(spotify_template("track", FIELDS).is_err());
+    }
+
+    #[test]
+    fn test_calendar_template() {
+        let result = calendar_template(
+            "Team sync,2026-10-17T09:00Z,2026-10-17 10:30,Room 4, Level 2",
+            FIELDS,
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "BEGIN:VEVENT\nSUMMARY:Team sync\nDTSTART:20261017T090000Z\n\
+             DTEND:20261017T103000\nLOCATION:Room 4\\, Level 2\nEND:VEVENT"
+        );
+    }
+
+    #[test]
+    fn test_calendar_template_all_day() {
+        let result = calendar_template("Holiday,2026-12-25,2026-12-26", FIELDS).unwrap();
+        assert!(result.contains("DTSTART;VALUE=DATE:20261225\n"));
+        assert!(result.contains("DTEND;VALUE=DATE:20261226\n"));
+        assert!(!result.contains("LOCATION"));
+    }
+
+    #[test]
+    fn test_calendar_template_invalid() {
+        assert!(calendar_template("Team sync,2026-10-17", FIELDS).is_err());
+        assert!(calendar_template("Team sync,tomorrow,2026-10-18", FIELDS).is_err());
+        assert!(calendar_template("Team sync,2026-10-17T9,2026-10-18", FIELDS).is_err());
+        assert!(calendar_template("Team sync,2026-13-01,2026-13-02", FIELDS).is_err());
+        assert!(calendar_template("Team sync,2026-02-29,2026-03-01", FIELDS).is_err());
+        assert!(calendar_template("Team sync,2026-10-17T25:00,2026-10-18", FIELDS).is_err());
+        assert!(calendar_template("Team sync,2026-10-17T09:60,2026-10-18", FIELDS).is_err());
+        assert!(calendar_template("Leap day,2028-02-29,2028-03-01", FIELDS).is_ok());
+        let err = calendar_template("Team sync,2026-10-18,2026-10-17", FIELDS).unwrap_err();
+        assert!(err.to_string().contains("ends"));
+    }
+
+    #[test]
+    fn test_calendar_ics_wraps_same_event() {
+        let data = "Launch; party,20261017T180000Z,20261017T220000Z";
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_792_281_600);
+        let ics = calendar_ics(data, FIELDS, now).unwrap();
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:"));
+        assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+        assert!(ics.contains("\r\nDTSTAMP:20261018T000000Z\r\n"));
+        assert!(ics.contains("\r\nUID:"));
+        // Every property of the code's payload appears in the file
+        let payload = calendar_template(data, FIELDS).unwrap();
+        for line in payload.lines() {
+            assert!(ics.contains(&format!("{}\r\n", line)), "{}", line);
+        }
+        assert_eq!(ics, calendar_ics(data, FIELDS, now).unwrap());
+    }
+
+    #[test]
+    fn test_calendar_ics_folds_long_lines() {
+        let summary = "Planning for the café and bakery teams ".repeat(4);
+        let data = format!("{};2026-10-17;2026-10-18", summary);
+        let fields = FieldOptions {
+            separator: ';',
+            trim: false,
+        };
+        let ics = calendar_ics(&data, fields, SystemTime::UNIX_EPOCH).unwrap();
+
+        let lines: Vec<&str> = ics.split("\r\n").collect();
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert!(lines.iter().any(|line| line.starts_with(' ')));
+        // Unfolding gives back the whole summary
+        let unfolded = ics.replace("\r\n ", "");
+        assert!(unfolded.contains(&format!("\r\nSUMMARY:{}\r\n", summary)));
+    }
+
+    #[test]
+    fn test_apply_template_spotify() {
+        let result = apply_template("spotify", "album,1DFixLWuPkv3KT3TnV35m3", &OPTIONS).unwrap();
+        assert_eq!(
+            result,
+            "https://open.spotify.com/album/1DFixLWuPkv3KT3TnV35m3"
+        );
+    }
+
     #[test]
     fn test_apply_template_wifi() {
-        let result = apply_template("wifi", "MyNet,pass,WPA").unwrap();
+        let result = apply_template("wifi", "MyNet,pass,WPA", &OPTIONS).unwrap();
         assert!(result.contains("WIFI:T:WPA"));
     }
 
     #[test]
     fn test_apply_template_vcard() {
-        let result = apply_template("vcard", "John,123").unwrap();
+        let result = apply_template("vcard", "John,123", &OPTIONS).unwrap();
         assert!(result.contains("FN:John"));
     }
 
     #[test]
     fn test_apply_template_sms() {
-        let result = apply_template("sms", "123,msg").unwrap();
+        let result = apply_template("sms", "123,msg", &OPTIONS).unwrap();
         assert_eq!(result, "SMSTO:123:msg");
     }
 
     #[test]
     fn test_apply_template_email() {
-        let result = apply_template("email", "test@test.com").unwrap();
+        let result = apply_template("email", "test@test.com", &OPTIONS).unwrap();
         assert!(result.starts_with("mailto:"));
     }
 
     #[test]
     fn test_apply_template_phone() {
-        let result = apply_template("phone", "123").unwrap();
+        let result = apply_template("phone", "123", &OPTIONS).unwrap();
         assert_eq!(result, "tel:123");
     }
 
+    #[test]
+    fn test_vcard_template_trims_fields() {
+        let result =
+            vcard_template("John , 123 , john@example.com", FIELDS, "3.0", PROMPTS).unwrap();
+        assert!(result.contains("FN:John\n"));
+        assert!(result.contains("TEL:123\n"));
+        assert!(result.contains("EMAIL:john@example.com\n"));
+    }
+
+    #[test]
+    fn test_sms_and_email_trim_fields() {
+        assert_eq!(sms_template(" 123 , hi ", FIELDS, "smsto"), "SMSTO:123:hi");
+        assert_eq!(sms_template(" 123 ", FIELDS, "smsto"), "SMSTO:123:");
+        assert_eq!(
+            email_template("a@b.com , Hi , Body", FIELDS),
+            "mailto:a@b.com?subject=Hi&body=Body"
+        );
+    }
+
+    #[test]
+    fn test_wifi_template_no_trim_preserves_password_spaces() {
+        let untrimmed = FieldOptions {
+            trim: false,
+            ..FIELDS
+        };
+        let result = wifi_template(
+            "MyNet,  secret ,WPA",
+            untrimmed,
+            WifiOptions::default(),
+            PROMPTS,
+        )
+        .unwrap();
+        assert!(result.contains("P:  secret ;"));
+
+        let result = wifi_template(
+            "MyNet,  secret ,WPA",
+            FIELDS,
+            WifiOptions::default(),
+            PROMPTS,
+        )
+        .unwrap();
+        assert!(result.contains("P:secret;"));
+    }
+
+    #[test]
+    fn test_vcard_template_pipe_separator() {
+        let result = vcard_template(
+            "Smith, Jane|+9876543210|jane@example.com",
+            FieldOptions {
+                separator: '|',
+                ..FIELDS
+            },
+            "3.0",
+            PROMPTS,
+        )
+        .unwrap();
+        assert!(result.contains("FN:Smith, Jane\n"));
+        assert!(result.contains("TEL:+9876543210\n"));
+        assert!(result.contains("EMAIL:jane@example.com\n"));
+    }
+
+    #[test]
+    fn test_email_template_tab_separator() {
+        let result = email_template(
+            "a@example.com\tHi, all\tItems: 1, 2, 3",
+            FieldOptions {
+                separator: '\t',
+                ..FIELDS
+            },
+        );
+        assert_eq!(
+            result,
+            "mailto:a@example.com?subject=Hi, all&body=Items: 1, 2, 3"
+        );
+    }
+
+    #[test]
+    fn test_template_examples_apply() {
+        let options = TemplateOptions {
+            prompts: PromptOptions {
+                enabled: false,
+                timeout: None,
+            },
+            ..OPTIONS
+        };
+        for info in TEMPLATES {
+            apply_template(info.name, info.example, &options)
+                .unwrap_or_else(|e| panic!("{} example: {:#}", info.name, e));
+        }
+    }
+
+    #[test]
+    fn test_template_help() {
+        let help = template_help("wifi").unwrap();
+        assert!(help.starts_with("wifi - Join a WiFi network\n"));
+        assert!(help.contains("  ssid        (required) Network name\n"));
+        assert!(help.contains("Default error correction: L"));
+        assert!(help.contains("qrgen \"MyNetwork,mypassword,WPA\" --template wifi -o wifi.png"));
+
+        let help = template_help("vcard").unwrap();
+        assert!(help.contains("(optional) Email address"));
+        assert!(template_help("nope").is_none());
+    }
+
+    #[test]
+    fn test_default_error_correction() {
+        assert_eq!(default_error_correction("wifi"), Some(EcLevel::L));
+        assert_eq!(default_error_correction("vcard"), Some(EcLevel::L));
+        assert_eq!(default_error_correction("phone"), Some(EcLevel::M));
+        assert_eq!(default_error_correction("calendar"), Some(EcLevel::M));
+        assert_eq!(default_error_correction("spotify"), Some(EcLevel::H));
+        assert_eq!(default_error_correction("invalid"), None);
+    }
+
     #[test]
     fn test_apply_template_invalid() {
-        let result = apply_template("invalid", "test data");
+        let result = apply_template("invalid", "test data", &OPTIONS);
         assert!(result.is_err());
     }
 }
@@ -0,0 +1,270 @@
+use std::path::Path;
+
+use ab_glyph::{Font as _, FontArc, GlyphId, PxScale, PxScaleFont, ScaleFont};
+use anyhow::{Context, Result};
+use image::{Rgba, RgbaImage};
+
+/// Glyphs are 5x7 pixels at scale 1, with one pixel between characters
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+/// Rows of the built-in pixel font, top to bottom, with the leftmost pixel in
+/// bit 4. Letters are capitals only; lowercase is drawn as uppercase and
+/// anything outside printable ASCII as `?`.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '"' => [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '$' => [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '&' => [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D],
+        '\'' => [0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '*' => [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        ';' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        '@' => [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        '\\' => [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        '^' => [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '`' => [0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '{' => [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02],
+        '|' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        '}' => [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08],
+        '~' => [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+/// Width and height of `text` drawn on one line at `scale` pixels per font pixel
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let chars = text.chars().count() as u32;
+    let width = (chars * ADVANCE).saturating_sub(1) * scale;
+    (width, GLYPH_HEIGHT * scale)
+}
+
+/// Draw `text` with its top-left corner at (x, y), clipping anything that
+/// falls outside the image
+pub fn draw_text(img: &mut RgbaImage, text: &str, (x, y): (u32, u32), scale: u32, color: Rgba<u8>) {
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as u32 * ADVANCE * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                let px = left + col * scale;
+                let py = y + row as u32 * scale;
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        if px + dx < img.width() && py + dy < img.height() {
+                            img.put_pixel(px + dx, py + dy, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Typeface for captions and frame text: the built-in pixel font, or a
+/// TrueType/OpenType font from --font
+#[derive(Clone, Debug, Default)]
+pub enum Font {
+    #[default]
+    Builtin,
+    Outline(FontArc),
+}
+
+impl Font {
+    /// Load a TTF or OTF file
+    pub fn load(path: &Path) -> Result<Font> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read font: {}", path.display()))?;
+        let font = FontArc::try_from_vec(bytes)
+            .with_context(|| format!("Not a TrueType or OpenType font: {}", path.display()))?;
+        Ok(Font::Outline(font))
+    }
+
+    /// Width and height of `text` on one line. `scale` is the built-in
+    /// font's pixel size; outline fonts are sized to the same line height so
+    /// layouts don't change with the font.
+    pub fn text_size(&self, text: &str, scale: u32) -> (u32, u32) {
+        match self {
+            Font::Builtin => text_size(text, scale),
+            Font::Outline(font) => {
+                let font = font.as_scaled(PxScale::from((GLYPH_HEIGHT * scale) as f32));
+                let width: f32 = outline_positions(&font, text)
+                    .last()
+                    .map_or(0.0, |&(id, x)| x + font.h_advance(id));
+                (width.ceil() as u32, GLYPH_HEIGHT * scale)
+            }
+        }
+    }
+
+    /// Draw `text` with its top-left corner at (x, y), clipping anything that
+    /// falls outside the image. Outline glyphs are anti-aliased against the
+    /// pixels already there.
+    pub fn draw_text(
+        &self,
+        img: &mut RgbaImage,
+        text: &str,
+        (x, y): (u32, u32),
+        scale: u32,
+        color: Rgba<u8>,
+    ) {
+        let Font::Outline(font) = self else {
+            return draw_text(img, text, (x, y), scale, color);
+        };
+
+        let scaled = font.as_scaled(PxScale::from((GLYPH_HEIGHT * scale) as f32));
+        let baseline = y as f32 + scaled.ascent();
+        for (id, left) in outline_positions(&scaled, text) {
+            let glyph = id.with_scale_and_position(scaled.scale(), (x as f32 + left, baseline));
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i64 + gx as i64;
+                let py = bounds.min.y as i64 + gy as i64;
+                if px < 0 || py < 0 || px >= img.width() as i64 || py >= img.height() as i64 {
+                    return;
+                }
+                let pixel = img.get_pixel_mut(px as u32, py as u32);
+                for c in 0..3 {
+                    let blended =
+                        pixel[c] as f32 + (color[c] as f32 - pixel[c] as f32) * coverage.min(1.0);
+                    pixel[c] = blended.round() as u8;
+                }
+                pixel[3] = pixel[3].max((color[3] as f32 * coverage.min(1.0)).round() as u8);
+            });
+        }
+    }
+}
+
+/// Each glyph of `text` with its left edge, kerned against the one before
+fn outline_positions(font: &PxScaleFont<&FontArc>, text: &str) -> Vec<(GlyphId, f32)> {
+    let mut positions = Vec::new();
+    let mut x = 0.0;
+    let mut previous: Option<GlyphId> = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(prev) = previous {
+            x += font.h_advance(prev) + font.kern(prev, id);
+        }
+        positions.push((id, x));
+        previous = Some(id);
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_size() {
+        assert_eq!(text_size("AB", 1), (11, 7));
+        assert_eq!(text_size("AB", 3), (33, 21));
+        assert_eq!(text_size("", 2), (0, 14));
+    }
+
+    #[test]
+    fn test_draw_text_lowercase_matches_uppercase() {
+        let white = Rgba([255, 255, 255, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+        let mut lower = RgbaImage::from_pixel(40, 10, white);
+        let mut upper = lower.clone();
+        draw_text(&mut lower, "scan", (1, 1), 1, black);
+        draw_text(&mut upper, "SCAN", (1, 1), 1, black);
+
+        assert_eq!(lower, upper);
+        // Top row of the S: pixels 1-4 of the glyph
+        assert_eq!(*lower.get_pixel(1, 1), white);
+        assert_eq!(*lower.get_pixel(2, 1), black);
+    }
+
+    /// An OFL-licensed subset of Fira Mono, see tests/fonts/OFL.txt
+    fn fixture_font() -> Font {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fonts/FiraMono-subset.ttf");
+        Font::load(&path).unwrap()
+    }
+
+    #[test]
+    fn test_outline_font_draws_differently() {
+        let font = fixture_font();
+        let white = Rgba([255, 255, 255, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+        let mut builtin = RgbaImage::from_pixel(120, 30, white);
+        let mut outline = builtin.clone();
+        Font::Builtin.draw_text(&mut builtin, "SCAN ME", (2, 2), 3, black);
+        font.draw_text(&mut outline, "SCAN ME", (2, 2), 3, black);
+
+        assert_ne!(builtin, outline);
+        // Same line height as the pixel font, with some ink in it
+        let (width, height) = font.text_size("SCAN ME", 3);
+        assert_eq!(height, 21);
+        assert!(width > 0 && width < 120);
+        assert!(outline.pixels().any(|p| p[0] < 128));
+    }
+
+    #[test]
+    fn test_load_rejects_non_font() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("font.ttf");
+        std::fs::write(&path, b"not a font").unwrap();
+        assert!(Font::load(&path).is_err());
+        assert!(Font::load(&dir.path().join("missing.ttf")).is_err());
+    }
+}
@@ -12,6 +12,21 @@ fn run_qrgen(args: &[&str]) -> std::process::Output {
         .expect("Failed to execute qrgen")
 }
 
+/// Helper function to decode the QR code in a saved image
+fn decode_qr(path: &std::path::Path) -> String {
+    let img = image::ImageReader::open(path)
+        .unwrap()
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .unwrap()
+        .to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare(img);
+    let grids = prepared.detect_grids();
+    let (_, content) = grids[0].decode().unwrap();
+    content
+}
+
 #[test]
 fn test_basic_qr_generation() {
     let temp_dir = TempDir::new().unwrap();
@@ -35,6 +50,178 @@ fn test_qr_generation_with_url() {
     assert!(output_path.exists());
 }
 
+#[test]
+fn test_tiff_output() {
+    let temp_dir = TempDir::new().unwrap();
+
+    for compression in ["none", "lzw", "deflate"] {
+        let output_path = temp_dir.path().join(format!("qr_{}.tiff", compression));
+        let output = run_qrgen(&[
+            "test",
+            "-o",
+            output_path.to_str().unwrap(),
+            "--tiff-compression",
+            compression,
+        ]);
+
+        assert!(output.status.success(), "Failed for {}", compression);
+        let bytes = std::fs::read(&output_path).unwrap();
+        assert!(bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*"));
+    }
+}
+
+#[test]
+fn test_json_matrix_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("matrix.json");
+
+    let output = run_qrgen(&["https://example.com", "-o", output_path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let contents = std::fs::read_to_string(&output_path).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&contents).unwrap();
+
+    let width = json["width"].as_u64().unwrap() as usize;
+    assert_eq!(width, 25);
+    assert_eq!(json["version"], 2);
+    let rows = json["modules"].as_array().unwrap();
+    assert_eq!(rows.len(), width);
+    for row in rows {
+        assert_eq!(row.as_array().unwrap().len(), width);
+    }
+}
+
+#[test]
+fn test_emit_png_and_json() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("out.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--emit",
+        "png,json",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let contents = std::fs::read_to_string(temp_dir.path().join("out.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    let width = json["width"].as_u64().unwrap() as u32;
+
+    // Default 10 px modules with a 4-module border on each side
+    let img = image::open(&output_path).unwrap();
+    assert_eq!(img.width() / 10 - 2 * 4, width);
+    assert_eq!(decode_qr(&output_path), "https://example.com");
+
+    // Without -o there's nothing to write the formats next to
+    let output = run_qrgen(&["https://example.com", "--emit", "png,json", "--terminal"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--emit"));
+}
+
+#[test]
+fn test_min_version() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("matrix.json");
+
+    let output = run_qrgen(&[
+        "hi",
+        "--min-version",
+        "5",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let contents = std::fs::read_to_string(&output_path).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    assert_eq!(json["version"], 5);
+    assert_eq!(json["width"], 37);
+
+    let output = run_qrgen(&["hi", "--min-version", "41"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_normalize_nfc_shrinks_decomposed_text() {
+    let temp_dir = TempDir::new().unwrap();
+    // Twenty "é" spelled as "e" plus a combining acute accent
+    let decomposed = "e\u{301}".repeat(20);
+
+    let version_for = |form: &str| {
+        let output_path = temp_dir.path().join(format!("{}.json", form));
+        let output = run_qrgen(&[
+            &decomposed,
+            "--normalize",
+            form,
+            "-o",
+            output_path.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+        let contents = std::fs::read_to_string(&output_path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        json["version"].as_i64().unwrap()
+    };
+
+    let nfc = version_for("nfc");
+    let nfd = version_for("nfd");
+    // 40 bytes composed versus 60 decomposed crosses a version boundary
+    assert!(nfc < nfd, "NFC version {} vs NFD version {}", nfc, nfd);
+}
+
+#[test]
+fn test_output_format_without_extension() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("plainname");
+
+    let output = run_qrgen(&[
+        "test",
+        "-o",
+        output_path.to_str().unwrap(),
+        "--output-format",
+        "png",
+    ]);
+
+    assert!(output.status.success());
+    let bytes = std::fs::read(&output_path).unwrap();
+    assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+    assert_eq!(decode_qr(&output_path), "test");
+}
+
+#[test]
+fn test_output_format_overrides_extension() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("code.png");
+
+    let output = run_qrgen(&[
+        "test",
+        "-o",
+        output_path.to_str().unwrap(),
+        "--output-format",
+        "svg",
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Warning"));
+    let contents = std::fs::read_to_string(&output_path).unwrap();
+    assert!(contents.starts_with("<svg"));
+}
+
+#[test]
+fn test_raster_formats_by_extension() {
+    let temp_dir = TempDir::new().unwrap();
+
+    for ext in ["jpg", "webp", "bmp"] {
+        let output_path = temp_dir.path().join(format!("qr.{}", ext));
+        let output = run_qrgen(&["test", "-o", output_path.to_str().unwrap()]);
+
+        assert!(output.status.success(), "Failed for {}", ext);
+        assert_eq!(decode_qr(&output_path), "test");
+    }
+}
+
 #[test]
 fn test_custom_size() {
     let temp_dir = TempDir::new().unwrap();
@@ -78,11 +265,150 @@ fn test_custom_border() {
     assert!(output_path.exists());
 }
 
+#[test]
+fn test_narrow_border_keeps_quiet_zone() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("narrow_border.png");
+
+    let output = run_qrgen(&[
+        "test",
+        "--size",
+        "10",
+        "--border",
+        "1",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--border 1 is narrower than the 4-box quiet zone"));
+
+    // Version 1 is 21 modules wide, plus 4 modules of white on each side
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    assert_eq!(img.dimensions(), (290, 290));
+    let in_quiet_zone = |x: u32, y: u32| x < 40 || y < 40 || x >= 250 || y >= 250;
+    assert!(img
+        .enumerate_pixels()
+        .filter(|(x, y, _)| in_quiet_zone(*x, *y))
+        .all(|(_, _, p)| p.0 == [255, 255, 255, 255]));
+    // The finder pattern starts right after it
+    assert_eq!(img.get_pixel(40, 40).0, [0, 0, 0, 255]);
+    assert_eq!(decode_qr(&output_path), "test");
+}
+
+#[test]
+fn test_code_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("bare.png");
+
+    let output = run_qrgen(&[
+        "test",
+        "--code-only",
+        "-s",
+        "5",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    // Version 1 is 21 modules, with no quiet zone
+    assert_eq!(img.dimensions(), (105, 105));
+    let black = image::Rgba([0, 0, 0, 255]);
+    assert!(img.pixels().all(|p| *p == black || p[3] == 0));
+    // The finder pattern's corner is dark; the separator beside it is light
+    assert_eq!(*img.get_pixel(0, 0), black);
+    assert_eq!(img.get_pixel(35, 0)[3], 0);
+
+    // The transparent background reads as light when verifying
+    let output = run_qrgen(&[
+        "test",
+        "--code-only",
+        "--verify",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let output = run_qrgen(&["test", "--code-only", "-o", "bare.jpg"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_per_side_borders() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("sides.png");
+
+    let output = run_qrgen(&[
+        "test",
+        "-s",
+        "10",
+        "--border-top",
+        "4",
+        "--border-right",
+        "6",
+        "--border-bottom",
+        "8",
+        "--border-left",
+        "12",
+        "--border-color",
+        "lightyellow",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    // Version 1 is 21 modules; 12 + 6 across and 4 + 8 down
+    assert_eq!(img.dimensions(), (390, 330));
+    // The top-left finder pattern starts 12 modules in and 4 down
+    let black = image::Rgba([0, 0, 0, 255]);
+    let border = image::Rgba([255, 255, 224, 255]);
+    assert_eq!(*img.get_pixel(120, 40), black);
+    assert_eq!(*img.get_pixel(119, 40), border);
+    assert_eq!(*img.get_pixel(120, 39), border);
+    assert_eq!(decode_qr(&output_path), "test");
+}
+
+#[test]
+fn test_per_side_border_below_quiet_zone_rejected() {
+    let output = run_qrgen(&["test", "--border-left", "2"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--border-left 2"));
+}
+
+#[test]
+fn test_no_quiet_zone() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("no_quiet_zone_qr.png");
+
+    let output = run_qrgen(&[
+        "test",
+        "-o",
+        output_path.to_str().unwrap(),
+        "--size",
+        "10",
+        "--no-quiet-zone",
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Warning"));
+
+    // "test" at EC M is a version 1 code: 21 modules wide, no border
+    let (width, height) = image::image_dimensions(&output_path).unwrap();
+    assert_eq!(width, 21 * 10);
+    assert_eq!(height, 21 * 10);
+}
+
 #[test]
 fn test_error_correction_levels() {
     let temp_dir = TempDir::new().unwrap();
 
-    for level in ["L", "M", "Q", "H"] {
+    for level in ["L", "M", "Q", "H", "auto"] {
         let output_path = temp_dir.path().join(format!("qr_{}.png", level));
         let output = run_qrgen(&[
             "test",
@@ -117,45 +443,53 @@ fn test_custom_colors() {
 }
 
 #[test]
-fn test_hex_colors() {
+fn test_auto_contrast_adjusts_low_contrast_pair() {
     let temp_dir = TempDir::new().unwrap();
-    let output_path = temp_dir.path().join("hex_qr.png");
+    let output_path = temp_dir.path().join("adjusted.png");
 
     let output = run_qrgen(&[
         "test",
         "-o",
         output_path.to_str().unwrap(),
         "--fill-color",
-        "#FF5733",
+        "#808080",
         "--back-color",
-        "#C70039",
+        "lightgray",
+        "--auto-contrast",
     ]);
 
     assert!(output.status.success());
-    assert!(output_path.exists());
-}
-
-#[test]
-fn test_terminal_output() {
-    let output = run_qrgen(&["test", "--terminal"]);
-
-    assert!(output.status.success());
-    // Terminal output should contain box drawing characters
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("█") || stdout.contains("▄") || stdout.contains("▀"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Adjusted colors for contrast: fill #616161, back #dedede"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("low contrast"));
+
+    // The image uses the adjusted pair: quiet zone and a finder center
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    assert_eq!(*img.get_pixel(0, 0), image::Rgba([222, 222, 222, 255]));
+    let center = 4 * 10 + 3 * 10 + 5;
+    assert_eq!(
+        *img.get_pixel(center, center),
+        image::Rgba([97, 97, 97, 255])
+    );
 }
 
 #[test]
-fn test_wifi_template() {
+fn test_hex_colors() {
     let temp_dir = TempDir::new().unwrap();
-    let output_path = temp_dir.path().join("wifi_qr.png");
+    let output_path = temp_dir.path().join("hex_qr.png");
 
     let output = run_qrgen(&[
-        "MyNetwork,password123,WPA",
-        "--template",
-        "wifi",
+        "test",
         "-o",
         output_path.to_str().unwrap(),
+        "--fill-color",
+        "#FF5733",
+        "--back-color",
+        "#C70039",
     ]);
 
     assert!(output.status.success());
@@ -163,50 +497,76 @@ fn test_wifi_template() {
 }
 
 #[test]
-fn test_vcard_template() {
+fn test_back_gradient_keeps_modules_solid() {
     let temp_dir = TempDir::new().unwrap();
-    let output_path = temp_dir.path().join("vcard_qr.png");
+    let output_path = temp_dir.path().join("gradient.png");
 
     let output = run_qrgen(&[
-        "John Doe,+1234567890,john@example.com,Acme Corp",
-        "--template",
-        "vcard",
+        "test",
         "-o",
         output_path.to_str().unwrap(),
+        "--fill-color",
+        "#000080",
+        "--back-gradient",
+        "white,#c0d8ff",
+        "--verify",
     ]);
 
     assert!(output.status.success());
-    assert!(output_path.exists());
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    let (width, height) = img.dimensions();
+    // The quiet zone runs from the first color at the top to the second at the bottom
+    assert_eq!(*img.get_pixel(0, 0), image::Rgba([255, 255, 255, 255]));
+    assert_eq!(
+        *img.get_pixel(0, height - 1),
+        image::Rgba([192, 216, 255, 255])
+    );
+
+    // Finder pattern centers near the top and bottom share the solid fill
+    let fill = image::Rgba([0, 0, 128, 255]);
+    let (border, size) = (4 * 10, 10);
+    let center = border + 3 * size + size / 2;
+    assert_eq!(*img.get_pixel(center, center), fill);
+    assert_eq!(*img.get_pixel(center, width - 1 - center), fill);
 }
 
 #[test]
-fn test_sms_template() {
+fn test_back_gradient_low_contrast_warning() {
     let temp_dir = TempDir::new().unwrap();
-    let output_path = temp_dir.path().join("sms_qr.png");
+    let output_path = temp_dir.path().join("gradient.png");
 
     let output = run_qrgen(&[
-        "1234567890,Hello!",
-        "--template",
-        "sms",
+        "test",
         "-o",
         output_path.to_str().unwrap(),
+        "--back-gradient",
+        "white,#303030",
     ]);
 
     assert!(output.status.success());
-    assert!(output_path.exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("low contrast"), "{}", stderr);
+
+    let output = run_qrgen(&[
+        "test",
+        "-o",
+        temp_dir.path().join("gradient.svg").to_str().unwrap(),
+        "--back-gradient",
+        "white,#c0d8ff",
+    ]);
+    assert!(!output.status.success());
 }
 
 #[test]
-fn test_email_template() {
+fn test_verify_flag() {
     let temp_dir = TempDir::new().unwrap();
-    let output_path = temp_dir.path().join("email_qr.png");
+    let output_path = temp_dir.path().join("verified_qr.png");
 
     let output = run_qrgen(&[
-        "test@example.com,Subject,Body",
-        "--template",
-        "email",
+        "https://example.com",
         "-o",
         output_path.to_str().unwrap(),
+        "--verify",
     ]);
 
     assert!(output.status.success());
@@ -214,45 +574,2148 @@ fn test_email_template() {
 }
 
 #[test]
-fn test_phone_template() {
+fn test_template_default_error_correction() {
     let temp_dir = TempDir::new().unwrap();
-    let output_path = temp_dir.path().join("phone_qr.png");
+    let output_path = temp_dir.path().join("wifi.png");
+    let path = output_path.to_str().unwrap();
 
     let output = run_qrgen(&[
-        "1234567890",
+        "MyNet,secret,WPA",
         "--template",
-        "phone",
+        "wifi",
+        "--encoding-report",
         "-o",
-        output_path.to_str().unwrap(),
+        path,
     ]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\"error_correction\": \"L\""));
 
+    let output = run_qrgen(&[
+        "MyNet,secret,WPA",
+        "--template",
+        "wifi",
+        "-e",
+        "H",
+        "--encoding-report",
+        "-o",
+        path,
+    ]);
     assert!(output.status.success());
-    assert!(output_path.exists());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\"error_correction\": \"H\""));
 }
 
 #[test]
-fn test_help_output() {
-    let output = run_qrgen(&["--help"]);
+fn test_terminal_output() {
+    let output = run_qrgen(&["test", "--terminal"]);
 
     assert!(output.status.success());
+    // Terminal output should contain box drawing characters
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("Generate QR codes"));
-    assert!(stdout.contains("--output"));
-    assert!(stdout.contains("--size"));
-    assert!(stdout.contains("--terminal"));
+    assert!(stdout.contains("█") || stdout.contains("▄") || stdout.contains("▀"));
 }
 
 #[test]
-fn test_version_output() {
-    let output = run_qrgen(&["--version"]);
+fn test_terminal_theme_dark_inverts() {
+    let light = run_qrgen(&["test", "--terminal", "--terminal-theme", "light"]);
+    let dark = run_qrgen(&["test", "--terminal", "--terminal-theme", "dark"]);
+    assert!(light.status.success());
+    assert!(dark.status.success());
+
+    // The quiet zone row is blank on light terminals and solid on dark ones
+    let light = String::from_utf8_lossy(&light.stdout);
+    let dark = String::from_utf8_lossy(&dark.stdout);
+    let first_line = |s: &str| s.lines().next().unwrap_or_default().to_string();
+    assert!(first_line(&light).chars().all(|c| c == ' '));
+    assert!(first_line(&dark).chars().all(|c| c == '█'));
+    assert_eq!(light.chars().count(), dark.chars().count());
+}
+
+#[test]
+fn test_max_payload_bytes_vcard() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("contact.png");
+    let contact = "John Doe,+1234567890,john@example.com,Acme Corporation International";
+    let run = |limit: &str| {
+        run_qrgen(&[
+            contact,
+            "--template",
+            "vcard",
+            "--max-payload-bytes",
+            limit,
+            "-o",
+            output_path.to_str().unwrap(),
+        ])
+    };
+
+    let output = run("64");
+    assert!(!output.status.success());
+    assert!(!output_path.exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("more than the --max-payload-bytes limit of 64"));
+    assert!(stderr.contains("Payload is "));
 
+    let output = run("1000");
     assert!(output.status.success());
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("0.1.0"));
+    assert!(decode_qr(&output_path).starts_with("BEGIN:VCARD"));
 }
 
 #[test]
-fn test_invalid_color() {
+fn test_wifi_template_strict_encryption() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("wifi_qr.png");
+    let path = output_path.to_str().unwrap();
+
+    let output = run_qrgen(&[
+        "MyNetwork,password123,WPA2",
+        "--template",
+        "wifi",
+        "--strict-wifi-encryption",
+        "-o",
+        path,
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        decode_qr(&output_path),
+        "WIFI:T:WPA;S:MyNetwork;P:password123;;"
+    );
+
+    let output = run_qrgen(&[
+        "MyNetwork,password123,GIBBERISH",
+        "--template",
+        "wifi",
+        "--strict-wifi-encryption",
+        "-o",
+        path,
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown WiFi encryption"));
+}
+
+#[test]
+fn test_template_help() {
+    let output = run_qrgen(&["--template-help", "wifi"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for field in ["ssid", "password", "encryption"] {
+        assert!(stdout.contains(field), "{}", stdout);
+    }
+    assert!(stdout.contains("--template wifi"));
+
+    let output = run_qrgen(&["--template-help", "nope"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_wifi_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("wifi_qr.png");
+
+    let output = run_qrgen(&[
+        "MyNetwork,password123,WPA",
+        "--template",
+        "wifi",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(output_path.exists());
+}
+
+#[test]
+fn test_vcard_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("vcard_qr.png");
+
+    let output = run_qrgen(&[
+        "John Doe,+1234567890,john@example.com,Acme Corp",
+        "--template",
+        "vcard",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(output_path.exists());
+}
+
+#[test]
+fn test_sms_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("sms_qr.png");
+
+    let output = run_qrgen(&[
+        "1234567890,Hello!",
+        "--template",
+        "sms",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(output_path.exists());
+}
+
+#[test]
+fn test_sms_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("sms_qr.png");
+
+    for (format, expected) in [
+        ("smsto", "SMSTO:1234567890:See you at 6"),
+        ("uri", "sms:1234567890?body=See%20you%20at%206"),
+    ] {
+        let output = run_qrgen(&[
+            "1234567890,See you at 6",
+            "--template",
+            "sms",
+            "--sms-format",
+            format,
+            "-o",
+            output_path.to_str().unwrap(),
+        ]);
+
+        assert!(output.status.success());
+        assert_eq!(decode_qr(&output_path), expected);
+    }
+}
+
+#[test]
+fn test_email_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("email_qr.png");
+
+    let output = run_qrgen(&[
+        "test@example.com,Subject,Body",
+        "--template",
+        "email",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(output_path.exists());
+}
+
+#[test]
+fn test_phone_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("phone_qr.png");
+
+    let output = run_qrgen(&[
+        "1234567890",
+        "--template",
+        "phone",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(output_path.exists());
+}
+
+#[test]
+fn test_calendar_template_also_ics() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("event.png");
+    let ics_path = temp_dir.path().join("out.ics");
+
+    let output = run_qrgen(&[
+        "Quarterly review,2026-11-02T14:00Z,2026-11-02T15:00Z,Board room",
+        "--template",
+        "calendar",
+        "--also-ics",
+        ics_path.to_str().unwrap(),
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let payload = decode_qr(&output_path);
+    assert!(payload.starts_with("BEGIN:VEVENT\n"));
+    let summary = payload
+        .lines()
+        .find(|line| line.starts_with("SUMMARY:"))
+        .unwrap();
+    assert_eq!(summary, "SUMMARY:Quarterly review");
+
+    let ics = std::fs::read_to_string(&ics_path).unwrap();
+    assert!(ics.starts_with("BEGIN:VCALENDAR"));
+    assert!(ics.lines().any(|line| line == summary));
+
+    // --no-clobber covers the invite too
+    let output = run_qrgen(&[
+        "Quarterly review,2026-11-02T14:00Z,2026-11-02T15:00Z",
+        "--template",
+        "calendar",
+        "--also-ics",
+        ics_path.to_str().unwrap(),
+        "--no-clobber",
+        "-o",
+        temp_dir.path().join("other.png").to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(std::fs::read_to_string(&ics_path).unwrap(), ics);
+
+    // No invite is left behind when the code itself can't be saved
+    let orphan_path = temp_dir.path().join("orphan.ics");
+    let output = run_qrgen(&[
+        "Quarterly review,2026-11-02T14:00Z,2026-11-02T15:00Z",
+        "--template",
+        "calendar",
+        "--also-ics",
+        orphan_path.to_str().unwrap(),
+        "--border-radius",
+        "5",
+        "-o",
+        temp_dir.path().join("event.svg").to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert!(!orphan_path.exists());
+
+    // The invite only makes sense for calendar events
+    let output = run_qrgen(&[
+        "1234567890",
+        "--template",
+        "phone",
+        "--also-ics",
+        ics_path.to_str().unwrap(),
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_batch_generation() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("batch.csv");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::write(
+        &csv_path,
+        "data,filename\nhttps://example.com,one.png\nsecond,two.png\nthird,\n",
+    )
+    .unwrap();
+
+    // stderr is captured (not a TTY), so the progress bar stays hidden
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(out_dir.join("one.png").exists());
+    assert!(out_dir.join("two.png").exists());
+    assert!(out_dir.join("qr_0003.png").exists());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("3 succeeded, 0 failed"));
+}
+
+#[test]
+fn test_batch_verify_unique_flags_duplicates() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("tickets.csv");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::write(
+        &csv_path,
+        "data,filename\nTICKET-001,a.png\nTICKET-002,b.png\nTICKET-001,c.png\n",
+    )
+    .unwrap();
+
+    let run = |mode: &str| {
+        run_qrgen(&[
+            "--batch",
+            csv_path.to_str().unwrap(),
+            mode,
+            "-o",
+            out_dir.to_str().unwrap(),
+        ])
+    };
+
+    let output = run("--verify-unique");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Row 3: same payload as row 1"));
+    assert!(stderr.contains("1 of 3 rows repeat an earlier payload"));
+    assert!(!out_dir.join("a.png").exists());
+
+    let output = run("--verify-unique=warn");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Row 3: same payload as row 1"));
+    assert!(out_dir.join("c.png").exists());
+}
+
+#[test]
+fn test_batch_validate_only_reports_failing_row() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("wifi.csv");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::write(
+        &csv_path,
+        "data\n\"HomeNet,secret,WPA\"\nbroken\n\"GuestNet,,nopass\"\n",
+    )
+    .unwrap();
+
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--template",
+        "wifi",
+        "--validate-only",
+    ]);
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Row 1: ok"));
+    assert!(stdout.contains("Row 3: ok"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Row 2: WiFi template expects"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("2 passed, 1 failed"));
+    // Nothing is written, not even the output directory
+    assert!(!out_dir.exists());
+}
+
+#[test]
+fn test_batch_parallel_jobs() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("batch.csv");
+    let out_dir = temp_dir.path().join("out");
+    let mut csv = String::from("data\n");
+    for i in 1..=20 {
+        csv.push_str(&format!("https://example.com/item/{}\n", i));
+    }
+    std::fs::write(&csv_path, csv).unwrap();
+
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--jobs",
+        "4",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(std::fs::read_dir(&out_dir).unwrap().count(), 20);
+    for i in 1..=20 {
+        let path = out_dir.join(format!("qr_{:04}.png", i));
+        assert_eq!(decode_qr(&path), format!("https://example.com/item/{}", i));
+    }
+
+    // Messages come out in row order even though rows finish in any order
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let saved: Vec<&str> = stdout.lines().collect();
+    assert_eq!(saved.len(), 20);
+    assert!(saved[0].ends_with("qr_0001.png"));
+    assert!(saved[19].ends_with("qr_0020.png"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("20 succeeded, 0 failed"));
+}
+
+#[test]
+fn test_batch_rows_do_not_prompt() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("wifi.csv");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::write(&csv_path, "data\n\"Office,secret,WPA\"\nwifi\n").unwrap();
+
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "--template",
+        "wifi",
+        "-o",
+        out_dir.to_str().unwrap(),
+    ]);
+
+    // The short row fails instead of asking for the network details
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Network SSID"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Row 2:"));
+    assert!(stderr.contains("1 succeeded, 1 failed"));
+}
+
+#[test]
+fn test_batch_rejects_shared_output_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("batch.csv");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::write(&csv_path, "data,filename\none,code.png\ntwo,code.png\n").unwrap();
+
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Rows 1 and 2 would both be saved to"));
+    assert!(!out_dir.join("code.png").exists());
+}
+
+#[test]
+fn test_json_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("code.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--json-output",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["path"], output_path.to_str().unwrap());
+    assert_eq!(summary["format"], "png");
+    assert_eq!(summary["version"], 2);
+    assert_eq!(summary["width_px"], 330);
+    assert_eq!(summary["ec_level"], "M");
+    assert_eq!(summary["bytes"], 19);
+
+    // Batch modes print one array once every row is done
+    let csv_path = temp_dir.path().join("batch.csv");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::write(&csv_path, "data,filename\nfirst,a.png\nsecond,b.svg\n").unwrap();
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--json-output",
+    ]);
+
+    assert!(output.status.success());
+    let summaries: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let summaries = summaries.as_array().unwrap();
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(
+        summaries[1]["path"],
+        out_dir.join("b.svg").to_str().unwrap()
+    );
+    assert_eq!(summaries[1]["format"], "svg");
+    assert_eq!(summaries[1]["version"], 1);
+}
+
+#[test]
+fn test_batch_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("batch.csv");
+    let out_dir = temp_dir.path().join("out");
+    let manifest_path = temp_dir.path().join("out.json");
+    std::fs::write(
+        &csv_path,
+        "data,filename\nfirst,a.png\nsecond,b.png\nthird,c.svg\n",
+    )
+    .unwrap();
+
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--manifest",
+        manifest_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    // A .json manifest is one JSON array, in row order
+    let manifest = std::fs::read_to_string(&manifest_path).unwrap();
+    let entries: Vec<serde_json::Value> = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(entries.len(), 3);
+    for (entry, (data, filename)) in
+        entries
+            .iter()
+            .zip([("first", "a.png"), ("second", "b.png"), ("third", "c.svg")])
+    {
+        assert_eq!(entry["path"], out_dir.join(filename).to_str().unwrap());
+        assert_eq!(entry["payload"], data);
+        assert_eq!(entry["sha256"].as_str().unwrap().len(), 64);
+        assert!(out_dir.join(filename).exists());
+    }
+}
+
+#[test]
+fn test_no_clobber_refuses_existing_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("existing.png");
+    std::fs::write(&output_path, b"keep me").unwrap();
+
+    let output = run_qrgen(&["test", "--no-clobber", "-o", output_path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+    assert_eq!(std::fs::read(&output_path).unwrap(), b"keep me");
+
+    let output = run_qrgen(&["test", "--force", "-o", output_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&output_path), "test");
+}
+
+#[test]
+fn test_batch_no_clobber_skips_existing() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("batch.csv");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::create_dir(&out_dir).unwrap();
+    std::fs::write(out_dir.join("one.png"), b"keep me").unwrap();
+    std::fs::write(
+        &csv_path,
+        "data,filename
+first,one.png
+second,two.png
+",
+    )
+    .unwrap();
+
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--no-clobber",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(std::fs::read(out_dir.join("one.png")).unwrap(), b"keep me");
+    assert_eq!(decode_qr(&out_dir.join("two.png")), "second");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 succeeded, 0 failed, 1 skipped"));
+}
+
+#[test]
+fn test_data_suffix() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("suffix_qr.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "-o",
+        output_path.to_str().unwrap(),
+        "--data-suffix",
+        "?x=1",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&output_path), "https://example.com?x=1");
+}
+
+#[test]
+fn test_data_prefix_and_suffix() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("affix_qr.png");
+
+    let output = run_qrgen(&[
+        "test@example.com",
+        "--template",
+        "email",
+        "-o",
+        output_path.to_str().unwrap(),
+        "--data-prefix",
+        "[",
+        "--data-suffix",
+        "]",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        decode_qr(&output_path),
+        "[mailto:test@example.com?subject=&body=]"
+    );
+}
+
+#[test]
+fn test_strip_control_chars() {
+    let temp_dir = TempDir::new().unwrap();
+    let raw_path = temp_dir.path().join("raw.png");
+    let clean_path = temp_dir.path().join("clean.png");
+    // 13 bytes of text fits version 1 at level M; the BOM and zero-width
+    // space add three bytes each
+    let data = "\u{feff}hello, \u{200b}world!";
+
+    let raw = run_qrgen(&[data, "-e", "M", "-o", raw_path.to_str().unwrap()]);
+    let clean = run_qrgen(&[
+        data,
+        "-e",
+        "M",
+        "--strip-control-chars",
+        "-o",
+        clean_path.to_str().unwrap(),
+    ]);
+    assert!(raw.status.success());
+    assert!(clean.status.success());
+    assert!(String::from_utf8_lossy(&clean.stderr).contains("Removed 2 control character(s)"));
+
+    let raw_img = image::open(&raw_path).unwrap();
+    let clean_img = image::open(&clean_path).unwrap();
+    assert!(clean_img.width() < raw_img.width());
+    assert_eq!(decode_qr(&clean_path), "hello, world!");
+}
+
+#[test]
+fn test_data_base64() {
+    let temp_dir = TempDir::new().unwrap();
+    let plain_path = temp_dir.path().join("plain.png");
+    let decoded_path = temp_dir.path().join("decoded.png");
+
+    let plain = run_qrgen(&["hello", "-o", plain_path.to_str().unwrap()]);
+    let decoded = run_qrgen(&[
+        "aGVsbG8=",
+        "--data-base64",
+        "-o",
+        decoded_path.to_str().unwrap(),
+    ]);
+
+    assert!(plain.status.success());
+    assert!(decoded.status.success());
+    assert_eq!(
+        image::open(&plain_path).unwrap().to_rgba8(),
+        image::open(&decoded_path).unwrap().to_rgba8()
+    );
+
+    let invalid = run_qrgen(&[
+        "not base64!",
+        "--data-base64",
+        "-o",
+        decoded_path.to_str().unwrap(),
+    ]);
+    assert!(!invalid.status.success());
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("Invalid base64 data"));
+}
+
+#[test]
+fn test_wrap_data_uri_vcard() {
+    use base64::Engine;
+
+    let temp_dir = TempDir::new().unwrap();
+    let plain_path = temp_dir.path().join("plain.png");
+    let wrapped_path = temp_dir.path().join("wrapped.png");
+
+    let plain = run_qrgen(&[
+        "John Doe,+1234567890",
+        "--template",
+        "vcard",
+        "-o",
+        plain_path.to_str().unwrap(),
+    ]);
+    let wrapped = run_qrgen(&[
+        "John Doe,+1234567890",
+        "--template",
+        "vcard",
+        "--wrap-data-uri",
+        "text/vcard",
+        "-o",
+        wrapped_path.to_str().unwrap(),
+    ]);
+    assert!(plain.status.success());
+    assert!(wrapped.status.success());
+
+    let decoded = decode_qr(&wrapped_path);
+    let encoded = decoded
+        .strip_prefix("data:text/vcard;base64,")
+        .expect("payload should be a vCard data URI");
+    let vcard = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .unwrap();
+    assert_eq!(String::from_utf8(vcard).unwrap(), decode_qr(&plain_path));
+
+    let invalid = run_qrgen(&[
+        "hello",
+        "--wrap-data-uri",
+        "vcard",
+        "-o",
+        wrapped_path.to_str().unwrap(),
+    ]);
+    assert!(!invalid.status.success());
+}
+
+#[test]
+fn test_whatsapp_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("whatsapp_qr.png");
+
+    let output = run_qrgen(&[
+        "+44 7700 900123,Hello there",
+        "--template",
+        "whatsapp",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        decode_qr(&output_path),
+        "https://wa.me/447700900123?text=Hello%20there"
+    );
+}
+
+#[test]
+fn test_hash_suffix_names_follow_payload() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("batch.csv");
+    let out_dir = temp_dir.path().join("out");
+    // Both rows ask for the same name, so only the hash keeps them apart
+    std::fs::write(&csv_path, "data,filename\nalpha,code.png\nbeta,code.png\n").unwrap();
+
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--hash-suffix",
+    ]);
+    assert!(output.status.success());
+
+    let mut names: Vec<String> = std::fs::read_dir(&out_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names.len(), 2, "{:?}", names);
+    for name in &names {
+        let hash = name
+            .strip_prefix("code.")
+            .and_then(|rest| rest.strip_suffix(".png"))
+            .unwrap();
+        assert_eq!(hash.len(), 8);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    // The same payload on its own lands on the same name as its batch row
+    let output = run_qrgen(&[
+        "alpha",
+        "-o",
+        out_dir.join("code.png").to_str().unwrap(),
+        "--hash-suffix",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let saved = stdout.trim().strip_prefix("QR code saved to: ").unwrap();
+    assert_eq!(decode_qr(std::path::Path::new(saved)), "alpha");
+    assert_eq!(std::fs::read_dir(&out_dir).unwrap().count(), 2);
+}
+
+#[test]
+fn test_batch_output_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let csv_path = temp_dir.path().join("products.csv");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::write(
+        &csv_path,
+        "sku,data\nA100,https://example.com/a100\nB200,https://example.com/b200\n",
+    )
+    .unwrap();
+
+    let output = run_qrgen(&[
+        "--batch",
+        csv_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+        "--output-template",
+        "code_{sku}.png",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        decode_qr(&out_dir.join("code_A100.png")),
+        "https://example.com/a100"
+    );
+    assert_eq!(
+        decode_qr(&out_dir.join("code_B200.png")),
+        "https://example.com/b200"
+    );
+}
+
+#[test]
+fn test_strict_rejects_malformed_url() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("strict.png");
+
+    let output = run_qrgen(&[
+        "https://exa mple.com",
+        "--strict",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid URL"));
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn test_strict_accepts_valid_url() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("strict.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--strict",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&output_path), "https://example.com");
+}
+
+#[test]
+fn test_module_gap() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("gap.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--module-gap",
+        "0.2",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&output_path), "https://example.com");
+}
+
+#[test]
+fn test_finder_shape_rounded() {
+    let temp_dir = TempDir::new().unwrap();
+    let square_path = temp_dir.path().join("square.png");
+    let rounded_path = temp_dir.path().join("rounded.png");
+    let run = |shape: &str, path: &std::path::Path| {
+        run_qrgen(&[
+            "https://example.com",
+            "--size",
+            "10",
+            "--border",
+            "4",
+            "--finder-shape",
+            shape,
+            "-o",
+            path.to_str().unwrap(),
+        ])
+    };
+
+    let square = run("square", &square_path);
+    let rounded = run("rounded", &rounded_path);
+    assert!(square.status.success());
+    assert!(rounded.status.success());
+    assert!(String::from_utf8_lossy(&rounded.stderr).contains("--finder-shape styles"));
+
+    // The top-left finder's hole and dot get rounded corners; the outer
+    // corner and the center stay dark
+    let square_img = image::open(&square_path).unwrap().to_rgba8();
+    let rounded_img = image::open(&rounded_path).unwrap().to_rgba8();
+    let (black, white) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+    assert_eq!(square_img.get_pixel(51, 51).0, white);
+    assert_eq!(rounded_img.get_pixel(51, 51).0, black);
+    assert_eq!(square_img.get_pixel(61, 61).0, black);
+    assert_eq!(rounded_img.get_pixel(61, 61).0, white);
+    assert_eq!(rounded_img.get_pixel(40, 40).0, black);
+    assert_eq!(rounded_img.get_pixel(75, 75).0, black);
+    assert_eq!(decode_qr(&rounded_path), "https://example.com");
+}
+
+#[test]
+fn test_brand_frame() {
+    let temp_dir = TempDir::new().unwrap();
+    let bare_path = temp_dir.path().join("bare.png");
+    let framed_path = temp_dir.path().join("framed.png");
+
+    let bare = run_qrgen(&["https://example.com", "-o", bare_path.to_str().unwrap()]);
+    let framed = run_qrgen(&[
+        "https://example.com",
+        "--brand-frame",
+        "scan-me",
+        "--frame-color",
+        "#ff0000",
+        "--square-canvas",
+        "off",
+        "-o",
+        framed_path.to_str().unwrap(),
+    ]);
+    assert!(bare.status.success());
+    assert!(framed.status.success());
+
+    let bare_img = image::open(&bare_path).unwrap().to_rgba8();
+    let framed_img = image::open(&framed_path).unwrap().to_rgba8();
+    assert!(framed_img.width() > bare_img.width());
+    assert!(framed_img.height() > bare_img.height());
+
+    // The frame runs down the left and right edges and across the top
+    let red = [255, 0, 0, 255];
+    let middle = bare_img.height() / 2;
+    assert_eq!(framed_img.get_pixel(0, middle).0, red);
+    assert_eq!(framed_img.get_pixel(framed_img.width() - 1, middle).0, red);
+    assert_eq!(framed_img.get_pixel(framed_img.width() / 2, 0).0, red);
+    assert_eq!(decode_qr(&framed_path), "https://example.com");
+}
+
+#[test]
+fn test_square_canvas_with_frame() {
+    let temp_dir = TempDir::new().unwrap();
+    let square_path = temp_dir.path().join("square.png");
+    let tall_path = temp_dir.path().join("tall.png");
+
+    let square = run_qrgen(&[
+        "hello",
+        "--brand-frame",
+        "scan-me",
+        "-o",
+        square_path.to_str().unwrap(),
+    ]);
+    let tall = run_qrgen(&[
+        "hello",
+        "--brand-frame",
+        "scan-me",
+        "--square-canvas",
+        "off",
+        "-o",
+        tall_path.to_str().unwrap(),
+    ]);
+    assert!(square.status.success());
+    assert!(tall.status.success());
+
+    // The tab makes the framed code taller than wide, so the canvas is
+    // padded out sideways to the same height
+    let (width, height) = image::image_dimensions(&square_path).unwrap();
+    let (tall_width, tall_height) = image::image_dimensions(&tall_path).unwrap();
+    assert!(tall_height > tall_width);
+    assert_eq!(width, height);
+    assert_eq!(height, tall_height);
+    assert_eq!(decode_qr(&square_path), "hello");
+}
+
+#[test]
+fn test_unloadable_font_falls_back() {
+    let temp_dir = TempDir::new().unwrap();
+    let font_path = temp_dir.path().join("brand.ttf");
+    let output_path = temp_dir.path().join("framed.png");
+    std::fs::write(&font_path, "not a font").unwrap();
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--brand-frame",
+        "scan-me",
+        "--font",
+        font_path.to_str().unwrap(),
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("using the built-in font"));
+    assert_eq!(decode_qr(&output_path), "https://example.com");
+}
+
+#[test]
+fn test_module_gap_out_of_range_warns() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("gap.png");
+
+    let output = run_qrgen(&[
+        "test",
+        "--module-gap",
+        "0.9",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--module-gap must be between"));
+}
+
+#[test]
+fn test_scannability_warnings_and_quiet() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("pale.png");
+    let args = [
+        "test",
+        "--fill-color",
+        "#dddddd",
+        "-o",
+        output_path.to_str().unwrap(),
+    ];
+
+    let output = run_qrgen(&args);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("low contrast"));
+
+    let output = run_qrgen(&[&args[..], &["--quiet"]].concat());
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Warning"));
+}
+
+#[test]
+fn test_copy_falls_back_to_file() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_qrgen"))
+        .args(["https://example.com", "--copy"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to execute qrgen");
+
+    assert!(output.status.success());
+    let default_path = temp_dir.path().join("qr_code.png");
+    if String::from_utf8_lossy(&output.stderr).contains("Warning") {
+        // No clipboard here (e.g. headless CI), so the default file is written
+        assert_eq!(decode_qr(&default_path), "https://example.com");
+    } else {
+        assert!(String::from_utf8_lossy(&output.stdout).contains("copied to clipboard"));
+        assert!(!default_path.exists());
+    }
+}
+
+#[test]
+fn test_max_size_limit() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("huge.png");
+
+    let output = run_qrgen(&[
+        "test",
+        "--size",
+        "100000",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--max-size"));
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn test_tiny_modules_need_allow_tiny() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("tiny.png");
+
+    let output = run_qrgen(&["test", "--size", "1", "-o", output_path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--allow-tiny"));
+    assert!(!output_path.exists());
+
+    let output = run_qrgen(&[
+        "test",
+        "--size",
+        "1",
+        "--allow-tiny",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    // 21 modules plus the 4-module quiet zone on each side, one pixel each
+    assert_eq!(image::open(&output_path).unwrap().width(), 29);
+}
+
+#[test]
+fn test_app_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("app.png");
+
+    let output = run_qrgen(&[
+        "https://apps.apple.com/app/id123,https://play.google.com/store/apps/details?id=com.example,https://example.com/app",
+        "--template",
+        "app",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&output_path), "https://example.com/app");
+}
+
+#[test]
+fn test_border_radius() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("rounded.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--border-radius",
+        "30",
+        "--verify",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    assert_eq!(img.get_pixel(0, 0)[3], 0);
+    assert_eq!(img.get_pixel(img.width() / 2, 0)[3], 255);
+}
+
+#[test]
+fn test_border_radius_requires_png() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("rounded.jpg");
+
+    let output = run_qrgen(&[
+        "test",
+        "--border-radius",
+        "10",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn test_count_sequential_codes() {
+    let temp_dir = TempDir::new().unwrap();
+    let pattern = temp_dir.path().join("t_{n}.png");
+
+    let output = run_qrgen(&[
+        "TICKET-{n}",
+        "-o",
+        pattern.to_str().unwrap(),
+        "--count",
+        "5",
+        "--start",
+        "1000",
+    ]);
+
+    assert!(output.status.success());
+    for n in 1000..1005 {
+        assert!(temp_dir.path().join(format!("t_{}.png", n)).exists());
+    }
+    assert_eq!(
+        decode_qr(&temp_dir.path().join("t_1002.png")),
+        "TICKET-1002"
+    );
+}
+
+#[test]
+fn test_count_with_padding() {
+    let temp_dir = TempDir::new().unwrap();
+    let pattern = temp_dir.path().join("id_{n}.png");
+
+    let output = run_qrgen(&[
+        "ID{n}",
+        "-o",
+        pattern.to_str().unwrap(),
+        "--count",
+        "2",
+        "--pad",
+        "4",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&temp_dir.path().join("id_0002.png")), "ID0002");
+}
+
+#[test]
+fn test_clip_data() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("clipped.png");
+    let data = "abcdefghij".repeat(400);
+
+    let output = run_qrgen(&[
+        &data,
+        "--clip-data",
+        "-s",
+        "4",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("dropped the last"));
+    let decoded = decode_qr(&output_path);
+    assert!(!decoded.is_empty() && decoded.len() < data.len());
+    assert!(data.starts_with(&decoded));
+}
+
+#[test]
+fn test_too_long_without_clip_data_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("long.png");
+    let data = "abcdefghij".repeat(400);
+
+    let output = run_qrgen(&[&data, "-o", output_path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_auto_downgrade_ec() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("downgraded.png");
+    // Too long for version 40 at M, but fits at L
+    let data = "abcdefghij".repeat(290);
+
+    let output = run_qrgen(&[
+        &data,
+        "-e",
+        "H",
+        "-s",
+        "4",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+
+    let output = run_qrgen(&[
+        &data,
+        "-e",
+        "H",
+        "--auto-downgrade-ec",
+        "-s",
+        "4",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("using EC L"));
+    assert_eq!(decode_qr(&output_path), data);
+}
+
+#[test]
+fn test_symbology_datamatrix() {
+    let temp_dir = TempDir::new().unwrap();
+    let dm_path = temp_dir.path().join("label_dm.png");
+    let qr_path = temp_dir.path().join("label_qr.png");
+
+    for (path, symbology) in [(&dm_path, "datamatrix"), (&qr_path, "qr")] {
+        let output = run_qrgen(&[
+            "PART-0042",
+            "--symbology",
+            symbology,
+            "-s",
+            "5",
+            "-o",
+            path.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+    }
+
+    let dm = image::open(&dm_path).unwrap().to_luma8();
+    let qr = image::open(&qr_path).unwrap().to_luma8();
+    assert_ne!(dm.dimensions(), qr.dimensions());
+
+    // Sample each module center (5px modules, 4 module border) and decode
+    let modules = dm.width() / 5 - 8;
+    let pixels: Vec<bool> = (0..modules)
+        .flat_map(|y| (0..modules).map(move |x| (x, y)))
+        .map(|(x, y)| dm.get_pixel((x + 4) * 5 + 2, (y + 4) * 5 + 2)[0] < 128)
+        .collect();
+    let decoded = datamatrix::DataMatrix::decode(&pixels, modules as usize).unwrap();
+    assert_eq!(decoded, b"PART-0042");
+
+    // rqrr finds no QR code in the Data Matrix image
+    let mut prepared = rqrr::PreparedImage::prepare(dm);
+    assert!(prepared.detect_grids().is_empty());
+}
+
+#[test]
+fn test_symbology_datamatrix_rejects_qr_options() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("dm.png");
+
+    let output = run_qrgen(&[
+        "PART-0042",
+        "--symbology",
+        "datamatrix",
+        "--logo",
+        "logo.png",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("only supported with --symbology qr"));
+}
+
+#[test]
+fn test_physical_size_and_dpi() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("print.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--physical-size",
+        "50",
+        "--dpi",
+        "300",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+
+    // Version 2 is 25 modules + 8 quiet zone; 50 mm at 300 DPI is 590.55 px,
+    // so whole-pixel modules land within half a module of that
+    let img = image::open(&output_path).unwrap();
+    let expected = 50.0 / 25.4 * 300.0;
+    assert_eq!(img.width(), 594);
+    assert!((img.width() as f64 - expected).abs() <= 33.0 / 2.0);
+
+    let file = std::fs::File::open(&output_path).unwrap();
+    let reader = png::Decoder::new(std::io::BufReader::new(file))
+        .read_info()
+        .unwrap();
+    let dims = reader.info().pixel_dims.unwrap();
+    assert_eq!(dims.xppu, 11811);
+    assert_eq!(decode_qr(&output_path), "https://example.com");
+}
+
+#[test]
+fn test_max_dimension_clamps_size() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("thumb.png");
+    let data = "x".repeat(500);
+
+    let output = run_qrgen(&[
+        &data,
+        "--max-dimension",
+        "200",
+        "--allow-tiny",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let img = image::open(&output_path).unwrap();
+    assert_eq!(img.width(), img.height());
+    assert!(img.width() <= 200);
+
+    // Without --allow-tiny the shrunken modules are refused
+    let output = run_qrgen(&[
+        &data,
+        "--max-dimension",
+        "200",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("--max-dimension 200 gives 2 px modules")
+    );
+
+    // The frame and its tab count towards the cap too
+    let output = run_qrgen(&[
+        "hello",
+        "--max-dimension",
+        "200",
+        "--brand-frame",
+        "scan-me",
+        "--square-canvas",
+        "off",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let (width, height) = image::image_dimensions(&output_path).unwrap();
+    assert!(width <= 200 && height <= 200, "{}x{}", width, height);
+}
+
+#[test]
+fn test_dpi_without_physical_size_keeps_pixels() {
+    let temp_dir = TempDir::new().unwrap();
+    let plain_path = temp_dir.path().join("plain.png");
+    let dpi_path = temp_dir.path().join("dpi.png");
+
+    let output = run_qrgen(&["https://example.com", "-o", plain_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--dpi",
+        "300",
+        "-o",
+        dpi_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    // Same pixel grid, only the pHYs chunk differs: 300 DPI is 11811 px/m
+    let plain = image::open(&plain_path).unwrap().to_rgba8();
+    let stamped = image::open(&dpi_path).unwrap().to_rgba8();
+    assert_eq!(plain, stamped);
+
+    let file = std::fs::File::open(&dpi_path).unwrap();
+    let reader = png::Decoder::new(std::io::BufReader::new(file))
+        .read_info()
+        .unwrap();
+    let dims = reader.info().pixel_dims.unwrap();
+    assert_eq!(dims.unit, png::Unit::Meter);
+    assert_eq!((dims.xppu, dims.yppu), (11811, 11811));
+}
+
+#[test]
+fn test_embed_metadata() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("meta.png");
+
+    let output = run_qrgen(&[
+        "Hello, World!",
+        "--embed-metadata",
+        "-e",
+        "Q",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+
+    let file = std::fs::File::open(&output_path).unwrap();
+    let reader = png::Decoder::new(std::io::BufReader::new(file))
+        .read_info()
+        .unwrap();
+    let chunk = reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == "qrgen")
+        .unwrap();
+    assert_eq!(
+        chunk.text,
+        format!(
+            "qrgen {}; data length 13 bytes; error correction Q; QR version 2",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+    assert_eq!(decode_qr(&output_path), "Hello, World!");
+}
+
+#[test]
+fn test_embed_srgb() {
+    let temp_dir = TempDir::new().unwrap();
+    let srgb_chunk = |name: &str, extra: &[&str]| {
+        let output_path = temp_dir.path().join(name);
+        let mut args = vec!["test", "-o", output_path.to_str().unwrap()];
+        args.extend_from_slice(extra);
+        let output = run_qrgen(&args);
+        assert!(output.status.success());
+
+        let file = std::fs::File::open(&output_path).unwrap();
+        let reader = png::Decoder::new(std::io::BufReader::new(file))
+            .read_info()
+            .unwrap();
+        reader.info().srgb
+    };
+
+    assert!(srgb_chunk("tagged.png", &["--embed-srgb"]).is_some());
+    assert!(srgb_chunk("plain.png", &[]).is_none());
+}
+
+#[test]
+fn test_reproducible_png() {
+    let temp_dir = TempDir::new().unwrap();
+    let generate = |name: &str| {
+        let output_path = temp_dir.path().join(name);
+        let output = run_qrgen(&[
+            "https://example.com",
+            "--reproducible",
+            "--embed-metadata",
+            "-o",
+            output_path.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+        std::fs::read(&output_path).unwrap()
+    };
+
+    let first = generate("first.png");
+    assert_eq!(first, generate("second.png"));
+    assert_eq!(
+        decode_qr(&temp_dir.path().join("first.png")),
+        "https://example.com"
+    );
+
+    // Walk the chunks: no tIME timestamp, and every row of the inflated
+    // IDAT data starts with the pinned Up filter (type 2)
+    let (width, height) = image::image_dimensions(temp_dir.path().join("first.png")).unwrap();
+    let mut idat = Vec::new();
+    let mut pos = 8;
+    while pos < first.len() {
+        let len = u32::from_be_bytes(first[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &first[pos + 4..pos + 8];
+        assert_ne!(kind, b"tIME");
+        if kind == b"IDAT" {
+            idat.extend_from_slice(&first[pos + 8..pos + 8 + len]);
+        }
+        pos += 12 + len;
+    }
+    let mut raw = Vec::new();
+    std::io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(&idat[..]), &mut raw).unwrap();
+    let stride = 1 + width as usize * 4;
+    assert_eq!(raw.len(), stride * height as usize);
+    assert!(raw.chunks(stride).all(|row| row[0] == 2));
+}
+
+#[test]
+fn test_encoding_report() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("code.png");
+    let report_path = temp_dir.path().join("report.json");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        &format!("--encoding-report={}", report_path.display()),
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+    // 4 mode bits + 8 count bits + 19 bytes, in version 2-M's 28 data codewords
+    assert_eq!(report["version"], 2);
+    assert_eq!(report["mode"], "Byte");
+    assert_eq!(report["capacity_used_bits"], 164);
+    assert_eq!(report["capacity_bits"], 224);
+
+    // Without a file the report goes to stderr
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--encoding-report",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\"capacity_used_bits\": 164"));
+}
+
+#[test]
+fn test_optimize_segments_shrinks_version() {
+    let temp_dir = TempDir::new().unwrap();
+    let data = "https://x.io/AB12CD34EF56GH78IJ90KL12MN34OP56";
+    let version = |extra: &[&str]| {
+        let output_path = temp_dir.path().join("code.png");
+        let report_path = temp_dir.path().join("report.json");
+        let mut args = vec![data, "-o", output_path.to_str().unwrap()];
+        let report_arg = format!("--encoding-report={}", report_path.display());
+        args.push(&report_arg);
+        args.extend_from_slice(extra);
+
+        assert!(run_qrgen(&args).status.success());
+        assert_eq!(decode_qr(&output_path), data);
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        report["version"].as_u64().unwrap()
+    };
+
+    // The default optimizer keeps the alphanumeric tail in byte mode
+    assert_eq!(version(&[]), 4);
+    assert_eq!(version(&["--optimize-segments"]), 3);
+}
+
+#[test]
+fn test_dump_bits() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("code.png");
+
+    let output = run_qrgen(&["12345", "--dump-bits", "-o", output_path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Numeric mode indicator 0001, then the 10-bit character count 5
+    assert!(stderr.contains("  bin: 00010000 00010100"));
+    assert_eq!(decode_qr(&output_path), "12345");
+}
+
+#[test]
+fn test_show_matrix() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("code.png");
+
+    let output = run_qrgen(&[
+        "12345",
+        "--show-matrix",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut lines = stderr
+        .lines()
+        .skip_while(|line| !line.starts_with("Matrix:"));
+    assert_eq!(lines.next(), Some("Matrix: QR version 1, 21x21 modules"));
+    let rows: Vec<Vec<char>> = lines.take(21).map(|row| row.chars().collect()).collect();
+    assert_eq!(rows.len(), 21);
+    assert!(rows.iter().all(|row| row.len() == 21));
+
+    // Each finder pattern's four corners are dark
+    for (left, top) in [(0, 0), (14, 0), (0, 14)] {
+        for (x, y) in [(0, 0), (6, 0), (0, 6), (6, 6)] {
+            assert_eq!(
+                rows[top + y][left + x],
+                '1',
+                "finder at ({}, {})",
+                left,
+                top
+            );
+        }
+    }
+}
+
+#[test]
+fn test_visualize_ec() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("ec.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "-e",
+        "H",
+        "--visualize-ec",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error correction codewords (red)"));
+    assert!(stderr.contains("not meant to be scanned"));
+
+    // Data and error correction modules get their own tints on top of the
+    // black and white function patterns
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    let mut colors: Vec<[u8; 4]> = img.pixels().map(|p| p.0).collect();
+    colors.sort();
+    colors.dedup();
+    colors.retain(|&c| c != [255, 255, 255, 255] && c != [0, 0, 0, 255]);
+    assert!(colors.len() >= 2, "colors: {:?}", colors);
+}
+
+#[test]
+fn test_log_file_appends_per_invocation() {
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("qrgen.log");
+    let first = temp_dir.path().join("first.png");
+    let second = temp_dir.path().join("second.svg");
+
+    for (data, path) in [("first secret", &first), ("second secret", &second)] {
+        let output = run_qrgen(&[
+            data,
+            "-o",
+            path.to_str().unwrap(),
+            "--log-file",
+            log_path.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+    }
+
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("first.png") && lines[0].contains("ec=M"));
+    assert!(lines[1].contains("second.svg") && lines[1].contains("symbol=\"QR version"));
+    assert!(!log.contains("secret"));
+}
+
+#[test]
+fn test_benchmark_subcommand() {
+    let output = run_qrgen(&["benchmark", "--size", "2", "-n", "2"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.lines().any(|line| line.contains("codes/sec")));
+}
+
+#[test]
+fn test_reencode_subcommand() {
+    let temp_dir = TempDir::new().unwrap();
+    let original_path = temp_dir.path().join("original.png");
+    let reencoded_path = temp_dir.path().join("reencoded.png");
+    let data = "https://example.com/reencode";
+
+    let output = run_qrgen(&[data, "-o", original_path.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let output = run_qrgen(&[
+        "reencode",
+        original_path.to_str().unwrap(),
+        "--size",
+        "20",
+        "--fill-color",
+        "darkblue",
+        "-o",
+        reencoded_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let original = image::open(&original_path).unwrap().to_rgba8();
+    let reencoded = image::open(&reencoded_path).unwrap().to_rgba8();
+    assert_eq!(reencoded.width(), original.width() * 2);
+    assert!(reencoded
+        .pixels()
+        .any(|&p| p == image::Rgba([0, 0, 139, 255])));
+    assert_eq!(decode_qr(&reencoded_path), data);
+}
+
+#[test]
+fn test_prompt_timeout() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("wifi.png");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_qrgen"))
+        .args(["wifi", "--template", "wifi", "--prompt-timeout", "1", "-o"])
+        .arg(&output_path)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute qrgen");
+    // Hold stdin open without writing, like a user who never answers
+    let _stdin = child.stdin.take();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("No answer to \"Network SSID\" within 1s")
+    );
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn test_template_separator() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("vcard.png");
+
+    let output = run_qrgen(&[
+        "Doe, John|+1234567890",
+        "--template",
+        "vcard",
+        "--separator",
+        "|",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let decoded = decode_qr(&output_path);
+    assert!(decoded.contains("FN:Doe, John\n"));
+    assert!(decoded.contains("TEL:+1234567890\n"));
+}
+
+#[test]
+fn test_preview_skipped_when_not_interactive() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("preview.png");
+
+    // stdout is captured here, so --preview must not try to open anything
+    let output = run_qrgen(&["test", "--preview", "-o", output_path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert!(output_path.exists());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("couldn't open"));
+}
+
+#[test]
+fn test_zatca_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("invoice.png");
+
+    let output = run_qrgen(&[
+        "Bobs Records,310122393500003,2022-04-25T15:30:00Z,1000.00,150.00",
+        "--template",
+        "zatca",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        decode_qr(&output_path),
+        "AQxCb2JzIFJlY29yZHMCDzMxMDEyMjM5MzUwMDAwMwMUMjAyMi0wNC0yNVQxNTozMDowMFoEBzEwMDAuMDAFBjE1MC4wMA=="
+    );
+}
+
+#[test]
+fn test_spotify_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("track.png");
+
+    let output = run_qrgen(&[
+        "track,4uLU6hMCjMI75M1A2tKUQC",
+        "--template",
+        "spotify",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        decode_qr(&output_path),
+        "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC"
+    );
+
+    let output = run_qrgen(&["episode,4uLU6hMCjMI75M1A2tKUQC", "--template", "spotify"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_logo_position_corner() {
+    let temp_dir = TempDir::new().unwrap();
+    let logo_path = temp_dir.path().join("logo.png");
+    let output_path = temp_dir.path().join("corner.png");
+    image::RgbaImage::from_pixel(16, 16, image::Rgba([200, 0, 0, 255]))
+        .save(&logo_path)
+        .unwrap();
+
+    let data = "https://example.com/products/12345/details";
+    let output = run_qrgen(&[
+        data,
+        "--logo",
+        logo_path.to_str().unwrap(),
+        "--logo-position",
+        "bottom-right",
+        "-e",
+        "H",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("overlaps a finder"));
+    assert_eq!(decode_qr(&output_path), data);
+}
+
+#[test]
+fn test_logo_border_ring() {
+    let temp_dir = TempDir::new().unwrap();
+    let logo_path = temp_dir.path().join("logo.png");
+    let output_path = temp_dir.path().join("ringed.png");
+    image::RgbaImage::from_pixel(16, 16, image::Rgba([200, 0, 0, 255]))
+        .save(&logo_path)
+        .unwrap();
+
+    let data = "https://example.com/products/12345/details";
+    let output = run_qrgen(&[
+        data,
+        "--logo",
+        logo_path.to_str().unwrap(),
+        "--logo-border-color",
+        "#1565c0",
+        "--logo-border-width",
+        "6",
+        "-e",
+        "H",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    // The white box is a fifth of the image plus 20%, centered, with the
+    // ring just outside it
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    let width = img.width();
+    let box_size = ((width / 5) as f32 * 1.2) as u32;
+    let box_left = (width - box_size) / 2;
+    let middle = width / 2;
+    let ring = image::Rgba([0x15, 0x65, 0xc0, 255]);
+    assert_eq!(*img.get_pixel(box_left - 1, middle), ring);
+    assert_eq!(*img.get_pixel(box_left - 6, middle), ring);
+    assert_eq!(*img.get_pixel(middle, box_left + box_size), ring);
+    assert_eq!(
+        *img.get_pixel(box_left, middle),
+        image::Rgba([255, 255, 255, 255])
+    );
+    assert_eq!(decode_qr(&output_path), data);
+}
+
+#[test]
+fn test_palette_rainbow() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("rainbow.png");
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--palette",
+        "rainbow",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    let white = image::Rgba([255, 255, 255, 255]);
+    let colors: std::collections::HashSet<_> = img.pixels().filter(|&&p| p != white).collect();
+    assert!(colors.len() >= 3);
+    assert_eq!(decode_qr(&output_path), "https://example.com");
+}
+
+#[test]
+fn test_color_from_logo() {
+    let temp_dir = TempDir::new().unwrap();
+    let logo_path = temp_dir.path().join("logo.png");
+    let output_path = temp_dir.path().join("branded.png");
+    image::RgbaImage::from_pixel(16, 16, image::Rgba([10, 40, 180, 255]))
+        .save(&logo_path)
+        .unwrap();
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--logo",
+        logo_path.to_str().unwrap(),
+        "--color-from-logo",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    // The top-left finder pattern corner is a dark module
+    let img = image::open(&output_path).unwrap().to_rgba8();
+    assert_eq!(*img.get_pixel(40, 40), image::Rgba([10, 40, 180, 255]));
+}
+
+#[test]
+fn test_template_fields_trimmed_by_default() {
+    let temp_dir = TempDir::new().unwrap();
+    let trimmed_path = temp_dir.path().join("trimmed.png");
+    let raw_path = temp_dir.path().join("raw.png");
+
+    let output = run_qrgen(&[
+        "MyNet , secret ,WPA",
+        "--template",
+        "wifi",
+        "-o",
+        trimmed_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&trimmed_path), "WIFI:T:WPA;S:MyNet;P:secret;;");
+
+    let output = run_qrgen(&[
+        "MyNet , secret ,WPA",
+        "--template",
+        "wifi",
+        "--no-trim-fields",
+        "-o",
+        raw_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&raw_path), "WIFI:T:WPA;S:MyNet ;P: secret ;;");
+}
+
+#[test]
+fn test_background_image_with_dither() {
+    let temp_dir = TempDir::new().unwrap();
+    let photo_path = temp_dir.path().join("photo.png");
+    let output_path = temp_dir.path().join("poster.png");
+    image::RgbImage::from_fn(64, 64, |x, y| {
+        image::Rgb([230, 220 + (x % 16) as u8, 210 + (y % 32) as u8])
+    })
+    .save(&photo_path)
+    .unwrap();
+
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--background-image",
+        photo_path.to_str().unwrap(),
+        "-e",
+        "H",
+        "-s",
+        "12",
+        "--dither",
+        "--verify",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(decode_qr(&output_path), "https://example.com");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--background-image lets the photo show through"));
+
+    // The photo advisory is a scannability warning, so --quiet drops it
+    let output = run_qrgen(&[
+        "https://example.com",
+        "--background-image",
+        photo_path.to_str().unwrap(),
+        "-e",
+        "H",
+        "--quiet",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Warning"), "{}", stderr);
+}
+
+#[test]
+fn test_dither_requires_background_image() {
+    let output = run_qrgen(&["test", "--dither"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_from_lines() {
+    let temp_dir = TempDir::new().unwrap();
+    let lines_path = temp_dir.path().join("urls.txt");
+    let out_dir = temp_dir.path().join("out");
+    std::fs::write(
+        &lines_path,
+        "https://example.com/one\n# skip me\n\nhttps://example.com/two\n",
+    )
+    .unwrap();
+
+    let output = run_qrgen(&[
+        "--from-lines",
+        lines_path.to_str().unwrap(),
+        "-o",
+        out_dir.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(std::fs::read_dir(&out_dir).unwrap().count(), 2);
+    assert_eq!(
+        decode_qr(&out_dir.join("line_0001.png")),
+        "https://example.com/one"
+    );
+    assert_eq!(
+        decode_qr(&out_dir.join("line_0002.png")),
+        "https://example.com/two"
+    );
+}
+
+#[test]
+fn test_output_template_requires_multi_mode() {
+    let output = run_qrgen(&["test", "--output-template", "{n}.png"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_help_output() {
+    let output = run_qrgen(&["--help"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Generate QR codes"));
+    assert!(stdout.contains("--output"));
+    assert!(stdout.contains("--size"));
+    assert!(stdout.contains("--terminal"));
+}
+
+#[test]
+fn test_version_output() {
+    let output = run_qrgen(&["--version"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("0.1.0"));
+}
+
+#[test]
+fn test_invalid_color() {
     let temp_dir = TempDir::new().unwrap();
     let output_path = temp_dir.path().join("invalid_qr.png");
 
@@ -274,12 +2737,24 @@ fn test_empty_data() {
     let temp_dir = TempDir::new().unwrap();
     let output_path = temp_dir.path().join("empty_qr.png");
 
-    let output = run_qrgen(&["", "-o", output_path.to_str().unwrap()]);
+    let output = run_qrgen(&["", "--allow-empty", "-o", output_path.to_str().unwrap()]);
 
     assert!(output.status.success());
     assert!(output_path.exists());
 }
 
+#[test]
+fn test_empty_data_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("empty_qr.png");
+
+    let output = run_qrgen(&["", "-o", output_path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--allow-empty"));
+    assert!(!output_path.exists());
+}
+
 #[test]
 fn test_long_data() {
     let temp_dir = TempDir::new().unwrap();